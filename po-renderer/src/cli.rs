@@ -0,0 +1,409 @@
+//! The `po-renderer` command line: the viewer by default, and subcommands
+//! that render, compare and build shaders without a window.
+
+use std::collections::HashMap;
+
+/// Parses the arguments and configuration, then runs the subcommand or the
+/// viewer. Exits the process on errors.
+pub fn main() {
+    dotenv::dotenv().ok();
+    let (overrides, args) = split_config_flags(std::env::args().skip(1));
+    let config = match crate::config::Config::load(&overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(config.log_filter.as_str())
+        .init();
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        Some("build-shaders") => build_shaders(args.next()),
+        Some("render") => render(args.collect(), &config),
+        Some("render-cpu") => render_cpu(args.collect(), &config),
+        Some("compare") => compare(args.collect()),
+        Some("screenshot") => screenshot(args.collect(), &config),
+        Some("--integrator") => run(args.next(), &config),
+        _ => run(None, &config),
+    }
+}
+
+/// Separates the `--set key=value` flags, accepted before or after the
+/// subcommand, from the other arguments.
+fn split_config_flags(mut args: impl Iterator<Item = String>) -> (Vec<String>, Vec<String>) {
+    let mut overrides = Vec::new();
+    let mut rest = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--set" {
+            overrides.push(args.next().unwrap_or_default());
+        } else if let Some(pair) = arg.strip_prefix("--set=") {
+            overrides.push(pair.to_owned());
+        } else {
+            rest.push(arg);
+        }
+    }
+    (overrides, rest)
+}
+
+/// Removes `flag` and the value after it from `args`.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    args.remove(i);
+    if i < args.len() {
+        Some(args.remove(i))
+    } else {
+        None
+    }
+}
+
+/// `--camera <name>`, `--bookmark <name>` and `--calibration <file>`, taken
+/// out of the arguments before the positional ones are read.
+struct ViewFlags {
+    camera: Option<String>,
+    bookmark: Option<String>,
+    calibration: Option<crate::calibration::Intrinsics>,
+}
+
+impl ViewFlags {
+    /// Exits when the calibration file cannot be read.
+    fn take(args: &mut Vec<String>) -> Self {
+        let calibration = take_flag(args, "--calibration").map(|path| {
+            crate::calibration::Intrinsics::from_file(std::path::Path::new(&path)).unwrap_or_else(
+                |e| {
+                    log::error!("cannot load calibration: {}", e);
+                    std::process::exit(1);
+                },
+            )
+        });
+        Self {
+            camera: take_flag(args, "--camera"),
+            bookmark: take_flag(args, "--bookmark"),
+            calibration,
+        }
+    }
+
+    /// The calibrated image size, unless given on the command line.
+    fn size(&self, width: Option<u32>, height: Option<u32>) -> (u32, u32) {
+        let calibrated = self.calibration.map(|c| (c.width, c.height));
+        (
+            width.or(calibrated.map(|c| c.0)).unwrap_or(800),
+            height.or(calibrated.map(|c| c.1)).unwrap_or(600),
+        )
+    }
+
+    /// Points `settings` at the camera or bookmark of the glTF file `scene`
+    /// and puts the calibrated sensor on it, exits when there is no such
+    /// camera or bookmark.
+    fn apply(&self, settings: &mut crate::RenderSettings, scene: &std::path::Path) {
+        let mut result = Ok(());
+        if let Some(name) = &self.camera {
+            result = crate::scene_camera::SceneCamera::from_file(scene)
+                .map_err(|e| e.to_string())
+                .and_then(|cameras| settings.use_scene_camera(&cameras, name));
+        }
+        if let Some(name) = &self.bookmark {
+            result = result.and_then(|()| {
+                crate::bookmark::Bookmarks::load(scene)
+                    .and_then(|bookmarks| settings.use_bookmark(&bookmarks, name))
+            });
+        }
+        if let Err(e) = result {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+        if let Some(intrinsics) = &self.calibration {
+            settings.use_calibration(intrinsics);
+        }
+    }
+}
+
+#[cfg(feature = "hot-reload")]
+fn build_shaders(out_dir: Option<String>) {
+    let out_dir = out_dir
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(crate::shader_cache::shader_dir);
+    if let Err(e) = crate::shader_cache::build_all(&out_dir) {
+        log::error!("building shaders failed:\n{}", e);
+        std::process::exit(1);
+    }
+    log::info!("shaders written to {}", out_dir.display());
+}
+
+#[cfg(not(feature = "hot-reload"))]
+fn build_shaders(_out_dir: Option<String>) {
+    log::error!("build-shaders needs po-renderer built with the hot-reload feature");
+    std::process::exit(1);
+}
+
+/// Writes every result to `<result>.exr` in the working directory.
+fn write_results(results: Vec<crate::RenderResult>) {
+    for result in results {
+        let path = format!("{}.exr", result.name);
+        if let Err(e) = result.write_exr(std::path::Path::new(&path)) {
+            log::error!("cannot write render result: {}", e);
+        }
+    }
+}
+
+/// `render <scene.gltf> [width height] [--camera <name>] [--bookmark <name>]
+/// [--calibration <file>]`, renders a scene through Po without a window and
+/// writes one EXR per result.
+fn render(mut args: Vec<String>, config: &crate::config::Config) {
+    let view = ViewFlags::take(&mut args);
+    let scene_path = match args.first() {
+        Some(scene) => std::path::PathBuf::from(scene),
+        None => {
+            log::error!(
+                "usage: po-renderer render <scene.gltf> [width height] [--camera <name>] \
+                 [--bookmark <name>] [--calibration <file>]"
+            );
+            std::process::exit(1);
+        }
+    };
+    let (width, height) = view.size(
+        args.get(1).and_then(|w| w.parse().ok()),
+        args.get(2).and_then(|h| h.parse().ok()),
+    );
+    let adapter = crate::adapter::Preference::parse(config.adapter.as_deref().unwrap_or_default());
+    let mut offscreen = match crate::Offscreen::new(width, height, &adapter) {
+        Ok(offscreen) => offscreen,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(skymap) = &config.skymap {
+        if let Err(e) = offscreen.set_skymap(skymap) {
+            log::warn!("ignoring skymap: {}", e);
+        }
+    }
+    let scene = match offscreen.load_scene(&scene_path) {
+        Ok(scene) => scene,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut settings = crate::RenderSettings {
+        width,
+        height,
+        ..Default::default()
+    };
+    settings.camera.aspect_ratio = width as f32 / height.max(1) as f32;
+    view.apply(&mut settings, &scene_path);
+    write_results(offscreen.render_po(&settings, &scene));
+}
+
+/// `render-cpu <scene.gltf> [width height] [--camera <name>] [--bookmark
+/// <name>] [--calibration <file>]`, renders a scene with the CPU reference
+/// renderer and writes one EXR per result, for machines without a ray tracing
+/// capable GPU.
+fn render_cpu(mut args: Vec<String>, config: &crate::config::Config) {
+    let view = ViewFlags::take(&mut args);
+    let scene_path = match args.first() {
+        Some(scene) => std::path::PathBuf::from(scene),
+        None => {
+            log::error!(
+                "usage: po-renderer render-cpu <scene.gltf> [width height] [--camera <name>] \
+                 [--bookmark <name>] [--calibration <file>]"
+            );
+            std::process::exit(1);
+        }
+    };
+    let scene = match crate::cpu::CpuScene::from_file(&scene_path) {
+        Ok(scene) => scene,
+        Err(e) => {
+            log::error!("cannot load scene: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let skymap = config.skymap.as_ref().and_then(|p| {
+        match image::open(p) {
+            Ok(img) => Some(img.into_rgba8()),
+            Err(e) => {
+                log::warn!("ignoring skymap: {}: {}", p.display(), e);
+                None
+            }
+        }
+    });
+    let (width, height) = view.size(
+        args.get(1).and_then(|w| w.parse().ok()),
+        args.get(2).and_then(|h| h.parse().ok()),
+    );
+    let mut settings = crate::RenderSettings {
+        width,
+        height,
+        ..Default::default()
+    };
+    settings.camera.aspect_ratio = width as f32 / height.max(1) as f32;
+    view.apply(&mut settings, &scene_path);
+    write_results(crate::cpu::render(
+        &settings,
+        &scene,
+        skymap.as_ref(),
+        &settings.camera,
+    ));
+}
+
+/// `compare <test> <reference> [error map]`, prints the metrics and writes
+/// the FLIP error map, `flip.png` by default.
+fn compare(args: Vec<String>) {
+    if args.len() < 2 {
+        log::error!("usage: po-renderer compare <test> <reference> [error map]");
+        std::process::exit(1);
+    }
+    let load = |path: &str| {
+        crate::compare::read_image(std::path::Path::new(path)).unwrap_or_else(|e| {
+            log::error!("cannot load image: {}", e);
+            std::process::exit(1);
+        })
+    };
+    let (test, reference) = (load(&args[0]), load(&args[1]));
+    let comparison = match crate::compare::compare(&test, &reference) {
+        Ok(comparison) => comparison,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("PSNR     {:.3} dB", comparison.psnr);
+    println!("SSIM     {:.5}", comparison.ssim);
+    println!("relMSE   {:.6}", comparison.rel_mse);
+    println!("FLIP     {:.5}", comparison.flip);
+    let error_map = args.get(2).map_or("flip.png", |s| s.as_str());
+    if let Err(e) = comparison.write_error_map(std::path::Path::new(error_map)) {
+        log::error!("cannot write error map: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// `screenshot <scene.gltf> <image> [width height] [--camera <name>]
+/// [--bookmark <name>] [--calibration <file>]`, renders the ray tracing pass
+/// without a window from the default camera, a camera of the scene or a
+/// bookmark, optionally through a calibrated sensor at its image size.
+fn screenshot(mut args: Vec<String>, config: &crate::config::Config) {
+    let view = ViewFlags::take(&mut args);
+    if args.len() < 2 {
+        log::error!(
+            "usage: po-renderer screenshot <scene.gltf> <image> [width height] [--camera <name>] \
+             [--bookmark <name>] [--calibration <file>]"
+        );
+        std::process::exit(1);
+    }
+    let (width, height) = view.size(
+        args.get(2).and_then(|w| w.parse().ok()),
+        args.get(3).and_then(|h| h.parse().ok()),
+    );
+    let adapter = crate::adapter::Preference::parse(config.adapter.as_deref().unwrap_or_default());
+    let mut offscreen = match crate::Offscreen::new(width, height, &adapter) {
+        Ok(offscreen) => offscreen,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(skymap) = &config.skymap {
+        if let Err(e) = offscreen.set_skymap(skymap) {
+            log::warn!("ignoring skymap: {}", e);
+        }
+    }
+    let scene = match offscreen.load_scene(std::path::Path::new(&args[0])) {
+        Ok(scene) => scene,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut settings = crate::RenderSettings {
+        width,
+        height,
+        ..Default::default()
+    };
+    settings.camera.aspect_ratio = width as f32 / height.max(1) as f32;
+    view.apply(&mut settings, std::path::Path::new(&args[0]));
+    let target = offscreen.create_target(width, height);
+    offscreen.render(crate::Pass::RayTracing, &scene, &settings.camera, &target);
+    if let Err(e) = target.save(std::path::Path::new(&args[1])) {
+        log::error!("cannot save screenshot: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(integrator: Option<String>, config: &crate::config::Config) {
+    let event_loop = winit::event_loop::EventLoop::new();
+
+    let mut windows = HashMap::new();
+    let main_window = winit::window::WindowBuilder::new()
+        .with_inner_size(winit::dpi::PhysicalSize::new(
+            config.window.width,
+            config.window.height,
+        ))
+        .build(&event_loop)
+        .unwrap();
+
+    // let profiler_window = winit::window::WindowBuilder::new()
+    //     .with_inner_size(winit::dpi::PhysicalSize::new(640, 600))
+    //     .build(&event_loop)
+    //     .unwrap();
+    let mut engine = match crate::Engine::new(&main_window, config) {
+        Ok(engine) => engine,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(manifest) = integrator {
+        if let Err(e) = engine.load_integrator(std::path::Path::new(&manifest)) {
+            log::error!("cannot load integrator: {}", e);
+        }
+    }
+    let main_window_id = main_window.id();
+
+    windows.insert(main_window_id, main_window);
+
+    // let mut profiler = crate::profiler::Profiler::new(&profiler_window, config)
+    //     .unwrap_or_else(|e| {
+    //         log::error!("{}", e);
+    //         std::process::exit(1);
+    //     });
+    puffin::set_scopes_on(false);
+
+    event_loop.run(move |event, _, control_flow| {
+        // puffin::GlobalProfiler::lock().new_frame();
+        *control_flow = winit::event_loop::ControlFlow::Poll;
+
+        engine.update(&event);
+        // profiler.update(&event);
+        match event {
+            winit::event::Event::WindowEvent {
+                window_id,
+                event: winit::event::WindowEvent::CloseRequested,
+            } => {
+                windows.remove(&window_id);
+                if windows.is_empty() {
+                    *control_flow = winit::event_loop::ControlFlow::Exit;
+                }
+            }
+            winit::event::Event::LoopDestroyed => {
+                engine.wait_idle();
+                if let Some(path) = engine.session_path() {
+                    engine.save_session(path);
+                }
+                engine.save_pipeline_cache();
+            }
+            winit::event::Event::MainEventsCleared => {
+                if let Some(main_window) = windows.get(&main_window_id) {
+                    main_window.request_redraw();
+                }
+            }
+            winit::event::Event::RedrawRequested(_) => {
+                // puffin::profile_scope!("render!");
+                engine.render();
+                // profiler.render();
+            }
+            _ => {}
+        }
+    });
+}
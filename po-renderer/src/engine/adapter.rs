@@ -0,0 +1,319 @@
+//! Physical device selection. Adapters are scored by type and rejected when
+//! they lack an extension or push constant space the renderer needs; the
+//! `adapter` setting picks one by index or by a case insensitive part of its
+//! name instead.
+
+use std::ffi::CStr;
+use std::fmt;
+
+use maligog::vk;
+
+/// What the ray tracing pass, `Po` and the scene loader use.
+pub const RAY_TRACING_EXTENSIONS: &[&str] = &[
+    "VK_KHR_ray_tracing_pipeline",
+    "VK_KHR_acceleration_structure",
+    "VK_KHR_deferred_host_operations",
+    "VK_EXT_descriptor_indexing",
+];
+
+/// The largest push constant range of the passes, the three matrices of the
+/// wireframe pass. The ray tracing passes push only two.
+pub const PUSH_CONSTANTS_SIZE: u32 = 192;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preference {
+    /// The best scoring usable adapter.
+    Auto,
+    Index(usize),
+    /// A case insensitive substring of the device name.
+    Name(String),
+}
+
+impl Preference {
+    pub fn parse(s: &str) -> Self {
+        let s = s.trim();
+        if s.is_empty() {
+            Preference::Auto
+        } else if let Ok(index) = s.parse() {
+            Preference::Index(index)
+        } else {
+            Preference::Name(s.to_owned())
+        }
+    }
+
+    fn matches(&self, adapter: &AdapterInfo) -> bool {
+        match self {
+            Preference::Auto => true,
+            Preference::Index(index) => adapter.index == *index,
+            Preference::Name(name) => adapter.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+impl fmt::Display for Preference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Preference::Auto => write!(f, "auto"),
+            Preference::Index(index) => write!(f, "index {}", index),
+            Preference::Name(name) => write!(f, "name \"{}\"", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdapterInfo {
+    pub index: usize,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub extensions: Vec<String>,
+    pub max_push_constants_size: u32,
+}
+
+impl AdapterInfo {
+    pub fn new(index: usize, physical_device: &maligog::PhysicalDevice) -> Self {
+        let name = unsafe { CStr::from_ptr(physical_device.properties().device_name.as_ptr()) };
+        let extensions = physical_device
+            .extension_properties()
+            .iter()
+            .map(|e| {
+                unsafe { CStr::from_ptr(e.extension_name.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        Self {
+            index,
+            name: name.to_string_lossy().into_owned(),
+            device_type: physical_device.device_type(),
+            extensions,
+            max_push_constants_size: physical_device.properties().limits.max_push_constants_size,
+        }
+    }
+
+    fn type_score(&self) -> u32 {
+        match self.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 1000,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 100,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 10,
+            vk::PhysicalDeviceType::CPU => 1,
+            _ => 0,
+        }
+    }
+
+    fn missing_extensions(&self, required: &[&str]) -> Vec<String> {
+        required
+            .iter()
+            .filter(|r| !self.extensions.iter().any(|e| e == *r))
+            .map(|r| r.to_string())
+            .collect()
+    }
+}
+
+impl fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} ({:?})", self.index, self.name, self.device_type)
+    }
+}
+
+/// Why an adapter was or wasn't picked, for the startup report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Usable { score: u32 },
+    Rejected(String),
+}
+
+/// Judges every adapter. Returns the verdicts in adapter order and the index
+/// of the chosen adapter, or an error listing why each was rejected.
+pub fn choose(
+    adapters: &[AdapterInfo],
+    required: &[&str],
+    preference: &Preference,
+) -> (Vec<Verdict>, Result<usize, String>) {
+    let verdicts: Vec<Verdict> = adapters
+        .iter()
+        .map(|adapter| {
+            let missing = adapter.missing_extensions(required);
+            if !preference.matches(adapter) {
+                Verdict::Rejected(format!(
+                    "not selected by the adapter setting ({})",
+                    preference
+                ))
+            } else if !missing.is_empty() {
+                Verdict::Rejected(format!("missing {}", missing.join(", ")))
+            } else if adapter.max_push_constants_size < PUSH_CONSTANTS_SIZE {
+                Verdict::Rejected(format!(
+                    "{} bytes of push constants, needs {}",
+                    adapter.max_push_constants_size, PUSH_CONSTANTS_SIZE
+                ))
+            } else {
+                Verdict::Usable {
+                    score: adapter.type_score(),
+                }
+            }
+        })
+        .collect();
+    let best = verdicts
+        .iter()
+        .enumerate()
+        .filter_map(|(i, verdict)| {
+            match verdict {
+                Verdict::Usable { score } => Some((i, *score)),
+                Verdict::Rejected(_) => None,
+            }
+        })
+        // the first of equal scores, in driver order
+        .fold(None, |best: Option<(usize, u32)>, (i, score)| {
+            match best {
+                Some((_, best_score)) if best_score >= score => best,
+                _ => Some((i, score)),
+            }
+        })
+        .map(|(i, _)| i);
+    let result = best.ok_or_else(|| {
+        let mut message = String::from("no usable GPU");
+        if adapters.is_empty() {
+            message.push_str(", the Vulkan driver reports no adapters");
+        }
+        for (adapter, verdict) in adapters.iter().zip(&verdicts) {
+            if let Verdict::Rejected(reason) = verdict {
+                message.push_str(&format!("\n  {}: {}", adapter, reason));
+            }
+        }
+        message
+    });
+    (verdicts, result)
+}
+
+/// Picks a physical device of `instance` and logs the decision for every
+/// adapter.
+pub fn select(
+    instance: &maligog::Instance,
+    required: &[&str],
+    preference: &Preference,
+) -> Result<maligog::PhysicalDevice, String> {
+    let physical_devices = instance.enumerate_physical_device();
+    let adapters: Vec<AdapterInfo> = physical_devices
+        .iter()
+        .enumerate()
+        .map(|(i, p)| AdapterInfo::new(i, p))
+        .collect();
+    let (verdicts, chosen) = choose(&adapters, required, preference);
+    for (adapter, verdict) in adapters.iter().zip(&verdicts) {
+        match verdict {
+            Verdict::Usable { score } => log::info!("{}: usable, score {}", adapter, score),
+            Verdict::Rejected(reason) => log::info!("{}: rejected, {}", adapter, reason),
+        }
+    }
+    let chosen = chosen?;
+    log::info!("using {}", adapters[chosen]);
+    Ok(physical_devices.into_iter().nth(chosen).unwrap())
+}
+
+/// The adapter the viewer's windows share: ray tracing capable and picked
+/// by the `adapter` setting of `config`.
+pub fn select_for_viewer(
+    instance: &maligog::Instance,
+    config: &super::config::Config,
+) -> Result<maligog::PhysicalDevice, String> {
+    select(
+        instance,
+        RAY_TRACING_EXTENSIONS,
+        &Preference::parse(config.adapter.as_deref().unwrap_or_default()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter(
+        index: usize,
+        name: &str,
+        device_type: vk::PhysicalDeviceType,
+        rt: bool,
+    ) -> AdapterInfo {
+        AdapterInfo {
+            index,
+            name: name.to_owned(),
+            device_type,
+            extensions: if rt {
+                RAY_TRACING_EXTENSIONS
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect()
+            } else {
+                vec!["VK_KHR_swapchain".to_owned()]
+            },
+            max_push_constants_size: 256,
+        }
+    }
+
+    fn laptop() -> Vec<AdapterInfo> {
+        vec![
+            adapter(
+                0,
+                "Intel(R) UHD Graphics",
+                vk::PhysicalDeviceType::INTEGRATED_GPU,
+                false,
+            ),
+            adapter(
+                1,
+                "NVIDIA GeForce RTX 3060 Laptop GPU",
+                vk::PhysicalDeviceType::DISCRETE_GPU,
+                true,
+            ),
+            adapter(2, "llvmpipe", vk::PhysicalDeviceType::CPU, false),
+        ]
+    }
+
+    #[test]
+    fn prefers_capable_discrete_gpu() {
+        let (verdicts, chosen) = choose(&laptop(), RAY_TRACING_EXTENSIONS, &Preference::Auto);
+        assert_eq!(chosen, Ok(1));
+        assert!(matches!(verdicts[0], Verdict::Rejected(_)));
+    }
+
+    #[test]
+    fn falls_back_to_integrated_without_requirements() {
+        let mut adapters = laptop();
+        adapters.remove(1);
+        let (_, chosen) = choose(&adapters, &[], &Preference::Auto);
+        assert_eq!(chosen, Ok(0));
+    }
+
+    #[test]
+    fn override_by_name_or_index() {
+        let adapters = laptop();
+        let (_, chosen) = choose(&adapters, &[], &Preference::parse("llvm"));
+        assert_eq!(chosen, Ok(2));
+        let (_, chosen) = choose(&adapters, &[], &Preference::parse("0"));
+        assert_eq!(chosen, Ok(0));
+    }
+
+    #[test]
+    fn rejects_small_push_constants() {
+        let mut adapters = laptop();
+        adapters[1].max_push_constants_size = 128;
+        let (verdicts, chosen) = choose(&adapters, RAY_TRACING_EXTENSIONS, &Preference::Auto);
+        assert_eq!(
+            verdicts[1],
+            Verdict::Rejected("128 bytes of push constants, needs 192".to_owned())
+        );
+        assert!(chosen.is_err());
+    }
+
+    #[test]
+    fn error_lists_every_rejection() {
+        let adapters = laptop();
+        let (_, chosen) = choose(
+            &adapters,
+            RAY_TRACING_EXTENSIONS,
+            &Preference::parse("intel"),
+        );
+        let message = chosen.unwrap_err();
+        assert!(message.contains("[0] Intel(R) UHD Graphics"));
+        assert!(message.contains("missing VK_KHR_ray_tracing_pipeline"));
+        assert!(message.contains("[1] NVIDIA"));
+        assert!(message.contains("not selected by the adapter setting"));
+    }
+}
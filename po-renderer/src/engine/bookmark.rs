@@ -0,0 +1,192 @@
+//! Named camera views of a scene, kept in a sidecar file next to it so they
+//! travel with the asset.
+
+use std::path::{Path, PathBuf};
+
+use super::session::{self, CameraPose};
+
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    #[serde(flatten)]
+    pub pose: CameraPose,
+    /// Of the view it was taken in. Recalling keeps the window's.
+    pub aspect_ratio: f32,
+}
+
+impl Bookmark {
+    pub fn new(name: &str, camera: &super::Camera) -> Self {
+        Self {
+            name: name.to_owned(),
+            pose: CameraPose::new(camera),
+            aspect_ratio: camera.aspect_ratio,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Bookmarks {
+    version: u32,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+impl Default for Bookmarks {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            bookmarks: Vec::new(),
+        }
+    }
+}
+
+/// `scene.gltf` keeps its bookmarks in `scene.bookmarks.json`.
+pub fn sidecar_path(scene: &Path) -> PathBuf {
+    scene.with_extension("bookmarks.json")
+}
+
+impl Bookmarks {
+    /// The bookmarks of `scene`, none if it has no sidecar file yet.
+    pub fn load(scene: &Path) -> Result<Self, String> {
+        let path = sidecar_path(scene);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let text =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let bookmarks: Self =
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if bookmarks.version != VERSION {
+            return Err(format!(
+                "{}: bookmarks version {}, expected {}",
+                path.display(),
+                bookmarks.version,
+                VERSION
+            ));
+        }
+        Ok(bookmarks)
+    }
+
+    pub fn save(&self, scene: &Path) -> Result<(), String> {
+        session::write_json(&sidecar_path(scene), self)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|b| b.name == name)
+    }
+
+    /// Replaces the bookmark of the same name in place, or appends.
+    pub fn set(&mut self, bookmark: Bookmark) {
+        match self.bookmarks.iter_mut().find(|b| b.name == bookmark.name) {
+            Some(existing) => *existing = bookmark,
+            None => self.bookmarks.push(bookmark),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.bookmarks.len();
+        self.bookmarks.retain(|b| b.name != name);
+        self.bookmarks.len() != len
+    }
+}
+
+impl super::Engine {
+    /// Bookmarks the current view as `name` and saves the sidecar file.
+    pub fn add_bookmark(&mut self, name: &str) -> Result<(), String> {
+        let scene = self
+            .scene_path
+            .clone()
+            .ok_or_else(|| "bookmarks need an open scene".to_owned())?;
+        self.bookmarks.set(Bookmark::new(name, &self.camera));
+        self.bookmarks.save(&scene)
+    }
+
+    pub fn remove_bookmark(&mut self, name: &str) -> Result<(), String> {
+        if !self.bookmarks.remove(name) {
+            return Err(format!("no bookmark {}", name));
+        }
+        match &self.scene_path {
+            Some(scene) => self.bookmarks.save(scene),
+            None => Ok(()),
+        }
+    }
+
+    pub fn recall_bookmark(&mut self, name: &str) -> Result<(), String> {
+        let pose = self
+            .bookmarks
+            .get(name)
+            .ok_or_else(|| format!("no bookmark {}", name))?
+            .pose;
+        self.look_from_pose(&pose);
+        Ok(())
+    }
+
+    /// Number keys recall the bookmarks in order, 1 the first.
+    pub(super) fn recall_bookmark_at(&mut self, index: usize) {
+        if let Some(pose) = self.bookmarks.bookmarks.get(index).map(|b| b.pose) {
+            self.look_from_pose(&pose);
+        }
+    }
+
+    pub(super) fn reload_bookmarks(&mut self) {
+        self.bookmarks = match &self.scene_path {
+            Some(scene) => {
+                Bookmarks::load(scene).unwrap_or_else(|e| {
+                    log::warn!("ignoring bookmarks: {}", e);
+                    Bookmarks::default()
+                })
+            }
+            None => Bookmarks::default(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark(name: &str, yaw: f32) -> Bookmark {
+        Bookmark {
+            name: name.to_owned(),
+            pose: CameraPose {
+                location: [1.0, 2.0, 3.0],
+                yaw,
+                pitch: 0.0,
+                fov: 1.0,
+                lens: Default::default(),
+                projection: Default::default(),
+            },
+            aspect_ratio: 1.5,
+        }
+    }
+
+    #[test]
+    fn sidecar_is_next_to_the_scene() {
+        assert_eq!(
+            sidecar_path(Path::new("assets/chair.glb")),
+            Path::new("assets/chair.bookmarks.json")
+        );
+    }
+
+    #[test]
+    fn set_replaces_by_name() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(bookmark("front", 0.0));
+        bookmarks.set(bookmark("side", 1.0));
+        bookmarks.set(bookmark("front", 2.0));
+        assert_eq!(bookmarks.bookmarks.len(), 2);
+        assert_eq!(bookmarks.bookmarks[0].pose.yaw, 2.0);
+        assert!(bookmarks.remove("side"));
+        assert!(!bookmarks.remove("side"));
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(bookmark("front", 0.5));
+        let text = serde_json::to_string(&bookmarks).unwrap();
+        assert!(text.contains("\"yaw\":0.5"));
+        assert_eq!(serde_json::from_str::<Bookmarks>(&text).unwrap(), bookmarks);
+    }
+}
@@ -0,0 +1,208 @@
+//! World space bounds of the scene nodes: the `min` and `max` the glTF
+//! document gives for the position accessors of each mesh, moved by the
+//! transforms of the nodes using it. Read from the document alone, without
+//! the uploaded geometry. Used to place the camera and to scale navigation to
+//! the scene.
+
+use std::path::Path;
+
+use glam::{Mat4, Vec3};
+
+use super::LoadError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Bounds {
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3::splat(f32::INFINITY),
+            max: Vec3::splat(f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn grow(self, point: Vec3) -> Self {
+        Self {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn union(self, other: Bounds) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// The bounds of the transformed corners.
+    pub fn transform(self, transform: Mat4) -> Self {
+        if self.is_empty() {
+            return self;
+        }
+        (0..8).fold(Bounds::empty(), |bounds, corner| {
+            let point = Vec3::new(
+                if corner & 1 == 0 {
+                    self.min.x
+                } else {
+                    self.max.x
+                },
+                if corner & 2 == 0 {
+                    self.min.y
+                } else {
+                    self.max.y
+                },
+                if corner & 4 == 0 {
+                    self.min.z
+                } else {
+                    self.max.z
+                },
+            );
+            bounds.grow(transform.transform_point3(point))
+        })
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Radius of the sphere around [`Bounds::center`] enclosing the box.
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).length() * 0.5
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeBounds {
+    pub name: String,
+    pub bounds: Bounds,
+}
+
+/// One entry per node with a mesh, in depth first order.
+#[derive(Debug, Clone, Default)]
+pub struct SceneBounds {
+    pub nodes: Vec<NodeBounds>,
+}
+
+impl SceneBounds {
+    /// Only reads the document, the bounds come from the required `min` and
+    /// `max` of the position accessors.
+    pub fn from_file(path: &Path) -> Result<Self, LoadError> {
+        let gltf = gltf::Gltf::open(path).map_err(|e| LoadError::from_gltf(path, e))?;
+        Ok(Self::from_document(&gltf.document))
+    }
+
+    pub fn from_document(document: &gltf::Document) -> Self {
+        let mut nodes = Vec::new();
+        visit_nodes(document, &mut |node, transform| {
+            if let Some(mesh) = node.mesh() {
+                let bounds = mesh
+                    .primitives()
+                    .map(|primitive| {
+                        let bounding_box = primitive.bounding_box();
+                        Bounds {
+                            min: bounding_box.min.into(),
+                            max: bounding_box.max.into(),
+                        }
+                    })
+                    .fold(Bounds::empty(), Bounds::union);
+                nodes.push(NodeBounds {
+                    name: node
+                        .name()
+                        .or_else(|| mesh.name())
+                        .map(|name| name.to_owned())
+                        .unwrap_or_else(|| format!("node {}", node.index())),
+                    bounds: bounds.transform(transform),
+                });
+            }
+        });
+        Self { nodes }
+    }
+
+    pub fn all(&self) -> Bounds {
+        self.nodes
+            .iter()
+            .fold(Bounds::empty(), |bounds, node| bounds.union(node.bounds))
+    }
+}
+
+/// Calls `visit` with every node of the default scene and its world
+/// transform, parents before children.
+pub(crate) fn visit_nodes(document: &gltf::Document, visit: &mut dyn FnMut(&gltf::Node, Mat4)) {
+    fn walk(node: &gltf::Node, parent: Mat4, visit: &mut dyn FnMut(&gltf::Node, Mat4)) {
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        visit(node, transform);
+        for child in node.children() {
+            walk(&child, transform, visit);
+        }
+    }
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        for node in scene.nodes() {
+            walk(&node, Mat4::IDENTITY, visit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "name": "parent", "translation": [10, 0, 0], "children": [1] },
+            { "mesh": 0, "scale": [2, 2, 2] }
+        ],
+        "meshes": [{ "name": "cube", "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+        "buffers": [{ "uri": "cube.bin", "byteLength": 96 }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 96 }],
+        "accessors": [{
+            "bufferView": 0,
+            "componentType": 5126,
+            "count": 8,
+            "type": "VEC3",
+            "min": [-1, -1, -1],
+            "max": [1, 1, 1]
+        }]
+    }"#;
+
+    #[test]
+    fn nodes_are_in_world_space() {
+        let gltf = gltf::Gltf::from_slice(DOCUMENT.as_bytes()).unwrap();
+        let bounds = SceneBounds::from_document(&gltf.document);
+        assert_eq!(bounds.nodes.len(), 1);
+        assert_eq!(bounds.nodes[0].name, "cube");
+        assert_eq!(
+            bounds.all(),
+            Bounds {
+                min: Vec3::new(8.0, -2.0, -2.0),
+                max: Vec3::new(12.0, 2.0, 2.0),
+            }
+        );
+    }
+
+    #[test]
+    fn rotation_grows_the_box() {
+        let bounds = Bounds {
+            min: Vec3::splat(-1.0),
+            max: Vec3::splat(1.0),
+        }
+        .transform(Mat4::from_rotation_y(std::f32::consts::FRAC_PI_4));
+        let corner = 2.0f32.sqrt();
+        assert!((bounds.max.x - corner).abs() < 1e-5);
+        assert!((bounds.max.y - 1.0).abs() < 1e-5);
+        assert!(Bounds::empty().transform(Mat4::IDENTITY).is_empty());
+    }
+}
@@ -0,0 +1,399 @@
+//! Intrinsics of a calibrated sensor in OpenCV's camera model, so renders
+//! line up pixel for pixel with footage of the real camera.
+//!
+//! Read from the YAML or JSON files `cv::FileStorage` writes:
+//!
+//! ```yaml
+//! %YAML:1.0
+//! ---
+//! image_width: 1920
+//! image_height: 1080
+//! distortion_model: plumb_bob      # or fisheye
+//! camera_matrix: !!opencv-matrix
+//!    rows: 3
+//!    cols: 3
+//!    dt: d
+//!    data: [ 1400., 0., 960., 0., 1400., 540., 0., 0., 1. ]
+//! distortion_coefficients: !!opencv-matrix
+//!    rows: 1
+//!    cols: 5
+//!    dt: d
+//!    data: [ -0.1, 0.02, 0., 0., 0. ]
+//! ```
+//!
+//! `K` and `D`, `cameraMatrix` and `distCoeffs` are accepted as well, and
+//! the matrices may be plain arrays in JSON.
+
+use std::path::Path;
+
+use glam::{Vec2, Vec3, Vec4};
+use serde_json::Value;
+
+use super::interop;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum Distortion {
+    /// The rational polynomial model in OpenCV's order: k1, k2, p1, p2, k3,
+    /// k4, k5, k6. Files with fewer coefficients leave the rest zero.
+    BrownConrady { coefficients: [f32; 8] },
+    /// The equidistant fisheye model of `cv::fisheye`: k1, k2, k3, k4.
+    Fisheye { coefficients: [f32; 4] },
+}
+
+/// Pixel coordinates follow OpenCV: the first pixel's centre is the origin,
+/// x goes right and y down.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Intrinsics {
+    pub width: u32,
+    pub height: u32,
+    pub fx: f32,
+    pub fy: f32,
+    pub cx: f32,
+    pub cy: f32,
+    pub distortion: Distortion,
+}
+
+impl Intrinsics {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let value = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            _ => parse_opencv_yaml(&text),
+        };
+        value
+            .and_then(|value| Self::from_value(&value))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| value.get(*name))
+                .ok_or_else(|| format!("missing {}", names[0]))
+        };
+        let size = |names: &[&str]| {
+            field(names)?
+                .as_u64()
+                .filter(|size| *size > 0)
+                .map(|size| size as u32)
+                .ok_or_else(|| format!("{} is not a positive integer", names[0]))
+        };
+        let width = size(&["image_width", "width"])?;
+        let height = size(&["image_height", "height"])?;
+
+        let matrix = matrix_data(field(&["camera_matrix", "K", "cameraMatrix"])?)?;
+        if matrix.len() != 9 {
+            return Err(format!(
+                "camera_matrix has {} elements, expected 9",
+                matrix.len()
+            ));
+        }
+        let coefficients = match field(&["distortion_coefficients", "D", "distCoeffs"]) {
+            Ok(coefficients) => matrix_data(coefficients)?,
+            Err(_) => Vec::new(),
+        };
+        let model = field(&["distortion_model", "model"])
+            .ok()
+            .and_then(Value::as_str)
+            .unwrap_or("plumb_bob");
+        let distortion = match model {
+            "fisheye" | "equidistant" | "kannala_brandt" => {
+                if coefficients.len() > 4 {
+                    return Err(format!(
+                        "fisheye distortion has {} coefficients, expected 4",
+                        coefficients.len()
+                    ));
+                }
+                let mut k = [0.0; 4];
+                k[..coefficients.len()].copy_from_slice(&coefficients);
+                Distortion::Fisheye { coefficients: k }
+            }
+            "plumb_bob" | "rational_polynomial" | "brown_conrady" | "radtan" => {
+                // thin prism and tilt terms come after the eight
+                if coefficients.iter().skip(8).any(|c| *c != 0.0) {
+                    return Err("thin prism and tilt distortion are not supported".to_owned());
+                }
+                let mut k = [0.0; 8];
+                let n = coefficients.len().min(8);
+                k[..n].copy_from_slice(&coefficients[..n]);
+                Distortion::BrownConrady { coefficients: k }
+            }
+            model => return Err(format!("unknown distortion model {}", model)),
+        };
+        Ok(Self {
+            width,
+            height,
+            fx: matrix[0],
+            fy: matrix[4],
+            cx: matrix[2],
+            cy: matrix[5],
+            distortion,
+        })
+    }
+
+    /// Vertical field of view of the undistorted pinhole, for what only
+    /// knows perspective cameras.
+    pub fn fov(&self) -> f32 {
+        2.0 * (self.height as f32 * 0.5 / self.fy).atan()
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    /// Where the real camera images `direction`, given in its frame with y
+    /// down and z forward. `cv::projectPoints` without the extrinsics.
+    pub fn project(&self, direction: Vec3) -> Vec2 {
+        let point = Vec2::new(direction.x, direction.y) / direction.z;
+        let distorted = match self.distortion {
+            Distortion::BrownConrady { coefficients } => {
+                let [k1, k2, p1, p2, k3, k4, k5, k6] = coefficients;
+                let (x, y) = (point.x, point.y);
+                let r2 = x * x + y * y;
+                let radial = (1.0 + r2 * (k1 + r2 * (k2 + r2 * k3)))
+                    / (1.0 + r2 * (k4 + r2 * (k5 + r2 * k6)));
+                Vec2::new(
+                    x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+                    y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y,
+                )
+            }
+            Distortion::Fisheye { coefficients } => {
+                let r = Vec2::new(direction.x, direction.y).length();
+                let theta = r.atan2(direction.z);
+                if r == 0.0 {
+                    Vec2::ZERO
+                } else {
+                    Vec2::new(direction.x, direction.y) / r * fisheye_theta_d(coefficients, theta)
+                }
+            }
+        };
+        Vec2::new(
+            self.fx * distorted.x + self.cx,
+            self.fy * distorted.y + self.cy,
+        )
+    }
+
+    /// The unit direction the real camera sees at `pixel`, in its frame with
+    /// y down and z forward. Inverts [`Intrinsics::project`].
+    pub fn unproject(&self, pixel: Vec2) -> Vec3 {
+        let distorted = Vec2::new((pixel.x - self.cx) / self.fx, (pixel.y - self.cy) / self.fy);
+        // the raygen shaders undistort with the same functions
+        let direction = match self.distortion {
+            Distortion::BrownConrady { coefficients } => {
+                po_shader::undistort_brown_conrady(
+                    interop::to_shader_vec2(distorted),
+                    interop::to_shader_vec4(Vec4::from_slice(&coefficients[..4])),
+                    interop::to_shader_vec4(Vec4::from_slice(&coefficients[4..])),
+                )
+            }
+            Distortion::Fisheye { coefficients } => {
+                po_shader::undistort_fisheye(
+                    interop::to_shader_vec2(distorted),
+                    interop::to_shader_vec4(Vec4::from(coefficients)),
+                )
+            }
+        };
+        interop::from_shader_vec3(direction)
+    }
+}
+
+fn fisheye_theta_d(k: [f32; 4], theta: f32) -> f32 {
+    let t2 = theta * theta;
+    theta * (1.0 + t2 * (k[0] + t2 * (k[1] + t2 * (k[2] + t2 * k[3]))))
+}
+
+/// The elements of an `opencv-matrix` map, or of a plain, possibly nested,
+/// array.
+fn matrix_data(value: &Value) -> Result<Vec<f32>, String> {
+    fn flatten(value: &Value, data: &mut Vec<f32>) -> Result<(), String> {
+        match value {
+            Value::Array(values) => values.iter().try_for_each(|v| flatten(v, data)),
+            Value::Number(n) => {
+                data.push(n.as_f64().unwrap_or_default() as f32);
+                Ok(())
+            }
+            v => Err(format!("expected a number, found {}", v)),
+        }
+    }
+    let mut data = Vec::new();
+    flatten(value.get("data").unwrap_or(value), &mut data)?;
+    Ok(data)
+}
+
+/// Reads the subset of YAML `cv::FileStorage` writes: top level scalars and
+/// maps one level deep, with the flow sequences of matrix data allowed to
+/// span lines. `serde_json` values so JSON files take the same path.
+fn parse_opencv_yaml(text: &str) -> Result<Value, String> {
+    fn scalar(text: &str) -> Value {
+        let text = text.trim().trim_matches('"');
+        if let Ok(n) = text.parse::<i64>() {
+            return serde_json::json!(n);
+        }
+        match text.parse::<f64>() {
+            Ok(n) => serde_json::json!(n),
+            Err(_) => Value::String(text.to_owned()),
+        }
+    }
+    fn sequence(text: &str) -> Result<Value, String> {
+        let inner = text
+            .trim()
+            .strip_prefix('[')
+            .and_then(|t| t.strip_suffix(']'))
+            .ok_or_else(|| format!("unterminated sequence {}", text.trim()))?;
+        Ok(Value::Array(
+            inner
+                .split(',')
+                .filter(|item| !item.trim().is_empty())
+                .map(scalar)
+                .collect(),
+        ))
+    }
+
+    let mut root = serde_json::Map::new();
+    let mut map: Option<String> = None;
+    let mut lines = text.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let content = line.split('#').next().unwrap_or_default();
+        if content.trim().is_empty() || line.starts_with('%') || line.starts_with("---") {
+            continue;
+        }
+        let (key, value) = content
+            .split_once(':')
+            .ok_or_else(|| format!("line {}: expected key: value", number + 1))?;
+        let key = key.trim().to_owned();
+        let mut value = value.trim().to_owned();
+        if value.starts_with('[') {
+            // matrix data wraps onto more lines
+            while !value.ends_with(']') {
+                let (_, next) = lines
+                    .next()
+                    .ok_or_else(|| format!("line {}: unterminated sequence", number + 1))?;
+                value.push(' ');
+                value.push_str(next.trim());
+            }
+        }
+        let indented = line.starts_with(char::is_whitespace);
+        match (&map, indented) {
+            (Some(parent), true) => {
+                let value = if value.starts_with('[') {
+                    sequence(&value)?
+                } else {
+                    scalar(&value)
+                };
+                if let Some(Value::Object(fields)) = root.get_mut(parent) {
+                    fields.insert(key, value);
+                }
+            }
+            (_, true) => return Err(format!("line {}: unexpected indentation", number + 1)),
+            (_, false) => {
+                // a tag alone opens a map, as does nothing
+                if value.is_empty() || value.starts_with("!!") {
+                    root.insert(key.clone(), Value::Object(Default::default()));
+                    map = Some(key);
+                } else {
+                    root.insert(
+                        key,
+                        if value.starts_with('[') {
+                            sequence(&value)?
+                        } else {
+                            scalar(&value)
+                        },
+                    );
+                    map = None;
+                }
+            }
+        }
+    }
+    Ok(Value::Object(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = "%YAML:1.0
+---
+image_width: 640
+image_height: 480
+camera_matrix: !!opencv-matrix
+   rows: 3
+   cols: 3
+   dt: d
+   data: [ 500., 0., 319.5, 0., 505., 239.5,
+       0., 0., 1. ]
+distortion_coefficients: !!opencv-matrix
+   rows: 1
+   cols: 5
+   dt: d
+   data: [ -0.2, 0.05, 0.001, -0.002, 0.01 ]
+";
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn reads_opencv_yaml() {
+        let intrinsics = Intrinsics::from_value(&parse_opencv_yaml(YAML).unwrap()).unwrap();
+        assert_eq!((intrinsics.width, intrinsics.height), (640, 480));
+        assert_eq!(
+            (intrinsics.fx, intrinsics.fy, intrinsics.cx, intrinsics.cy),
+            (500.0, 505.0, 319.5, 239.5)
+        );
+        assert_eq!(
+            intrinsics.distortion,
+            Distortion::BrownConrady {
+                coefficients: [-0.2, 0.05, 0.001, -0.002, 0.01, 0.0, 0.0, 0.0]
+            }
+        );
+    }
+
+    #[test]
+    fn reads_json_with_plain_arrays() {
+        let value = serde_json::json!({
+            "width": 1280,
+            "height": 800,
+            "model": "fisheye",
+            "K": [[600.0, 0.0, 640.0], [0.0, 600.0, 400.0], [0.0, 0.0, 1.0]],
+            "D": [0.1, -0.01, 0.002, 0.0],
+        });
+        let intrinsics = Intrinsics::from_value(&value).unwrap();
+        assert_eq!(
+            intrinsics.distortion,
+            Distortion::Fisheye {
+                coefficients: [0.1, -0.01, 0.002, 0.0]
+            }
+        );
+        assert!(Intrinsics::from_value(&serde_json::json!({ "width": 1 })).is_err());
+    }
+
+    #[test]
+    fn unproject_inverts_project() {
+        let brown_conrady = Intrinsics::from_value(&parse_opencv_yaml(YAML).unwrap()).unwrap();
+        let fisheye = Intrinsics {
+            distortion: Distortion::Fisheye {
+                coefficients: [0.1, -0.01, 0.002, 0.0],
+            },
+            ..brown_conrady
+        };
+        for intrinsics in [brown_conrady, fisheye].iter() {
+            assert_near(
+                intrinsics.unproject(Vec2::new(intrinsics.cx, intrinsics.cy)),
+                Vec3::Z,
+            );
+            for direction in [
+                Vec3::new(0.3, -0.2, 1.0),
+                Vec3::new(-0.4, 0.3, 1.0),
+                Vec3::new(0.05, 0.5, 1.0),
+            ]
+            .iter()
+            {
+                let pixel = intrinsics.project(*direction);
+                assert_near(intrinsics.unproject(pixel), direction.normalize());
+            }
+        }
+    }
+}
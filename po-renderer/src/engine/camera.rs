@@ -1,5 +1,253 @@
+use super::calibration::{Distortion, Intrinsics};
+use super::interop;
 use crate::{vec3, Vec3};
+use glam::{Mat4, Vec2};
 
+/// Clip planes for scenes a few units across, see [`Camera::fit`].
+const DEFAULT_NEAR: f32 = 0.001;
+const DEFAULT_FAR: f32 = 10000.0;
+
+/// Height of a full frame sensor, relating f-stops to aperture radii in
+/// scenes modelled in metres.
+pub const SENSOR_HEIGHT: f32 = 0.024;
+
+/// How rays leave the camera. Perspective and orthographic can be
+/// rasterised, the panoramic ones are only ray traced.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Projection {
+    /// With the vertical field of view of [`Camera::fov`].
+    Perspective,
+    /// Parallel rays across a view `height` scene units high.
+    Orthographic { height: f32 },
+    /// The whole sphere, longitude across and latitude up.
+    Equirectangular,
+    /// An image circle as high as the view and `fov` radians across.
+    Fisheye { mapping: FisheyeMapping, fov: f32 },
+    /// The rays of a real sensor, its image stretched over the view.
+    Calibrated(Intrinsics),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FisheyeMapping {
+    /// The angle off the axis grows linearly with the radius.
+    Equidistant,
+    /// Equal areas of the image cover equal solid angles.
+    Equisolid,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective
+    }
+}
+
+impl Projection {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "Perspective",
+            Projection::Orthographic { .. } => "Orthographic",
+            Projection::Equirectangular => "Equirectangular",
+            Projection::Fisheye { .. } => "Fisheye",
+            Projection::Calibrated(_) => "Calibrated",
+        }
+    }
+
+    pub fn is_rasterisable(&self) -> bool {
+        matches!(
+            self,
+            Projection::Perspective | Projection::Orthographic { .. }
+        )
+    }
+}
+
+impl FisheyeMapping {
+    pub const ALL: [FisheyeMapping; 2] = [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FisheyeMapping::Equidistant => "Equidistant",
+            FisheyeMapping::Equisolid => "Equisolid",
+        }
+    }
+}
+
+/// [`Projection`] as the raygen shaders read it from their uniform buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+pub struct ProjectionInfo {
+    pub kind: u32,
+    pub fov: f32,
+    pub aspect_ratio: f32,
+    pub padding: u32,
+    /// fx, fy, cx, cy of a calibrated sensor in units of its image size,
+    /// with pixel centres at half steps.
+    pub intrinsics: glam::Vec4,
+    /// Its distortion coefficients in OpenCV's order.
+    pub distortion: [glam::Vec4; 2],
+}
+
+impl ProjectionInfo {
+    pub const PERSPECTIVE: u32 = 0;
+    pub const ORTHOGRAPHIC: u32 = 1;
+    pub const EQUIRECTANGULAR: u32 = 2;
+    pub const FISHEYE_EQUIDISTANT: u32 = 3;
+    pub const FISHEYE_EQUISOLID: u32 = 4;
+    pub const CALIBRATED_BROWN_CONRADY: u32 = 5;
+    pub const CALIBRATED_FISHEYE: u32 = 6;
+
+    pub fn new(camera: &Camera) -> Self {
+        let (kind, fov) = match camera.projection {
+            Projection::Perspective => (Self::PERSPECTIVE, camera.fov),
+            // the height is in the projection matrix
+            Projection::Orthographic { .. } => (Self::ORTHOGRAPHIC, camera.fov),
+            Projection::Equirectangular => (Self::EQUIRECTANGULAR, camera.fov),
+            Projection::Fisheye {
+                mapping: FisheyeMapping::Equidistant,
+                fov,
+            } => (Self::FISHEYE_EQUIDISTANT, fov),
+            Projection::Fisheye {
+                mapping: FisheyeMapping::Equisolid,
+                fov,
+            } => (Self::FISHEYE_EQUISOLID, fov),
+            Projection::Calibrated(Intrinsics {
+                distortion: Distortion::BrownConrady { .. },
+                ..
+            }) => (Self::CALIBRATED_BROWN_CONRADY, camera.fov),
+            Projection::Calibrated(Intrinsics {
+                distortion: Distortion::Fisheye { .. },
+                ..
+            }) => (Self::CALIBRATED_FISHEYE, camera.fov),
+        };
+        let (intrinsics, distortion) = match camera.projection {
+            Projection::Calibrated(intrinsics) => {
+                let (width, height) = (intrinsics.width as f32, intrinsics.height as f32);
+                let coefficients = match intrinsics.distortion {
+                    Distortion::BrownConrady { coefficients } => coefficients,
+                    Distortion::Fisheye { coefficients } => {
+                        let [k1, k2, k3, k4] = coefficients;
+                        [k1, k2, k3, k4, 0.0, 0.0, 0.0, 0.0]
+                    }
+                };
+                (
+                    glam::Vec4::new(
+                        intrinsics.fx / width,
+                        intrinsics.fy / height,
+                        (intrinsics.cx + 0.5) / width,
+                        (intrinsics.cy + 0.5) / height,
+                    ),
+                    [
+                        glam::Vec4::from_slice(&coefficients[..4]),
+                        glam::Vec4::from_slice(&coefficients[4..]),
+                    ],
+                )
+            }
+            _ => (glam::Vec4::ZERO, [glam::Vec4::ZERO; 2]),
+        };
+        Self {
+            kind,
+            fov,
+            aspect_ratio: camera.aspect_ratio,
+            padding: 0,
+            intrinsics,
+            distortion,
+        }
+    }
+
+    /// The same struct as the shader crates declare it, for calling their
+    /// functions on the host.
+    pub fn to_shader(&self) -> po_shader::ProjectionInfo {
+        po_shader::ProjectionInfo {
+            kind: self.kind,
+            fov: self.fov,
+            aspect_ratio: self.aspect_ratio,
+            padding: self.padding,
+            intrinsics: interop::to_shader_vec4(self.intrinsics),
+            distortion: [
+                interop::to_shader_vec4(self.distortion[0]),
+                interop::to_shader_vec4(self.distortion[1]),
+            ],
+        }
+    }
+}
+
+/// [`Lens`] as the raygen shaders read it from their uniform buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+pub struct LensInfo {
+    pub aperture_radius: f32,
+    pub focus_distance: f32,
+    pub blades: u32,
+    pub blade_rotation: f32,
+    pub anamorphic_ratio: f32,
+    /// Rays per pixel through an open aperture.
+    pub samples: u32,
+    /// Varies the rng between renders.
+    pub seed: u32,
+    pub padding: u32,
+}
+
+impl LensInfo {
+    pub fn new(lens: &Lens, samples: u32, seed: u32) -> Self {
+        Self {
+            aperture_radius: lens.aperture_radius,
+            focus_distance: lens.focus_distance,
+            blades: lens.blades,
+            blade_rotation: lens.blade_rotation,
+            anamorphic_ratio: lens.anamorphic_ratio,
+            samples,
+            seed,
+            padding: 0,
+        }
+    }
+
+    /// The same struct as the shader crates declare it.
+    pub fn to_shader(&self) -> po_shader::LensInfo {
+        po_shader::LensInfo {
+            aperture_radius: self.aperture_radius,
+            focus_distance: self.focus_distance,
+            blades: self.blades,
+            blade_rotation: self.blade_rotation,
+            anamorphic_ratio: self.anamorphic_ratio,
+            samples: self.samples,
+            seed: self.seed,
+            padding: self.padding,
+        }
+    }
+}
+
+/// Thin lens in front of the camera. A zero aperture is a pinhole with
+/// everything in focus.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Lens {
+    /// In scene units.
+    pub aperture_radius: f32,
+    /// Distance of the plane in focus along the view direction.
+    pub focus_distance: f32,
+    /// Straight blades shaping the bokeh, fewer than 3 is a round aperture.
+    pub blades: u32,
+    /// Of the first blade corner from the right, in radians.
+    pub blade_rotation: f32,
+    /// Width over height of the aperture, below 1 gives the tall bokeh of
+    /// anamorphic lenses.
+    pub anamorphic_ratio: f32,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Self {
+            aperture_radius: 0.0,
+            focus_distance: 10.0,
+            blades: 0,
+            blade_rotation: 0.0,
+            anamorphic_ratio: 1.0,
+        }
+    }
+}
+
+/// Perspective camera with a left handed, y up view.
 #[derive(Debug, Clone, Default)]
 pub struct Camera {
     pub location: Vec3,
@@ -10,6 +258,30 @@ pub struct Camera {
     pitch: f32,
     pub aspect_ratio: f32,
     pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+    pub lens: Lens,
+    pub projection: Projection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// WASD and right drag to look around.
+    Fly,
+    /// Right drag orbits, middle drag pans and the wheel dollies around
+    /// [`Orbit::pivot`].
+    Orbit,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 2] = [CameraMode::Fly, CameraMode::Orbit];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CameraMode::Fly => "Fly",
+            CameraMode::Orbit => "Orbit",
+        }
+    }
 }
 
 pub enum Direction {
@@ -39,6 +311,8 @@ impl Camera {
             pitch,
             aspect_ratio,
             fov,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
             ..Default::default()
         };
 
@@ -47,6 +321,111 @@ impl Camera {
         camera
     }
 
+    /// A camera looking along `yaw` and `pitch`, in radians.
+    pub fn from_angles(location: Vec3, yaw: f32, pitch: f32, aspect_ratio: f32, fov: f32) -> Self {
+        let mut camera = Self {
+            location,
+            yaw,
+            pitch: pitch.clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2),
+            aspect_ratio,
+            fov,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            ..Default::default()
+        };
+        camera.update_camera_vector();
+        camera
+    }
+
+    /// Scales the clip planes to a scene of bounding `radius`, keeping the
+    /// default depth range ratio.
+    pub fn fit(&mut self, radius: f32) {
+        self.near = radius * 1e-4;
+        self.far = radius * 1e3;
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_lh(self.location, self.location + self.front, self.up)
+    }
+
+    /// Panoramic projections are not linear, they get the perspective
+    /// matrix and the wireframe pass previews them through it.
+    pub fn projection_matrix(&self) -> Mat4 {
+        match self.projection {
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect_ratio;
+                Mat4::orthographic_lh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.near,
+                    self.far,
+                )
+            }
+            _ => Mat4::perspective_lh(self.fov, self.aspect_ratio, self.near, self.far),
+        }
+    }
+
+    /// Height of the view `distance` ahead, what an orthographic projection
+    /// needs to show as much.
+    pub fn view_height(&self, distance: f32) -> f32 {
+        match self.projection {
+            Projection::Orthographic { height } => height,
+            _ => 2.0 * distance * (self.fov * 0.5).tan(),
+        }
+    }
+
+    /// World space origin and direction of the ray through `ndc`, y up, the
+    /// same the raygen shaders trace through the lens centre. `None` outside
+    /// the image circle of a fisheye.
+    pub fn ray(&self, ndc: Vec2) -> Option<(Vec3, Vec3)> {
+        let (origin, direction) = po_shader::view_ray(
+            interop::to_shader_mat4(self.projection_matrix().inverse()),
+            &ProjectionInfo::new(self).to_shader(),
+            interop::to_shader_vec2(ndc),
+        );
+        let (origin, direction) = (
+            interop::from_shader_vec3(origin),
+            interop::from_shader_vec3(direction),
+        );
+        if direction == Vec3::ZERO {
+            return None;
+        }
+        let view_inv = self.view().inverse();
+        Some((
+            view_inv.transform_point3(origin),
+            view_inv.transform_vector3(direction).normalize(),
+        ))
+    }
+
+    /// Focal length of a [`SENSOR_HEIGHT`] sensor at the field of view.
+    fn focal_length(&self) -> f32 {
+        SENSOR_HEIGHT / (2.0 * (self.fov * 0.5).tan())
+    }
+
+    /// Infinite for a pinhole.
+    pub fn f_stop(&self) -> f32 {
+        if self.lens.aperture_radius > 0.0 {
+            self.focal_length() / (2.0 * self.lens.aperture_radius)
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    pub fn set_f_stop(&mut self, f_stop: f32) {
+        self.lens.aperture_radius = self.focal_length() / (2.0 * f_stop);
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
     pub fn update_camera_vector(&mut self) {
         self.front.x = self.yaw.cos() * self.pitch.cos();
         self.front.y = self.pitch.sin();
@@ -94,3 +473,180 @@ impl Camera {
         self.update_camera_vector();
     }
 }
+
+const MIN_ORBIT_DISTANCE: f32 = 1e-4;
+
+/// Turntable navigation: the camera keeps looking at `pivot` from `distance`
+/// away, only its angles and the pivot change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Orbit {
+    pub pivot: Vec3,
+    pub distance: f32,
+}
+
+impl Orbit {
+    /// The orbit `camera` is already on when the pivot is `distance` ahead,
+    /// so switching from flying keeps the view.
+    pub fn in_front_of(camera: &Camera, distance: f32) -> Self {
+        let distance = distance.max(MIN_ORBIT_DISTANCE);
+        Self {
+            pivot: camera.location + camera.front * distance,
+            distance,
+        }
+    }
+
+    fn place(&self, camera: &mut Camera) {
+        camera.location = self.pivot - camera.front * self.distance;
+    }
+
+    pub fn rotate(&self, camera: &mut Camera, yaw_offset: f32, pitch_offset: f32) {
+        camera.process_mouse_movement(yaw_offset, pitch_offset);
+        self.place(camera);
+    }
+
+    /// Slides the pivot and the camera across the view, `dx` and `dy` are in
+    /// units of the distance so the pivot follows the cursor.
+    pub fn pan(&mut self, camera: &mut Camera, dx: f32, dy: f32) {
+        self.pivot += (camera.up * dy - camera.right * dx) * self.distance;
+        self.place(camera);
+    }
+
+    /// Scales the distance to the pivot, below 1 moves closer. Never passes
+    /// through the pivot. An orthographic view zooms along.
+    pub fn dolly(&mut self, camera: &mut Camera, factor: f32) {
+        let distance = (self.distance * factor).max(MIN_ORBIT_DISTANCE);
+        if let Projection::Orthographic { height } = &mut camera.projection {
+            *height *= distance / self.distance;
+        }
+        self.distance = distance;
+        self.place(camera);
+    }
+
+    /// Keeps the view direction and backs off until a sphere fits the
+    /// narrower of the two fields of view, an orthographic view is sized to
+    /// fit it too.
+    pub fn frame(&mut self, camera: &mut Camera, center: Vec3, radius: f32) {
+        let vertical = camera.fov * 0.5;
+        let horizontal = (vertical.tan() * camera.aspect_ratio).atan();
+        self.pivot = center;
+        self.distance = (radius / vertical.min(horizontal).sin()).max(MIN_ORBIT_DISTANCE);
+        if let Projection::Orthographic { height } = &mut camera.projection {
+            *height = 2.0 * radius * camera.aspect_ratio.recip().max(1.0);
+        }
+        self.place(camera);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn entering_orbit_keeps_the_view() {
+        let mut camera = Camera::new(vec3(1.0, 2.0, 3.0), vec3(0.0, 0.0, 0.0), 1.5, 1.0);
+        let before = camera.clone();
+        let mut orbit = Orbit::in_front_of(&camera, 5.0);
+        orbit.dolly(&mut camera, 1.0);
+        assert_near(camera.location, before.location);
+        assert_near(camera.front, before.front);
+    }
+
+    #[test]
+    fn rotating_keeps_the_pivot_centered() {
+        let mut camera = Camera::new(vec3(0.0, 0.0, 10.0), vec3(0.0, 0.0, 0.0), 1.0, 1.0);
+        let mut orbit = Orbit::in_front_of(&camera, 10.0);
+        orbit.rotate(&mut camera, 0.7, -0.3);
+        orbit.pan(&mut camera, 0.1, 0.2);
+        assert_near(camera.location + camera.front * orbit.distance, orbit.pivot);
+        assert!(((camera.location - orbit.pivot).length() - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rays_follow_the_view() {
+        let camera = Camera::new(vec3(1.0, 2.0, 3.0), vec3(4.0, 0.0, -1.0), 1.5, 1.0);
+        let direction = |ndc| camera.ray(ndc).unwrap().1;
+        assert_near(direction(Vec2::ZERO), camera.front);
+        assert!(direction(Vec2::new(0.0, 1.0)).dot(camera.up) > 0.0);
+        assert!(direction(Vec2::new(1.0, 0.0)).dot(camera.right) > 0.0);
+        // the top edge is half the field of view above the axis
+        let top = direction(Vec2::new(0.0, 1.0));
+        assert!((top.dot(camera.front).acos() - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let mut camera = Camera::new(vec3(0.0, 0.0, 10.0), vec3(0.0, 0.0, 0.0), 2.0, 1.0);
+        camera.projection = Projection::Orthographic { height: 4.0 };
+        let (origin, direction) = camera.ray(Vec2::new(1.0, 1.0)).unwrap();
+        assert_near(direction, camera.front);
+        assert_near(
+            origin,
+            camera.location + camera.right * 4.0 + camera.up * 2.0,
+        );
+        let corner = camera.projection_matrix() * camera.view() * (origin + direction).extend(1.0);
+        assert!((corner.x - 1.0).abs() < 1e-4 && (corner.y - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn panoramas_cover_the_sphere() {
+        let mut camera = Camera::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0), 2.0, 1.0);
+        camera.projection = Projection::Equirectangular;
+        let direction = |camera: &Camera, x, y| camera.ray(Vec2::new(x, y)).unwrap().1;
+        assert_near(direction(&camera, 0.0, 0.0), camera.front);
+        assert_near(direction(&camera, 1.0, 0.0), -camera.front);
+        assert_near(direction(&camera, 0.5, 0.0), camera.right);
+        assert_near(direction(&camera, 0.0, 1.0), camera.up);
+
+        for &mapping in FisheyeMapping::ALL.iter() {
+            camera.projection = Projection::Fisheye {
+                mapping,
+                fov: std::f32::consts::PI,
+            };
+            // the rim of a 180° circle looks sideways, its corners are black
+            assert_near(direction(&camera, 0.0, 1.0), camera.up);
+            assert_near(direction(&camera, 0.5, 0.0), camera.right);
+            assert!(camera.ray(Vec2::new(1.0, 1.0)).is_none());
+        }
+    }
+
+    #[test]
+    fn undistorted_sensors_are_pinholes() {
+        let intrinsics = Intrinsics {
+            width: 640,
+            height: 480,
+            fx: 400.0,
+            fy: 400.0,
+            cx: 319.5,
+            cy: 239.5,
+            distortion: Distortion::BrownConrady {
+                coefficients: [0.0; 8],
+            },
+        };
+        let pinhole = Camera::new(
+            vec3(1.0, 2.0, 3.0),
+            vec3(0.0, 0.0, 0.0),
+            4.0 / 3.0,
+            intrinsics.fov(),
+        );
+        let mut calibrated = pinhole.clone();
+        calibrated.projection = Projection::Calibrated(intrinsics);
+        for ndc in [Vec2::ZERO, Vec2::new(1.0, 1.0), Vec2::new(-0.5, 0.25)].iter() {
+            assert_near(
+                calibrated.ray(*ndc).unwrap().1,
+                pinhole.ray(*ndc).unwrap().1,
+            );
+        }
+    }
+
+    #[test]
+    fn f_stop_round_trips() {
+        let mut camera = Camera::new(vec3(0.0, 0.0, 10.0), vec3(0.0, 0.0, 0.0), 1.5, 1.0);
+        assert!(camera.f_stop().is_infinite());
+        camera.set_f_stop(2.8);
+        assert!((camera.f_stop() - 2.8).abs() < 1e-4);
+    }
+}
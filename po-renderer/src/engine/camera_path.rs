@@ -0,0 +1,439 @@
+//! Keyframed camera animation for turntables and flythroughs, played back in
+//! the viewport and rendered through [`Po`](super::po::Po) as an image
+//! sequence.
+
+use std::path::Path;
+
+use super::session::{self, CameraPose};
+
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Passes through every key.
+    CatmullRom,
+    /// Uses the keys as control points of one curve, smoother but only the
+    /// first and last key are passed through.
+    Bezier,
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Interpolation::CatmullRom
+    }
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 2] = [Interpolation::CatmullRom, Interpolation::Bezier];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Interpolation::CatmullRom => "Catmull-Rom",
+            Interpolation::Bezier => "Bezier",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Keyframe {
+    /// Seconds from the start of the path.
+    pub time: f32,
+    #[serde(flatten)]
+    pub pose: CameraPose,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CameraPath {
+    version: u32,
+    pub interpolation: Interpolation,
+    /// Slows down into the first key and out of the last.
+    pub ease: bool,
+    /// Of the rendered image sequence.
+    pub fps: f32,
+    /// Sorted by time.
+    pub keys: Vec<Keyframe>,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            interpolation: Interpolation::default(),
+            ease: false,
+            fps: 24.0,
+            keys: Vec::new(),
+        }
+    }
+}
+
+/// What is interpolated between keys: location, yaw, pitch, fov, focus
+/// distance and aperture radius.
+type Channels = [f32; 8];
+
+fn channels(pose: &CameraPose) -> Channels {
+    [
+        pose.location[0],
+        pose.location[1],
+        pose.location[2],
+        pose.yaw,
+        pose.pitch,
+        pose.fov,
+        pose.lens.focus_distance,
+        pose.lens.aperture_radius,
+    ]
+}
+
+fn lerp(a: &Channels, b: &Channels, t: f32) -> Channels {
+    let mut c = *a;
+    for (c, b) in c.iter_mut().zip(b) {
+        *c += (b - *c) * t;
+    }
+    c
+}
+
+impl CameraPath {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut camera_path: Self =
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if camera_path.version != VERSION {
+            return Err(format!(
+                "{}: camera path version {}, expected {}",
+                path.display(),
+                camera_path.version,
+                VERSION
+            ));
+        }
+        camera_path.sort();
+        Ok(camera_path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        session::write_json(path, self)
+    }
+
+    /// Time of the last key.
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0.0, |key| key.time)
+    }
+
+    /// Frames of the image sequence, the last key included.
+    pub fn frame_count(&self) -> usize {
+        if self.keys.is_empty() {
+            return 0;
+        }
+        (self.duration() * self.fps + 1e-3).floor() as usize + 1
+    }
+
+    /// Adds a key at `time`, replacing a key already there.
+    pub fn insert(&mut self, time: f32, camera: &super::Camera) {
+        let key = Keyframe {
+            time,
+            pose: CameraPose::new(camera),
+        };
+        match self.keys.iter_mut().find(|k| k.time == time) {
+            Some(existing) => *existing = key,
+            None => self.keys.push(key),
+        }
+        self.sort();
+    }
+
+    /// Restores the order after key times were edited.
+    pub fn sort(&mut self) {
+        self.keys.sort_by(|a, b| {
+            a.time
+                .partial_cmp(&b.time)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+
+    /// Spaces the keys evenly from 0 to `duration` seconds.
+    pub fn retime(&mut self, duration: f32) {
+        let steps = self.keys.len().saturating_sub(1).max(1) as f32;
+        for (i, key) in self.keys.iter_mut().enumerate() {
+            key.time = duration * i as f32 / steps;
+        }
+    }
+
+    /// The interpolated pose at `time`, clamped to the path. The lens shape
+    /// and the projection switch at keys instead of blending.
+    pub fn pose_at(&self, time: f32) -> Option<CameraPose> {
+        let first = self.keys.first()?;
+        if self.keys.len() == 1 {
+            return Some(first.pose);
+        }
+        let duration = self.duration() - first.time;
+        let mut time = time.clamp(first.time, self.duration());
+        if self.ease && duration > 0.0 {
+            let t = (time - first.time) / duration;
+            time = first.time + duration * t * t * (3.0 - 2.0 * t);
+        }
+        let segment = self
+            .keys
+            .windows(2)
+            .position(|pair| time <= pair[1].time)
+            .unwrap_or(self.keys.len() - 2);
+        let (start, end) = (&self.keys[segment], &self.keys[segment + 1]);
+        let span = end.time - start.time;
+        let t = if span > 0.0 {
+            (time - start.time) / span
+        } else {
+            1.0
+        };
+
+        let points = self.unwrapped_channels();
+        let c = match self.interpolation {
+            Interpolation::CatmullRom => self.catmull_rom(&points, segment, t),
+            Interpolation::Bezier => {
+                bezier(&points, (segment as f32 + t) / (points.len() - 1) as f32)
+            }
+        };
+        let mut pose = if t < 1.0 { start.pose } else { end.pose };
+        pose.location = [c[0], c[1], c[2]];
+        pose.yaw = c[3];
+        pose.pitch = c[4];
+        pose.fov = c[5];
+        pose.lens.focus_distance = c[6];
+        pose.lens.aperture_radius = c[7].max(0.0);
+        Some(pose)
+    }
+
+    /// The channels of every key, with yaw turning the short way round.
+    fn unwrapped_channels(&self) -> Vec<Channels> {
+        let tau = 2.0 * std::f32::consts::PI;
+        let mut points: Vec<Channels> = self.keys.iter().map(|k| channels(&k.pose)).collect();
+        for i in 1..points.len() {
+            let previous = points[i - 1][3];
+            points[i][3] += tau * ((previous - points[i][3]) / tau).round();
+        }
+        points
+    }
+
+    /// Hermite segment from key `i` to `i + 1` with tangents scaled to the
+    /// key spacing, so uneven timing does not overshoot.
+    fn catmull_rom(&self, points: &[Channels], i: usize, t: f32) -> Channels {
+        let last = points.len() - 1;
+        let time = |i: usize| self.keys[i].time;
+        let span = time(i + 1) - time(i);
+        let tangent = |i: usize| {
+            let (before, after) = (i.saturating_sub(1), (i + 1).min(last));
+            let dt = time(after) - time(before);
+            let mut m = [0.0; 8];
+            if dt > 0.0 {
+                for (c, m) in m.iter_mut().enumerate() {
+                    *m = (points[after][c] - points[before][c]) / dt * span;
+                }
+            }
+            m
+        };
+        let (m0, m1) = (tangent(i), tangent(i + 1));
+        let (t2, t3) = (t * t, t * t * t);
+        let mut c = [0.0; 8];
+        for (k, c) in c.iter_mut().enumerate() {
+            *c = (2.0 * t3 - 3.0 * t2 + 1.0) * points[i][k]
+                + (t3 - 2.0 * t2 + t) * m0[k]
+                + (-2.0 * t3 + 3.0 * t2) * points[i + 1][k]
+                + (t3 - t2) * m1[k];
+        }
+        c
+    }
+}
+
+/// De Casteljau's algorithm over all `points` at `u` in 0 to 1.
+fn bezier(points: &[Channels], u: f32) -> Channels {
+    let mut points = points.to_vec();
+    while points.len() > 1 {
+        points = points
+            .windows(2)
+            .map(|pair| lerp(&pair[0], &pair[1], u))
+            .collect();
+    }
+    points[0]
+}
+
+impl super::Engine {
+    /// Keys the current view one second after the last key, or at the start
+    /// of an empty path.
+    pub fn add_path_key(&mut self) {
+        let time = if self.camera_path.keys.is_empty() {
+            0.0
+        } else {
+            self.camera_path.duration() + 1.0
+        };
+        self.camera_path.insert(time, &self.camera);
+    }
+
+    pub fn open_camera_path(&mut self, path: &Path) -> Result<(), String> {
+        self.camera_path = CameraPath::load(path)?;
+        self.path_playhead = None;
+        Ok(())
+    }
+
+    pub fn save_camera_path(&self, path: &Path) -> Result<(), String> {
+        self.camera_path.save(path)
+    }
+
+    /// Plays the path in the viewport from its start.
+    pub fn play_path(&mut self) {
+        if !self.camera_path.keys.is_empty() {
+            self.path_playhead = Some(0.0);
+        }
+    }
+
+    pub fn stop_path(&mut self) {
+        self.path_playhead = None;
+    }
+
+    /// Moves the playhead on by the last frame's time and the camera with
+    /// it, stopping at the end of the path.
+    pub(super) fn advance_path_playback(&mut self) {
+        let time = match self.path_playhead {
+            Some(time) => time,
+            None => return,
+        };
+        if let Some(pose) = self.camera_path.pose_at(time) {
+            self.look_from_pose(&pose);
+        }
+        self.path_playhead = if time < self.camera_path.duration() {
+            Some(time + self.frame_time as f32)
+        } else {
+            None
+        };
+    }
+
+    /// Renders every frame of the path through Po at its frame rate, with
+    /// the keyed focus and aperture, writing `<result>.<frame>.exr` files
+    /// into `dir`. Blocks until done.
+    pub fn render_camera_path(&mut self, dir: &Path) -> Result<(), String> {
+        if self.camera_path.keys.is_empty() {
+            return Err("the camera path has no keys".to_owned());
+        }
+        let scene = self
+            .scene
+            .as_ref()
+            .ok_or_else(|| "rendering a camera path needs an open scene".to_owned())?;
+        let frame_count = self.camera_path.frame_count();
+        let aspect_ratio =
+            self.render_settings.width as f32 / self.render_settings.height.max(1) as f32;
+        for frame in 0..frame_count {
+            let pose = self
+                .camera_path
+                .pose_at(frame as f32 / self.camera_path.fps)
+                .ok_or_else(|| "the camera path has no keys".to_owned())?;
+            let mut camera = pose.camera(aspect_ratio);
+            camera.near = self.camera.near;
+            camera.far = self.camera.far;
+            let results = self
+                .po
+                .render(&self.render_settings, scene, &self.skymap_view, &camera);
+            for result in results {
+                let path = dir.join(format!("{}.{:04}.exr", result.name, frame));
+                result.write_exr(&path)?;
+            }
+            log::info!("rendered frame {} of {}", frame + 1, frame_count);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, x: f32, yaw: f32) -> Keyframe {
+        Keyframe {
+            time,
+            pose: CameraPose {
+                location: [x, 0.0, 0.0],
+                yaw,
+                pitch: 0.0,
+                fov: 1.0,
+                lens: Default::default(),
+                projection: Default::default(),
+            },
+        }
+    }
+
+    fn path(interpolation: Interpolation, keys: Vec<Keyframe>) -> CameraPath {
+        CameraPath {
+            interpolation,
+            keys,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_the_keys() {
+        let path = path(
+            Interpolation::CatmullRom,
+            vec![key(0.0, 0.0, 0.0), key(1.0, 4.0, 0.0), key(3.0, 2.0, 0.0)],
+        );
+        for key in &path.keys {
+            assert!(
+                (path.pose_at(key.time).unwrap().location[0] - key.pose.location[0]).abs() < 1e-5
+            );
+        }
+        let x = path.pose_at(0.5).unwrap().location[0];
+        assert!(x > 0.0 && x < 4.0);
+        assert_eq!(path.pose_at(10.0).unwrap().location[0], 2.0);
+    }
+
+    #[test]
+    fn bezier_passes_through_the_ends_only() {
+        let path = path(
+            Interpolation::Bezier,
+            vec![key(0.0, 0.0, 0.0), key(1.0, 4.0, 0.0), key(2.0, 0.0, 0.0)],
+        );
+        assert_eq!(path.pose_at(0.0).unwrap().location[0], 0.0);
+        assert_eq!(path.pose_at(1.0).unwrap().location[0], 2.0);
+        assert_eq!(path.pose_at(2.0).unwrap().location[0], 0.0);
+    }
+
+    #[test]
+    fn yaw_turns_the_short_way() {
+        let path = path(
+            Interpolation::CatmullRom,
+            vec![key(0.0, 0.0, 3.0), key(1.0, 0.0, -3.0)],
+        );
+        let yaw = path.pose_at(0.5).unwrap().yaw;
+        assert!((yaw - std::f32::consts::PI).abs() < 0.01, "{}", yaw);
+    }
+
+    #[test]
+    fn easing_keeps_the_ends() {
+        let mut path = path(
+            Interpolation::CatmullRom,
+            vec![key(0.0, 0.0, 0.0), key(2.0, 2.0, 0.0)],
+        );
+        path.ease = true;
+        assert_eq!(path.pose_at(0.0).unwrap().location[0], 0.0);
+        assert!((path.pose_at(1.0).unwrap().location[0] - 1.0).abs() < 1e-5);
+        assert!(path.pose_at(0.2).unwrap().location[0] < 0.2);
+        assert_eq!(path.pose_at(2.0).unwrap().location[0], 2.0);
+    }
+
+    #[test]
+    fn frames_cover_the_last_key() {
+        let mut path = path(
+            Interpolation::CatmullRom,
+            vec![key(0.0, 0.0, 0.0), key(0.7, 1.0, 0.0), key(0.2, 2.0, 0.0)],
+        );
+        path.sort();
+        assert_eq!(path.keys[1].time, 0.2);
+        path.retime(2.0);
+        assert_eq!(path.keys[2].time, 2.0);
+        path.fps = 24.0;
+        assert_eq!(path.frame_count(), 49);
+        assert_eq!(CameraPath::default().frame_count(), 0);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let path = path(Interpolation::Bezier, vec![key(0.0, 1.0, 0.5)]);
+        let text = serde_json::to_string(&path).unwrap();
+        assert!(text.contains("\"interpolation\":\"bezier\""));
+        assert_eq!(serde_json::from_str::<CameraPath>(&text).unwrap(), path);
+    }
+}
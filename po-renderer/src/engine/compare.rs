@@ -0,0 +1,380 @@
+//! Error metrics for comparing renders against each other: PSNR, SSIM,
+//! relative MSE and a FLIP-style perceptual error map. Used by
+//! `po-renderer compare` and the golden image tests.
+
+use std::path::Path;
+
+use super::po::RenderResult;
+
+pub fn read_exr(path: &Path) -> Result<RenderResult, String> {
+    use exr::prelude::*;
+
+    let image = read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| {
+            (
+                resolution.width(),
+                vec![0.0; resolution.width() * resolution.height() * 3],
+            )
+        },
+        |(width, pixels), position, (r, g, b, _): (f32, f32, f32, f32)| {
+            let i = (position.y() * *width + position.x()) * 3;
+            pixels[i] = r;
+            pixels[i + 1] = g;
+            pixels[i + 2] = b;
+        },
+    )
+    .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let size = image.layer_data.size;
+    let (_, pixels) = image.layer_data.channel_data.pixels;
+    Ok(RenderResult {
+        name: path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        width: size.width() as u32,
+        height: size.height() as u32,
+        channels: 3,
+        pixels,
+    })
+}
+
+/// Loads an EXR, or any format the `image` crate reads, as linear RGB.
+pub fn read_image(path: &Path) -> Result<RenderResult, String> {
+    let is_exr = path
+        .extension()
+        .map_or(false, |e| e.eq_ignore_ascii_case("exr"));
+    if is_exr {
+        return read_exr(path);
+    }
+    let img = image::open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .into_rgb8();
+    Ok(RenderResult {
+        name: path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        width: img.width(),
+        height: img.height(),
+        channels: 3,
+        pixels: img.as_raw().iter().map(|v| srgb_to_linear(*v)).collect(),
+    })
+}
+
+fn srgb_to_linear(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn rgb(result: &RenderResult) -> Vec<[f32; 3]> {
+    result
+        .pixels
+        .chunks_exact(result.channels)
+        .map(|p| {
+            match p.len() {
+                1 | 2 => [p[0]; 3],
+                _ => [p[0], p[1], p[2]],
+            }
+        })
+        .collect()
+}
+
+/// Rec. 709 luminance, or the values themselves for single channel results.
+pub fn luminance(result: &RenderResult) -> Vec<f32> {
+    match result.channels {
+        1 => result.pixels.clone(),
+        c => {
+            result
+                .pixels
+                .chunks_exact(c)
+                .map(|p| 0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2])
+                .collect()
+        }
+    }
+}
+
+/// Mean structural similarity over 8x8 windows with a stride of 4. `range`
+/// is the dynamic range of the values, 1 for display referred images.
+pub fn ssim(a: &[f32], b: &[f32], width: usize, height: usize, range: f32) -> f32 {
+    const WINDOW: usize = 8;
+    const STRIDE: usize = 4;
+    let c1 = (0.01 * range) * (0.01 * range);
+    let c2 = (0.03 * range) * (0.03 * range);
+    let window = WINDOW.min(width).min(height);
+    let n = (window * window) as f32;
+    let mut total = 0.0;
+    let mut count = 0;
+    let mut y = 0;
+    while y + window <= height {
+        let mut x = 0;
+        while x + window <= width {
+            let (mut mean_a, mut mean_b) = (0.0, 0.0);
+            for j in y..y + window {
+                for i in x..x + window {
+                    mean_a += a[j * width + i];
+                    mean_b += b[j * width + i];
+                }
+            }
+            mean_a /= n;
+            mean_b /= n;
+            let (mut var_a, mut var_b, mut covar) = (0.0, 0.0, 0.0);
+            for j in y..y + window {
+                for i in x..x + window {
+                    let da = a[j * width + i] - mean_a;
+                    let db = b[j * width + i] - mean_b;
+                    var_a += da * da;
+                    var_b += db * db;
+                    covar += da * db;
+                }
+            }
+            var_a /= n - 1.0;
+            var_b /= n - 1.0;
+            covar /= n - 1.0;
+            total += ((2.0 * mean_a * mean_b + c1) * (2.0 * covar + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+            count += 1;
+            x += STRIDE;
+        }
+        y += STRIDE;
+    }
+    if count == 0 {
+        return 1.0;
+    }
+    total / count as f32
+}
+
+/// Black, red, yellow, white ramp for error maps; `value` is clamped to [0, 1].
+pub fn false_colour(value: f32) -> [u8; 3] {
+    let v = value.max(0.0).min(1.0) * 3.0;
+    let r = v.min(1.0);
+    let g = (v - 1.0).max(0.0).min(1.0);
+    let b = (v - 2.0).max(0.0).min(1.0);
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
+
+/// What [`psnr`] reports for identical images instead of infinity, beyond
+/// anything a float render that differs at all reaches.
+pub const MAX_PSNR: f32 = 100.0;
+
+/// Peak signal to noise ratio in dB over all colour channels, with the
+/// brightest reference value as the peak, at most [`MAX_PSNR`].
+pub fn psnr(test: &RenderResult, reference: &RenderResult) -> f32 {
+    let (a, b) = (rgb(test), rgb(reference));
+    let peak = b.iter().flatten().cloned().fold(0.0f32, f32::max).max(1e-6);
+    let mse = a
+        .iter()
+        .flatten()
+        .zip(b.iter().flatten())
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum::<f32>()
+        / (a.len() * 3) as f32;
+    if mse == 0.0 {
+        return MAX_PSNR;
+    }
+    (10.0 * (peak * peak / mse).log10()).min(MAX_PSNR)
+}
+
+/// Mean of `(test - reference)^2 / (reference^2 + 0.01)`, the usual
+/// convergence metric for HDR renders since it does not let bright pixels
+/// dominate.
+pub fn rel_mse(test: &RenderResult, reference: &RenderResult) -> f32 {
+    let (a, b) = (rgb(test), rgb(reference));
+    a.iter()
+        .flatten()
+        .zip(b.iter().flatten())
+        .map(|(a, b)| (a - b) * (a - b) / (b * b + 0.01))
+        .sum::<f32>()
+        / (a.len() * 3) as f32
+}
+
+/// Pixels per degree of visual angle for a 0.7m viewing distance on a 4K
+/// 0.7m wide monitor, the FLIP default.
+const PIXELS_PER_DEGREE: f32 = 67.0;
+
+/// Reinhard tone mapping then linear sRGB to CIE L*a*b*.
+fn to_lab(c: [f32; 3]) -> [f32; 3] {
+    let c = [
+        c[0] / (1.0 + c[0]),
+        c[1] / (1.0 + c[1]),
+        c[2] / (1.0 + c[2]),
+    ];
+    let x = (0.4124 * c[0] + 0.3576 * c[1] + 0.1805 * c[2]) / 0.9505;
+    let y = 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
+    let z = (0.0193 * c[0] + 0.1192 * c[1] + 0.9505 * c[2]) / 1.089;
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.max(0.0).cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn gaussian_blur(channel: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    let blur = |src: &[f32], dx: isize, dy: isize| -> Vec<f32> {
+        let mut dst = vec![0.0; src.len()];
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let mut acc = 0.0;
+                for (k, w) in kernel.iter().enumerate() {
+                    let o = k as isize - radius;
+                    let sx = (x + o * dx).max(0).min(width as isize - 1);
+                    let sy = (y + o * dy).max(0).min(height as isize - 1);
+                    acc += w * src[(sy * width as isize + sx) as usize];
+                }
+                dst[(y * width as isize + x) as usize] = acc / sum;
+            }
+        }
+        dst
+    };
+    blur(&blur(channel, 1, 0), 0, 1)
+}
+
+/// Magnitude of the Sobel gradient, the feature detector of the error map.
+fn edges(channel: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let at = |x: isize, y: isize| {
+        let x = x.max(0).min(width as isize - 1) as usize;
+        let y = y.max(0).min(height as isize - 1) as usize;
+        channel[y * width + x]
+    };
+    let mut out = vec![0.0; channel.len()];
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2.0 * at(x - 1, y)
+                - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2.0 * at(x, y - 1)
+                - at(x + 1, y - 1);
+            out[y as usize * width + x as usize] = (gx * gx + gy * gy).sqrt() / 4.0;
+        }
+    }
+    out
+}
+
+/// Per pixel perceptual error in [0, 1], modelled on NVIDIA's FLIP: the
+/// colour difference of the images after a blur standing in for the
+/// contrast sensitivity of the eye, amplified where edges differ.
+pub fn flip_error_map(test: &RenderResult, reference: &RenderResult) -> Vec<f32> {
+    let (width, height) = (test.width as usize, test.height as usize);
+    let sigma = 0.5 * PIXELS_PER_DEGREE / 60.0;
+    let filtered = |result: &RenderResult| -> [Vec<f32>; 3] {
+        let lab: Vec<[f32; 3]> = rgb(result).into_iter().map(to_lab).collect();
+        let channel = |c: usize| {
+            let values: Vec<f32> = lab.iter().map(|p| p[c]).collect();
+            gaussian_blur(&values, width, height, sigma)
+        };
+        [channel(0), channel(1), channel(2)]
+    };
+    let (a, b) = (filtered(test), filtered(reference));
+    let edges_a = edges(&a[0], width, height);
+    let edges_b = edges(&b[0], width, height);
+    (0..width * height)
+        .map(|i| {
+            // HyAB distance, normalised by the largest L*a*b* difference
+            let dl = (a[0][i] - b[0][i]).abs();
+            let dab = ((a[1][i] - b[1][i]).powi(2) + (a[2][i] - b[2][i]).powi(2)).sqrt();
+            let colour = ((dl + dab) / 200.0).min(1.0).powf(0.7);
+            let feature = ((edges_a[i] - edges_b[i]).abs() / 100.0).min(1.0).sqrt();
+            colour.powf(1.0 - feature)
+        })
+        .collect()
+}
+
+pub struct Comparison {
+    pub width: u32,
+    pub height: u32,
+    pub psnr: f32,
+    pub ssim: f32,
+    pub rel_mse: f32,
+    /// Mean of `error_map`.
+    pub flip: f32,
+    pub error_map: Vec<f32>,
+}
+
+impl Comparison {
+    /// Writes the error map as a false colour image.
+    pub fn write_error_map(&self, path: &Path) -> Result<(), String> {
+        image::RgbImage::from_fn(self.width, self.height, |x, y| {
+            image::Rgb(false_colour(self.error_map[(y * self.width + x) as usize]))
+        })
+        .save(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+pub fn compare(test: &RenderResult, reference: &RenderResult) -> Result<Comparison, String> {
+    if (test.width, test.height) != (reference.width, reference.height) {
+        return Err(format!(
+            "images differ in size: {}x{} and {}x{}",
+            test.width, test.height, reference.width, reference.height
+        ));
+    }
+    let (a, b) = (luminance(test), luminance(reference));
+    let range = b.iter().cloned().fold(0.0f32, f32::max).max(1e-6);
+    let error_map = flip_error_map(test, reference);
+    Ok(Comparison {
+        width: test.width,
+        height: test.height,
+        psnr: psnr(test, reference),
+        ssim: ssim(&a, &b, test.width as usize, test.height as usize, range),
+        rel_mse: rel_mse(test, reference),
+        flip: error_map.iter().sum::<f32>() / error_map.len() as f32,
+        error_map,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, value: f32) -> RenderResult {
+        RenderResult {
+            name: "test".to_owned(),
+            width,
+            height,
+            channels: 3,
+            pixels: vec![value; (width * height * 3) as usize],
+        }
+    }
+
+    #[test]
+    fn identical_images_are_capped() {
+        let reference = image(16, 16, 0.5);
+        let comparison = compare(&reference, &reference).unwrap();
+        assert_eq!(comparison.psnr, MAX_PSNR);
+        assert!((comparison.ssim - 1.0).abs() < 1e-6);
+        assert_eq!(comparison.rel_mse, 0.0);
+        assert_eq!(comparison.flip, 0.0);
+    }
+
+    #[test]
+    fn psnr_of_a_known_mse() {
+        // peak 1 and an MSE of 0.01
+        let mut reference = image(4, 4, 0.5);
+        reference.pixels[0] = 1.0;
+        let mut test = image(4, 4, 0.4);
+        test.pixels[0] = 0.9;
+        assert!((psnr(&test, &reference) - 20.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rejects_mismatched_sizes() {
+        let error = compare(&image(4, 4, 0.5), &image(4, 3, 0.5)).err().unwrap();
+        assert_eq!(error, "images differ in size: 4x4 and 4x3");
+    }
+}
@@ -0,0 +1,421 @@
+//! Startup configuration. Layers are applied in order, later ones win:
+//!
+//! 1. built-in defaults
+//! 2. the user config, `<config dir>/po-renderer/config.toml`
+//! 3. the project config, `po.toml` in the working directory
+//! 4. environment variables, see [`ENV_VARS`], also read from `.env`
+//! 5. `--set key=value` command line flags
+//!
+//! Every layer is checked against the schema when it is applied, so errors
+//! name the file, variable or flag that introduced them.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::frame::{self, PresentMode};
+
+pub const PROJECT_CONFIG: &str = "po.toml";
+
+/// Scene diameter `move_speed` is given for.
+pub const REFERENCE_SIZE: f32 = 10.0;
+
+/// Environment variables and the keys they set, later entries win.
+pub const ENV_VARS: &[(&str, &str)] = &[
+    // names used by older .env files
+    ("DEFAULT_SCENE", "scene"),
+    ("DEFAULT_SKYMAP", "skymap"),
+    ("PO_SCENE", "scene"),
+    ("PO_SKYMAP", "skymap"),
+    ("PO_SESSION", "session"),
+    ("PO_RESTORE_SESSION", "restore_session"),
+    ("PO_LOG", "log_filter"),
+    ("PO_ADAPTER", "adapter"),
+    ("PO_PRESENT_MODE", "present_mode"),
+    ("PO_FRAMES_IN_FLIGHT", "frames_in_flight"),
+    ("PO_MOVE_SPEED", "move_speed"),
+    ("PO_WINDOW_WIDTH", "window.width"),
+    ("PO_WINDOW_HEIGHT", "window.height"),
+];
+
+/// Keys holding paths, resolved against the directory of the config file
+/// that sets them.
+const PATH_KEYS: &[&str] = &["scene", "skymap", "session"];
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// glTF scene opened at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scene: Option<PathBuf>,
+    /// Equirectangular sky image, a white sky without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skymap: Option<PathBuf>,
+    /// Session file written on exit, see [`super::session::default_path`]
+    /// when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<PathBuf>,
+    /// Reopen the session at startup. `scene` and `skymap` take precedence
+    /// over the ones it names.
+    pub restore_session: bool,
+    /// `tracing` filter directives.
+    pub log_filter: String,
+    /// Adapter index or part of its name, see [`super::adapter::Preference`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adapter: Option<String>,
+    pub present_mode: PresentMode,
+    pub frames_in_flight: usize,
+    /// Camera speed in units per second in a scene [`REFERENCE_SIZE`] units
+    /// across, scaled with the size of the loaded scene.
+    pub move_speed: f32,
+    pub window: WindowConfig,
+    /// Which layer set each key, for keys not left at their default.
+    #[serde(skip)]
+    origins: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            skymap: None,
+            session: None,
+            restore_session: true,
+            log_filter: "debug,gpu_allocator=info".to_owned(),
+            adapter: None,
+            present_mode: PresentMode::Fifo,
+            frames_in_flight: frame::MIN_FRAMES_IN_FLIGHT,
+            move_speed: 3.0,
+            window: WindowConfig::default(),
+            origins: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// The file, environment variable or flag at fault.
+    pub origin: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.origin, self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("po-renderer").join("config.toml"))
+}
+
+impl Config {
+    /// Applies every layer on top of the defaults. `overrides` are the
+    /// `key=value` arguments of `--set` flags.
+    pub fn load(overrides: &[String]) -> Result<Self, ConfigError> {
+        let mut layers = Vec::new();
+        let files = user_config_path()
+            .into_iter()
+            .chain(std::iter::once(PathBuf::from(PROJECT_CONFIG)));
+        for path in files.filter(|p| p.is_file()) {
+            layers.push((path.display().to_string(), read_file(&path)?));
+        }
+        for (var, key) in ENV_VARS {
+            match std::env::var(var) {
+                Ok(value) if !value.is_empty() => {
+                    layers.push((var.to_string(), override_table(var, key, &value)?));
+                }
+                _ => {}
+            }
+        }
+        for arg in overrides {
+            let origin = format!("--set {}", arg);
+            let (key, value) = arg.split_once('=').ok_or_else(|| {
+                ConfigError {
+                    origin: origin.clone(),
+                    message: "expected key=value".to_owned(),
+                }
+            })?;
+            layers.push((
+                origin.clone(),
+                override_table(&origin, key.trim(), value.trim())?,
+            ));
+        }
+        Self::from_layers(layers)
+    }
+
+    /// Merges `layers` onto the defaults in order.
+    pub fn from_layers(layers: Vec<(String, toml::value::Table)>) -> Result<Self, ConfigError> {
+        let mut merged = default_table();
+        let mut origins = BTreeMap::new();
+        let mut config = Self::default();
+        for (origin, layer) in layers {
+            merge(&mut merged, layer, &origin, "", &mut origins);
+            config = toml::Value::Table(merged.clone())
+                .try_into::<Self>()
+                .map_err(|e| {
+                    ConfigError {
+                        origin: origin.clone(),
+                        message: e.to_string(),
+                    }
+                })?;
+            config.validate().map_err(|message| {
+                ConfigError {
+                    origin: origin.clone(),
+                    message,
+                }
+            })?;
+        }
+        config.origins = origins;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let frames = frame::MIN_FRAMES_IN_FLIGHT..=frame::MAX_FRAMES_IN_FLIGHT;
+        if !frames.contains(&self.frames_in_flight) {
+            return Err(format!(
+                "frames_in_flight is {}, expected {} to {}",
+                self.frames_in_flight,
+                frames.start(),
+                frames.end()
+            ));
+        }
+        if self.window.width == 0 || self.window.height == 0 {
+            return Err(format!(
+                "window size {}x{} is empty",
+                self.window.width, self.window.height
+            ));
+        }
+        if self.move_speed.is_nan() || self.move_speed <= 0.0 {
+            return Err(format!("move_speed is {}, expected > 0", self.move_speed));
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log_filter) {
+            return Err(format!("log_filter: {}", e));
+        }
+        Ok(())
+    }
+
+    /// Where the value of the dotted `key` comes from.
+    pub fn origin(&self, key: &str) -> &str {
+        self.origins.get(key).map_or("default", |s| s.as_str())
+    }
+
+    /// Every set key with its value and origin, for display.
+    pub fn entries(&self) -> Vec<(String, String, &str)> {
+        let mut entries = Vec::new();
+        if let Ok(toml::Value::Table(table)) = toml::Value::try_from(self) {
+            flatten(&table, "", &mut entries);
+        }
+        entries
+            .into_iter()
+            .map(|(key, value)| {
+                let origin = self.origin(&key);
+                (key, value, origin)
+            })
+            .collect()
+    }
+}
+
+fn default_table() -> toml::value::Table {
+    match toml::Value::try_from(Config::default()) {
+        Ok(toml::Value::Table(table)) => table,
+        _ => unreachable!("Config serializes to a table"),
+    }
+}
+
+fn read_file(path: &Path) -> Result<toml::value::Table, ConfigError> {
+    let error = |message: String| {
+        ConfigError {
+            origin: path.display().to_string(),
+            message,
+        }
+    };
+    let text = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    let mut table: toml::value::Table = toml::from_str(&text).map_err(|e| error(e.to_string()))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for key in PATH_KEYS {
+        if let Some(toml::Value::String(p)) = table.get_mut(*key) {
+            *p = dir.join(&p).display().to_string();
+        }
+    }
+    Ok(table)
+}
+
+/// A one key table for an environment variable or flag, typed after the
+/// default value of `key` so `PO_ADAPTER=0` stays a string.
+fn override_table(origin: &str, key: &str, raw: &str) -> Result<toml::value::Table, ConfigError> {
+    let error = |message: String| {
+        ConfigError {
+            origin: origin.to_owned(),
+            message,
+        }
+    };
+    let mut default = Some(toml::Value::Table(default_table()));
+    for part in key.split('.') {
+        default = default.and_then(|v| v.get(part).cloned());
+    }
+    let value = match default {
+        Some(toml::Value::Integer(_)) => {
+            toml::Value::Integer(
+                raw.parse()
+                    .map_err(|_| error(format!("expected an integer, found `{}`", raw)))?,
+            )
+        }
+        Some(toml::Value::Float(_)) => {
+            toml::Value::Float(
+                raw.parse()
+                    .map_err(|_| error(format!("expected a number, found `{}`", raw)))?,
+            )
+        }
+        Some(toml::Value::Boolean(_)) => {
+            toml::Value::Boolean(
+                raw.parse()
+                    .map_err(|_| error(format!("expected true or false, found `{}`", raw)))?,
+            )
+        }
+        _ => toml::Value::String(raw.to_owned()),
+    };
+    let mut parts = key.rsplit('.');
+    let mut table = toml::value::Table::new();
+    table.insert(parts.next().unwrap().to_owned(), value);
+    for part in parts {
+        let mut parent = toml::value::Table::new();
+        parent.insert(part.to_owned(), toml::Value::Table(table));
+        table = parent;
+    }
+    Ok(table)
+}
+
+fn merge(
+    base: &mut toml::value::Table,
+    layer: toml::value::Table,
+    origin: &str,
+    prefix: &str,
+    origins: &mut BTreeMap<String, String>,
+) {
+    for (name, value) in layer {
+        let key = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match value {
+            toml::Value::Table(table) => {
+                let entry = base
+                    .entry(name)
+                    .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+                match entry {
+                    toml::Value::Table(base) => merge(base, table, origin, &key, origins),
+                    // a table where the schema wants a value, left for
+                    // deserializing to reject
+                    entry => {
+                        *entry = toml::Value::Table(table);
+                        origins.insert(key, origin.to_owned());
+                    }
+                }
+            }
+            value => {
+                base.insert(name, value);
+                origins.insert(key, origin.to_owned());
+            }
+        }
+    }
+}
+
+fn flatten(table: &toml::value::Table, prefix: &str, entries: &mut Vec<(String, String)>) {
+    for (name, value) in table {
+        let key = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match value {
+            toml::Value::Table(table) => flatten(table, &key, entries),
+            toml::Value::String(s) => entries.push((key, s.clone())),
+            value => entries.push((key, value.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(origin: &str, text: &str) -> (String, toml::value::Table) {
+        (origin.to_owned(), toml::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let config = Config::from_layers(vec![
+            layer(
+                "user",
+                "move_speed = 5.0\n[window]\nwidth = 1280\nheight = 720",
+            ),
+            layer(
+                "project",
+                "present_mode = \"mailbox\"\n[window]\nwidth = 1920",
+            ),
+            (
+                "PO_WINDOW_HEIGHT".to_owned(),
+                override_table("PO_WINDOW_HEIGHT", "window.height", "1080").unwrap(),
+            ),
+        ])
+        .unwrap();
+        assert_eq!(config.move_speed, 5.0);
+        assert_eq!(config.present_mode, PresentMode::Mailbox);
+        assert_eq!(
+            config.window,
+            WindowConfig {
+                width: 1920,
+                height: 1080
+            }
+        );
+        assert_eq!(config.origin("window.width"), "project");
+        assert_eq!(config.origin("window.height"), "PO_WINDOW_HEIGHT");
+        assert_eq!(config.origin("frames_in_flight"), "default");
+    }
+
+    #[test]
+    fn overrides_are_typed_by_the_schema() {
+        let table = override_table("PO_ADAPTER", "adapter", "0").unwrap();
+        let config = Config::from_layers(vec![("PO_ADAPTER".to_owned(), table)]).unwrap();
+        assert_eq!(config.adapter.as_deref(), Some("0"));
+
+        let e = override_table("PO_FRAMES_IN_FLIGHT", "frames_in_flight", "two").unwrap_err();
+        assert_eq!(e.origin, "PO_FRAMES_IN_FLIGHT");
+    }
+
+    #[test]
+    fn errors_name_the_layer() {
+        let e = Config::from_layers(vec![
+            layer("po.toml", "scene = \"a.gltf\""),
+            layer("user", "sene = 1"),
+        ])
+        .unwrap_err();
+        assert_eq!(e.origin, "user");
+        assert!(e.message.contains("sene"), "{}", e.message);
+
+        let e = Config::from_layers(vec![layer("po.toml", "frames_in_flight = 8")]).unwrap_err();
+        assert_eq!(e.origin, "po.toml");
+        assert!(e.message.contains("frames_in_flight"), "{}", e.message);
+    }
+}
@@ -0,0 +1,52 @@
+//! Commands typed into the console at the bottom of the viewport.
+
+const USAGE: &str = "commands: bookmark <name>, recall <name>, forget <name>, camera <name>, \
+                     frame, focus, calibration <file>, key, play, stop";
+
+impl super::Engine {
+    /// Runs one console line. Names may contain spaces.
+    pub fn run_command(&mut self, line: &str) -> Result<(), String> {
+        let mut parts = line.trim().splitn(2, ' ');
+        let command = parts.next().unwrap_or_default();
+        let argument = parts.next().map(str::trim).unwrap_or_default();
+        let name = || {
+            if argument.is_empty() {
+                Err(format!("{} needs a name", command))
+            } else {
+                Ok(argument)
+            }
+        };
+        match command {
+            "" => Ok(()),
+            "bookmark" => self.add_bookmark(name()?),
+            "recall" => self.recall_bookmark(name()?),
+            "forget" => self.remove_bookmark(name()?),
+            "camera" => {
+                self.view_through(name()?);
+                Ok(())
+            }
+            "frame" => {
+                self.frame_all();
+                Ok(())
+            }
+            "focus" => {
+                self.focus_selection();
+                Ok(())
+            }
+            "key" => {
+                self.add_path_key();
+                Ok(())
+            }
+            "play" => {
+                self.play_path();
+                Ok(())
+            }
+            "stop" => {
+                self.stop_path();
+                Ok(())
+            }
+            "calibration" => self.load_calibration(std::path::Path::new(name()?)),
+            _ => Err(format!("unknown command {}, {}", command, USAGE)),
+        }
+    }
+}
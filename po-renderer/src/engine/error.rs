@@ -0,0 +1,103 @@
+//! Failures of the scene and skymap loading paths. The viewer shows them as
+//! notifications and keeps what was loaded before.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    Unsupported {
+        path: PathBuf,
+        feature: String,
+    },
+    /// The file was read but its GPU resources cannot be created.
+    Upload {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl LoadError {
+    pub fn path(&self) -> &Path {
+        match self {
+            LoadError::Io { path, .. }
+            | LoadError::Parse { path, .. }
+            | LoadError::Unsupported { path, .. }
+            | LoadError::Upload { path, .. } => path,
+        }
+    }
+
+    pub(crate) fn from_gltf(path: &Path, e: gltf::Error) -> Self {
+        match e {
+            gltf::Error::Io(source) => {
+                LoadError::Io {
+                    path: path.to_owned(),
+                    source,
+                }
+            }
+            e => {
+                LoadError::Parse {
+                    path: path.to_owned(),
+                    message: e.to_string(),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn from_image(path: &Path, e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::IoError(source) => {
+                LoadError::Io {
+                    path: path.to_owned(),
+                    source,
+                }
+            }
+            image::ImageError::Unsupported(e) => {
+                LoadError::Unsupported {
+                    path: path.to_owned(),
+                    feature: e.to_string(),
+                }
+            }
+            e => {
+                LoadError::Parse {
+                    path: path.to_owned(),
+                    message: e.to_string(),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            LoadError::Parse { path, message } => {
+                write!(f, "{}: cannot parse: {}", path.display(), message)
+            }
+            LoadError::Unsupported { path, feature } => {
+                write!(f, "{}: unsupported {}", path.display(), feature)
+            }
+            LoadError::Upload { path, message } => {
+                write!(f, "{}: cannot upload: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Frames in flight. Every slot owns the command pool, synchronisation and UI
+//! buffers of one frame, so the CPU records the next frame while the GPU is
+//! still drawing the previous ones.
+
+pub const MIN_FRAMES_IN_FLIGHT: usize = 2;
+pub const MAX_FRAMES_IN_FLIGHT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresentMode {
+    /// Waits for vertical blank, always supported.
+    Fifo,
+    /// Waits for vertical blank but replaces queued images, so the GPU never
+    /// stalls.
+    Mailbox,
+    /// Presents at once and may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [PresentMode; 3] = [
+        PresentMode::Fifo,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PresentMode::Fifo => "FIFO",
+            PresentMode::Mailbox => "Mailbox",
+            PresentMode::Immediate => "Immediate",
+        }
+    }
+
+    /// This mode if the surface supports it, FIFO otherwise.
+    pub fn to_vk(self, supported: &[maligog::PresentModeKHR]) -> maligog::PresentModeKHR {
+        let mode = match self {
+            PresentMode::Fifo => maligog::PresentModeKHR::FIFO,
+            PresentMode::Mailbox => maligog::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => maligog::PresentModeKHR::IMMEDIATE,
+        };
+        if supported.contains(&mode) {
+            mode
+        } else {
+            log::warn!(
+                "the surface does not support {} present mode, using FIFO",
+                self.label()
+            );
+            maligog::PresentModeKHR::FIFO
+        }
+    }
+}
+
+impl Default for PresentMode {
+    fn default() -> Self {
+        PresentMode::Fifo
+    }
+}
+
+pub struct Frame {
+    command_pool: maligog::CommandPool,
+    /// Kept until the fence signals, it holds on to everything it references.
+    #[allow(dead_code)]
+    command_buffer: Option<maligog::CommandBuffer>,
+    in_flight: maligog::Fence,
+    pub image_available: maligog::BinarySemaphore,
+    pub render_finished: maligog::BinarySemaphore,
+    pub ui_pass: egui_maligog::UiPass,
+}
+
+impl Frame {
+    fn new(device: &maligog::Device) -> Self {
+        Self {
+            command_pool: device.create_command_pool(
+                Some("frame command pool"),
+                device.graphics_queue_family_index(),
+            ),
+            command_buffer: None,
+            in_flight: device.create_fence(Some("frame in flight"), true),
+            image_available: device.create_binary_semaphore(Some("image available")),
+            render_finished: device.create_binary_semaphore(Some("render finished")),
+            ui_pass: egui_maligog::UiPass::new(device),
+        }
+    }
+
+    pub fn command_buffer(&self) -> maligog::CommandBuffer {
+        self.command_pool
+            .allocate_command_buffer(Some("main cmd buf"))
+    }
+
+    /// Submits `cmd_buf` after the swapchain image is available and signals
+    /// `render_finished` for presentation.
+    pub fn submit(&mut self, device: &maligog::Device, cmd_buf: maligog::CommandBuffer) {
+        self.in_flight.reset();
+        device.graphics_queue().submit(
+            &[&cmd_buf],
+            &[(
+                &self.image_available,
+                maligog::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            )],
+            &[&self.render_finished],
+            Some(&self.in_flight),
+        );
+        self.command_buffer = Some(cmd_buf);
+    }
+}
+
+pub struct Frames {
+    frames: Vec<Frame>,
+    current: usize,
+}
+
+impl Frames {
+    pub fn new(device: &maligog::Device, count: usize) -> Self {
+        let count = count.clamp(MIN_FRAMES_IN_FLIGHT, MAX_FRAMES_IN_FLIGHT);
+        log::info!("{} frames in flight", count);
+        Self {
+            frames: (0..count).map(|_| Frame::new(device)).collect(),
+            current: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Moves to the next slot and waits until the GPU is done with its
+    /// previous submission. Returns the slot index.
+    pub fn begin(&mut self) -> usize {
+        self.current = (self.current + 1) % self.frames.len();
+        let frame = &mut self.frames[self.current];
+        frame.in_flight.wait();
+        frame.command_buffer = None;
+        frame.command_pool.reset();
+        self.current
+    }
+
+    pub fn current(&self) -> &Frame {
+        &self.frames[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Frame {
+        &mut self.frames[self.current]
+    }
+
+    /// Waits for every submitted frame, before touching resources all slots
+    /// share.
+    pub fn wait_idle(&self) {
+        for frame in &self.frames {
+            frame.in_flight.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_modes_fall_back_to_fifo() {
+        let supported = [
+            maligog::PresentModeKHR::FIFO,
+            maligog::PresentModeKHR::MAILBOX,
+        ];
+        assert_eq!(
+            PresentMode::Mailbox.to_vk(&supported),
+            maligog::PresentModeKHR::MAILBOX
+        );
+        assert_eq!(
+            PresentMode::Immediate.to_vk(&supported),
+            maligog::PresentModeKHR::FIFO
+        );
+    }
+}
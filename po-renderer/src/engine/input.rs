@@ -1,17 +1,27 @@
 use indexmap::IndexMap;
 
-use super::camera::Direction;
+use super::camera::{CameraMode, Direction};
+
+/// Wheel lines to scroll for halving the orbit distance.
+const LINES_PER_HALVING: f32 = 5.0;
 
 #[derive(Debug, Default)]
 pub struct Input {
     pub(super) command: String,
     pub(super) move_speed: f32,
     pub(super) in_control: bool,
+    /// Typed into the Camera window before adding a bookmark.
+    pub(super) bookmark_name: String,
+    /// Middle button held, pans in orbit mode.
+    pub(super) panning: bool,
     pub(super) pressed_keys: IndexMap<winit::event::VirtualKeyCode, bool>,
 }
 
 impl super::Engine {
     pub fn process_move(&mut self) {
+        if self.camera_mode != CameraMode::Fly {
+            return;
+        }
         for (key, _) in self
             .input
             .pressed_keys
@@ -59,10 +69,68 @@ impl super::Engine {
             }
         }
     }
+    pub fn process_mouse_motion(&mut self, delta: (f64, f64)) {
+        let (dx, dy) = (delta.0 as f32, delta.1 as f32);
+        match self.camera_mode {
+            CameraMode::Fly => {
+                if self.input.in_control {
+                    self.camera.process_mouse_movement(dx / 500.0, dy / 500.0);
+                }
+            }
+            CameraMode::Orbit => {
+                if self.input.in_control {
+                    self.orbit.rotate(&mut self.camera, dx / 500.0, dy / 500.0);
+                } else if self.input.panning {
+                    // the view is this many distances high at the pivot
+                    let extent = self.camera.view_height(self.orbit.distance) / self.orbit.distance;
+                    let scale = extent / self.height.max(1) as f32;
+                    self.orbit.pan(&mut self.camera, dx * scale, dy * scale);
+                }
+            }
+        }
+    }
+
+    pub fn process_scroll(&mut self, delta: &winit::event::MouseScrollDelta) {
+        if self.camera_mode != CameraMode::Orbit || self.ui_instance.context().wants_pointer_input()
+        {
+            return;
+        }
+        let lines = match delta {
+            winit::event::MouseScrollDelta::LineDelta(_, y) => *y,
+            winit::event::MouseScrollDelta::PixelDelta(p) => p.y as f32 / 50.0,
+        };
+        self.orbit
+            .dolly(&mut self.camera, 0.5f32.powf(lines / LINES_PER_HALVING));
+    }
+
+    /// Shortcuts fire once per press and not while typing into the UI.
+    fn process_shortcut(&mut self, keycode: winit::event::VirtualKeyCode) {
+        if self.ui_instance.context().wants_keyboard_input() {
+            return;
+        }
+        match keycode {
+            winit::event::VirtualKeyCode::F => self.focus_selection(),
+            winit::event::VirtualKeyCode::Home => self.frame_all(),
+            winit::event::VirtualKeyCode::Key1 => self.recall_bookmark_at(0),
+            winit::event::VirtualKeyCode::Key2 => self.recall_bookmark_at(1),
+            winit::event::VirtualKeyCode::Key3 => self.recall_bookmark_at(2),
+            winit::event::VirtualKeyCode::Key4 => self.recall_bookmark_at(3),
+            winit::event::VirtualKeyCode::Key5 => self.recall_bookmark_at(4),
+            winit::event::VirtualKeyCode::Key6 => self.recall_bookmark_at(5),
+            winit::event::VirtualKeyCode::Key7 => self.recall_bookmark_at(6),
+            winit::event::VirtualKeyCode::Key8 => self.recall_bookmark_at(7),
+            winit::event::VirtualKeyCode::Key9 => self.recall_bookmark_at(8),
+            _ => {}
+        }
+    }
+
     pub fn process_key(&mut self, keyboard_input: &winit::event::KeyboardInput) {
         if let Some(keycode) = keyboard_input.virtual_keycode {
             match keyboard_input.state {
                 winit::event::ElementState::Pressed => {
+                    if self.input.pressed_keys.get(&keycode) != Some(&true) {
+                        self.process_shortcut(keycode);
+                    }
                     self.input.pressed_keys.insert(keycode, true);
                 }
                 winit::event::ElementState::Released => {
@@ -0,0 +1,176 @@
+//! Custom integrators for the ray tracing pass: an external SPIR-V module or
+//! rust-gpu crate plus a manifest naming its entry points.
+//!
+//! ```toml
+//! name = "ambient occlusion"
+//! spirv = "ao.spv"          # or `crate = "../ao-integrator"`
+//! raygen = "main"
+//! miss = "miss"
+//! closest_hit = "closest_hit"
+//! ```
+//!
+//! Relative paths are resolved against the manifest. The module is bound to
+//! the same layout as the bundled `ray-tracing` shader:
+//!
+//! - set 0: 0 tlas, 1 index buffer, 2 vertex buffer, 3 geometry infos,
+//!   4 geometry info offsets, 5 instance transforms, 6 samplers[],
+//!   7 textures[], 8 material infos, 9 vertex colors, 10 texture coordinates
+//! - set 1: 0 rgba32f color image, 1 r32f ao image, 2 sky sampler,
+//!   4 uniform buffer of [`IntegratorOptions`] followed by the thin lens and
+//!   the projection of the camera
+//! - set 2: 0 skymap
+//! - push constants: inverse view and inverse projection matrices, visible
+//!   to raygen
+
+use std::path::{Path, PathBuf};
+
+use bytemuck::{Pod, Zeroable};
+
+use super::reflect::{self, ExecutionModel};
+use super::util::ShaderSource;
+
+/// Integrator parameters in the uniform buffer of every frame, so changing
+/// them never needs a new pipeline.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Zeroable, Pod, serde::Serialize, serde::Deserialize)]
+pub struct IntegratorOptions {
+    pub max_depth: u32,
+    pub sky_intensity: f32,
+    pub tmin: f32,
+    pub tmax: f32,
+}
+
+impl Default for IntegratorOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 10,
+            sky_intensity: 2.0,
+            tmin: 0.001,
+            tmax: 10000.0,
+        }
+    }
+}
+
+/// Shading variants compiled into the bundled ray tracing module as separate
+/// closest hit entry points. Each one gets its own cached pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShadingVariant {
+    PathTraced,
+    Normal,
+}
+
+impl ShadingVariant {
+    pub const ALL: [ShadingVariant; 2] = [ShadingVariant::PathTraced, ShadingVariant::Normal];
+
+    pub fn closest_hit(self) -> &'static str {
+        match self {
+            ShadingVariant::PathTraced => "closest_hit",
+            ShadingVariant::Normal => "closest_hit_normal",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ShadingVariant::PathTraced => "Path traced",
+            ShadingVariant::Normal => "Normal",
+        }
+    }
+}
+
+/// Also the key of the pipeline cache in the ray tracing pass.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntryPoints {
+    pub raygen: String,
+    pub miss: String,
+    pub closest_hit: String,
+}
+
+impl EntryPoints {
+    pub fn new(raygen: &str, miss: &str, closest_hit: &str) -> Self {
+        Self {
+            raygen: raygen.to_owned(),
+            miss: miss.to_owned(),
+            closest_hit: closest_hit.to_owned(),
+        }
+    }
+
+    /// Checks that `spirv` exports every entry point with the right stage.
+    pub fn check(&self, spirv: &[u8]) -> Result<(), String> {
+        let exported = reflect::reflect_entry_points(spirv).map_err(|e| e.to_string())?;
+        for (name, model) in [
+            (&self.raygen, ExecutionModel::RayGeneration),
+            (&self.miss, ExecutionModel::Miss),
+            (&self.closest_hit, ExecutionModel::ClosestHit),
+        ]
+        .iter()
+        {
+            if !exported
+                .iter()
+                .any(|e| &e.name == *name && e.model == *model)
+            {
+                return Err(format!(
+                    "module has no {:?} entry point named {:?}",
+                    model, name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_raygen() -> String {
+    "main".to_owned()
+}
+
+fn default_miss() -> String {
+    "miss".to_owned()
+}
+
+fn default_closest_hit() -> String {
+    "closest_hit".to_owned()
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct IntegratorManifest {
+    pub name: String,
+    pub spirv: Option<PathBuf>,
+    #[serde(rename = "crate")]
+    pub shader_crate: Option<PathBuf>,
+    #[serde(default = "default_raygen")]
+    pub raygen: String,
+    #[serde(default = "default_miss")]
+    pub miss: String,
+    #[serde(default = "default_closest_hit")]
+    pub closest_hit: String,
+}
+
+impl IntegratorManifest {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut manifest: Self =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        manifest.spirv = manifest.spirv.map(|p| dir.join(p));
+        manifest.shader_crate = manifest.shader_crate.map(|p| dir.join(p));
+        Ok(manifest)
+    }
+
+    pub fn source(&self) -> Result<ShaderSource, String> {
+        match (&self.spirv, &self.shader_crate) {
+            (Some(spirv), None) => Ok(ShaderSource::Spirv(spirv.clone())),
+            (None, Some(shader_crate)) => Ok(ShaderSource::Crate(shader_crate.clone())),
+            _ => {
+                Err(format!(
+                    "integrator {:?} must set exactly one of `spirv` and `crate`",
+                    self.name
+                ))
+            }
+        }
+    }
+
+    pub fn entry_points(&self) -> EntryPoints {
+        EntryPoints::new(&self.raygen, &self.miss, &self.closest_hit)
+    }
+}
@@ -0,0 +1,24 @@
+//! Conversions to the glam of the shader crates, which the host calls into
+//! for the camera and shading functions it shares with the GPU.
+
+use po_shader::glam as shader;
+
+pub fn to_shader_mat4(m: glam::Mat4) -> shader::Mat4 {
+    shader::Mat4::from_cols_array(&m.to_cols_array())
+}
+
+pub fn to_shader_vec2(v: glam::Vec2) -> shader::Vec2 {
+    shader::Vec2::new(v.x, v.y)
+}
+
+pub fn to_shader_vec3(v: glam::Vec3) -> shader::Vec3 {
+    shader::Vec3::new(v.x, v.y, v.z)
+}
+
+pub fn to_shader_vec4(v: glam::Vec4) -> shader::Vec4 {
+    shader::Vec4::new(v.x, v.y, v.z, v.w)
+}
+
+pub fn from_shader_vec3(v: shader::Vec3) -> glam::Vec3 {
+    glam::Vec3::new(v.x, v.y, v.z)
+}
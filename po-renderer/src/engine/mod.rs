@@ -1,69 +1,269 @@
+pub mod adapter;
+pub mod bookmark;
+pub mod bounds;
+pub mod calibration;
 mod camera;
-mod descriptor;
+pub mod camera_path;
+pub mod compare;
+pub mod config;
+mod console;
+pub mod error;
+pub mod frame;
 mod input;
-mod po;
-mod scene_pass;
+pub mod integrator;
+mod interop;
+pub mod pipeline_cache;
+pub mod po;
+mod reflect;
+pub mod render_target;
+pub mod scene_camera;
+pub mod scene_pass;
+pub mod session;
+pub mod shader_cache;
 mod ui;
 
 pub mod util;
 
-pub use descriptor::DescriptorHelper;
-
 use std::cell::RefCell;
-use std::env;
 use std::rc::Rc;
-use std::str::FromStr;
 
 use egui_winit_platform::PlatformDescriptor;
 
-use maligog::vk;
-
 use crate::engine::po::RenderSettings;
-use crate::{vec3, Vec3};
-pub use camera::{Camera, Direction};
+use crate::vec3;
+pub use camera::{Camera, CameraMode, Direction, FisheyeMapping, Lens, Orbit, Projection};
+pub use error::LoadError;
 
 use egui_maligog::egui;
 
+/// Required glTF extensions `maligog_gltf` implements.
+const SUPPORTED_GLTF_EXTENSIONS: &[&str] = &[];
+
+/// Loads a glTF scene onto `device`, named after the file.
+pub fn load_scene(
+    device: &maligog::Device,
+    path: &std::path::Path,
+) -> Result<maligog_gltf::Scene, LoadError> {
+    let document = read_scene(path)?;
+    upload_scene(device, path, &document)
+}
+
+/// Reads the document of a glTF scene and checks what `maligog_gltf` relies
+/// on, since it panics on bad input: the files of the buffers and images
+/// exist, every required extension is supported and every primitive is a
+/// triangle list. Only the JSON is parsed, `maligog_gltf` decodes the data.
+fn read_scene(path: &std::path::Path) -> Result<gltf::Document, LoadError> {
+    let gltf = gltf::Gltf::open(path).map_err(|e| LoadError::from_gltf(path, e))?;
+    let document = gltf.document;
+    for buffer in document.buffers() {
+        match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => check_scene_file(path, uri)?,
+            gltf::buffer::Source::Bin => {
+                if gltf.blob.is_none() {
+                    return Err(LoadError::Parse {
+                        path: path.to_owned(),
+                        message: "missing binary chunk".to_owned(),
+                    });
+                }
+            }
+        }
+    }
+    for image in document.images() {
+        if let gltf::image::Source::Uri { uri, .. } = image.source() {
+            check_scene_file(path, uri)?;
+        }
+    }
+    if let Some(extension) = document
+        .extensions_required()
+        .find(|e| !SUPPORTED_GLTF_EXTENSIONS.contains(e))
+    {
+        return Err(LoadError::Unsupported {
+            path: path.to_owned(),
+            feature: format!("required extension {}", extension),
+        });
+    }
+    let meshes = document.meshes();
+    if let Some(primitive) = meshes
+        .flat_map(|m| m.primitives())
+        .find(|p| p.mode() != gltf::mesh::Mode::Triangles)
+    {
+        return Err(LoadError::Unsupported {
+            path: path.to_owned(),
+            feature: format!("primitive mode {:?}, only triangles are", primitive.mode()),
+        });
+    }
+    Ok(document)
+}
+
+/// Fails with an io error of the scene at `path` when the file `uri` refers
+/// to, relative to the scene, is missing. Embedded data is not checked.
+fn check_scene_file(path: &std::path::Path, uri: &str) -> Result<(), LoadError> {
+    if uri.starts_with("data:") {
+        return Ok(());
+    }
+    let file = path
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new(""))
+        .join(uri);
+    std::fs::metadata(&file).map(|_| ()).map_err(|e| {
+        LoadError::Io {
+            path: path.to_owned(),
+            source: std::io::Error::new(e.kind(), format!("{}: {}", file.display(), e)),
+        }
+    })
+}
+
+/// Uploads a scene [`read_scene`] accepted as `document`.
+fn upload_scene(
+    device: &maligog::Device,
+    path: &std::path::Path,
+    document: &gltf::Document,
+) -> Result<maligog_gltf::Scene, LoadError> {
+    // Vulkan has no empty buffers, so the index and vertex buffers could not
+    // be created
+    if document.meshes().next().is_none() {
+        return Err(LoadError::Upload {
+            path: path.to_owned(),
+            message: "no meshes to build the acceleration structure from".to_owned(),
+        });
+    }
+    let name = path.file_stem().and_then(|s| s.to_str());
+    Ok(maligog_gltf::Scene::from_file(name, device, path))
+}
+
+/// The uploaded scene with the bounds and cameras of its document.
+fn load_scene_with_info(
+    device: &maligog::Device,
+    path: &std::path::Path,
+) -> Result<
+    (
+        maligog_gltf::Scene,
+        bounds::SceneBounds,
+        Vec<scene_camera::SceneCamera>,
+    ),
+    LoadError,
+> {
+    let document = read_scene(path)?;
+    Ok((
+        upload_scene(device, path, &document)?,
+        bounds::SceneBounds::from_document(&document),
+        scene_camera::SceneCamera::from_document(&document),
+    ))
+}
+
+/// Uploads an equirectangular sky image, or a white 1x1 sky without `path`.
+pub fn load_skymap(
+    device: &maligog::Device,
+    path: Option<&std::path::Path>,
+) -> Result<maligog::Image, LoadError> {
+    Ok(match path {
+        Some(path) => {
+            log::info!("loading skymap");
+            let img = image::open(path)
+                .map_err(|e| LoadError::from_image(path, e))?
+                .into_rgba8();
+            device.create_image_init(
+                Some("skymap"),
+                maligog::Format::R8G8B8A8_UNORM,
+                img.width(),
+                img.height(),
+                maligog::ImageUsageFlags::SAMPLED,
+                maligog::MemoryLocation::GpuOnly,
+                &img.as_raw(),
+            )
+        }
+        None => {
+            device.create_image_init(
+                Some("skymap"),
+                maligog::Format::R8G8B8A8_UNORM,
+                1,
+                1,
+                maligog::ImageUsageFlags::SAMPLED,
+                maligog::MemoryLocation::GpuOnly,
+                &[255, 255, 255, 255],
+            )
+        }
+    })
+}
+
+/// The interactive viewer: owns the device, the window swapchain, the
+/// scene passes, the offline renderer and the egui overlay.
 pub struct Engine {
     device: maligog::Device,
+    surface: maligog::Surface,
     swapchain: maligog::Swapchain,
+    /// Set when presenting reported a stale swapchain or the window changed
+    /// size; the swapchain is rebuilt before the next frame.
+    swapchain_outdated: bool,
+    present_mode: frame::PresentMode,
+    /// What the surface supports, [`frame::PresentMode::to_vk`] falls back
+    /// to FIFO for the others.
+    present_modes: Vec<maligog::PresentModeKHR>,
+    frames: frame::Frames,
     start_instant: std::time::Instant,
     last_frame_instant: std::time::Instant,
     frame_instant: std::time::Instant,
     frame_time: f64,
     camera: Camera,
-    ui_pass: egui_maligog::UiPass,
+    camera_mode: CameraMode,
+    /// Followed in orbit mode, re-seated in front of the camera when entering
+    /// it.
+    orbit: Orbit,
     ui_instance: egui_winit_platform::Platform,
     scale_factor: f64,
     width: u32,
     height: u32,
     paint_jobs: Vec<egui::ClippedMesh>,
     scene: Option<maligog_gltf::Scene>,
+    scene_bounds: bounds::SceneBounds,
+    /// Index into `scene_bounds.nodes`.
+    selection: Option<usize>,
+    scene_cameras: Vec<scene_camera::SceneCamera>,
+    bookmarks: bookmark::Bookmarks,
+    camera_path: camera_path::CameraPath,
+    /// Seconds into `camera_path` while it plays in the viewport.
+    path_playhead: Option<f32>,
     input: input::Input,
     scene_pass: Rc<RefCell<dyn scene_pass::ScenePass>>,
+    pass: render_target::Pass,
     wireframe: Rc<RefCell<scene_pass::Wireframe>>,
     ray_tracing: Rc<RefCell<scene_pass::RayTracing>>,
     skymap: maligog::Image,
     skymap_view: maligog::ImageView,
+    scene_path: Option<std::path::PathBuf>,
+    skymap_path: Option<std::path::PathBuf>,
+    session_path: Option<std::path::PathBuf>,
     po: po::Po,
+    render_settings: RenderSettings,
+    shading: integrator::ShadingVariant,
+    /// Shown until dismissed in the UI.
+    load_errors: Vec<LoadError>,
+    config: config::Config,
+    pipeline_cache: maligog::PipelineCache,
+    pipeline_cache_key: pipeline_cache::CacheKey,
 }
 
 impl Engine {
-    pub fn new(window: &winit::window::Window) -> Self {
+    /// Fails when no adapter supports ray tracing, see [`adapter::select`],
+    /// or when a bundled shader does not compile.
+    pub fn new(window: &winit::window::Window, config: &config::Config) -> Result<Self, String> {
         let entry = maligog::Entry::new().unwrap();
         let required_extensions = maligog::Surface::required_extensions();
         let instance = entry.create_instance(
             &[maligog::name::instance::Layer::LunargMonitor],
             &required_extensions,
         );
-        let device = instance
-            .enumerate_physical_device()
-            .into_iter()
-            .find(|p| p.device_type() == maligog::PhysicalDeviceType::DISCRETE_GPU)
-            .unwrap()
-            .create_device();
+        let physical_device = adapter::select_for_viewer(&instance, config)?;
+        let pipeline_cache_key = pipeline_cache::CacheKey::new(physical_device.properties());
         let surface = instance.create_surface(window);
-        let swapchain = device.create_swapchain(surface, maligog::PresentModeKHR::FIFO);
+        let present_modes = physical_device.surface_present_modes(&surface);
+        let device = physical_device.create_device();
+        let pipeline_cache =
+            device.create_pipeline_cache(&pipeline_cache::load(&pipeline_cache_key));
+        let present_mode = config.present_mode;
+        let swapchain =
+            device.create_swapchain(surface.clone(), present_mode.to_vk(&present_modes));
+        let frames = frame::Frames::new(&device, config.frames_in_flight);
 
         let start_instant = std::time::Instant::now();
         let frame_instant = start_instant;
@@ -79,12 +279,10 @@ impl Engine {
             std::f32::consts::FRAC_PI_3,
         );
 
-        let move_speed = 3.0;
-        let in_control = false;
+        let move_speed = config.move_speed;
 
         let scale_factor = window.scale_factor();
 
-        let ui_pass = egui_maligog::UiPass::new(&device);
         let ui_instance = egui_winit_platform::Platform::new(PlatformDescriptor {
             physical_width: width,
             physical_height: height,
@@ -93,85 +291,361 @@ impl Engine {
             style: egui::Style::default(),
         });
 
-        let wireframe = Rc::new(RefCell::new(scene_pass::Wireframe::new(&device)));
+        let wireframe = Rc::new(RefCell::new(scene_pass::Wireframe::new(
+            &device,
+            &pipeline_cache,
+        )?));
         let ray_tracing = Rc::new(RefCell::new(scene_pass::RayTracing::new(
-            &device, width, height,
-        )));
+            &device,
+            &pipeline_cache,
+            width,
+            height,
+            frames.len(),
+        )?));
         let scene_pass = ray_tracing.clone();
 
-        let scene = match env::var("DEFAULT_SCENE") {
-            Ok(p) => {
-                let p = std::path::PathBuf::from_str(&p).unwrap();
-                Some(maligog_gltf::Scene::from_file(
-                    Some(p.file_stem().unwrap().to_str().unwrap()),
-                    &device,
-                    &p,
-                ))
+        let session_path = config.session.clone().or_else(session::default_path);
+        let session = match &session_path {
+            Some(path) if config.restore_session && path.is_file() => {
+                session::Session::load(path)
+                    .map_err(|e| log::warn!("not restoring session: {}", e))
+                    .ok()
             }
-            Err(_) => None,
+            _ => None,
         };
-        let skymap = match env::var("DEFAULT_SKYMAP") {
-            Ok(p) => {
-                log::info!("loading skymap");
-                let p = std::path::PathBuf::from_str(&p).unwrap();
-                let img = image::open(&p).unwrap();
-                let img = img.into_rgba8();
-                device.create_image_init(
-                    Some("skymap"),
-                    maligog::Format::R8G8B8A8_UNORM,
-                    img.width(),
-                    img.height(),
-                    maligog::ImageUsageFlags::SAMPLED,
-                    maligog::MemoryLocation::GpuOnly,
-                    &img.as_raw(),
-                )
+        // the configuration names what to open, the session what was open
+        let mut scene_path = config
+            .scene
+            .clone()
+            .or_else(|| session.as_ref().and_then(|s| s.scene.clone()));
+        let mut skymap_path = config
+            .skymap
+            .clone()
+            .or_else(|| session.as_ref().and_then(|s| s.skymap.clone()));
+
+        let mut load_errors = Vec::new();
+        let (scene, scene_bounds, scene_cameras) = match &scene_path {
+            Some(p) => {
+                match load_scene_with_info(&device, p) {
+                    Ok((scene, bounds, cameras)) => (Some(scene), bounds, cameras),
+                    Err(e) => {
+                        log::error!("cannot load default scene: {}", e);
+                        load_errors.push(e);
+                        scene_path = None;
+                        Default::default()
+                    }
+                }
             }
-            Err(_) => {
-                device.create_image_init(
-                    Some("skymap"),
-                    maligog::Format::R8G8B8A8_UNORM,
-                    1,
-                    1,
-                    maligog::ImageUsageFlags::SAMPLED,
-                    maligog::MemoryLocation::GpuOnly,
-                    &[255, 255, 255, 255],
-                )
+            None => Default::default(),
+        };
+        let skymap = match &skymap_path {
+            Some(p) => {
+                load_skymap(&device, Some(p)).unwrap_or_else(|e| {
+                    log::error!("cannot load default skymap: {}", e);
+                    load_errors.push(e);
+                    skymap_path = None;
+                    load_skymap(&device, None).unwrap()
+                })
             }
+            None => load_skymap(&device, None).unwrap(),
         };
         let skymap_view = skymap.create_view();
 
-        let po = po::Po::new(&device);
+        let po = po::Po::new(&device, &pipeline_cache)?;
 
-        Self {
+        let mut engine = Self {
             device,
+            surface,
             swapchain,
+            swapchain_outdated: false,
+            present_mode,
+            present_modes,
+            frames,
             start_instant,
             last_frame_instant,
             frame_instant,
             frame_time,
+            orbit: Orbit::in_front_of(&camera, 10.0),
             camera,
-            ui_pass,
+            camera_mode: CameraMode::Fly,
             ui_instance,
             scale_factor,
             width,
             height,
             paint_jobs: vec![],
+            scene_bounds,
+            scene_cameras,
+            bookmarks: Default::default(),
+            camera_path: Default::default(),
+            path_playhead: None,
+            selection: None,
             scene,
             input: input::Input {
                 move_speed,
                 ..Default::default()
             },
             scene_pass: scene_pass,
+            pass: render_target::Pass::RayTracing,
             wireframe,
             ray_tracing,
             skymap,
             skymap_view,
+            scene_path,
+            skymap_path,
+            session_path,
             po,
+            render_settings: RenderSettings::default(),
+            shading: integrator::ShadingVariant::PathTraced,
+            load_errors,
+            config: config.clone(),
+            pipeline_cache,
+            pipeline_cache_key,
+        };
+        engine.reload_bookmarks();
+        engine.fit_to_scene();
+        match session {
+            Some(session) => engine.apply_session_view(&session),
+            None => engine.frame_all(),
+        }
+        Ok(engine)
+    }
+
+    pub fn set_pass(&mut self, pass: render_target::Pass) {
+        self.pass = pass;
+        self.scene_pass = match pass {
+            render_target::Pass::Wireframe => self.wireframe.clone(),
+            render_target::Pass::RayTracing => self.ray_tracing.clone(),
+        };
+    }
+
+    /// Switching modes keeps the view; entering orbit puts the pivot at the
+    /// previous orbit distance in front of the camera.
+    pub fn set_camera_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.camera_mode == CameraMode::Fly {
+            self.orbit = Orbit::in_front_of(&self.camera, self.orbit.distance);
+        }
+        self.camera_mode = mode;
+    }
+
+    /// Scales the clip planes, the ray interval and the move speed to the
+    /// scene, so assets in millimetres or kilometres navigate like ones in
+    /// metres.
+    fn fit_to_scene(&mut self) {
+        let bounds = self.scene_bounds.all();
+        if bounds.is_empty() {
+            return;
+        }
+        let radius = bounds.radius().max(f32::MIN_POSITIVE);
+        self.camera.fit(radius);
+        self.render_settings.integrator.tmin = self.camera.near;
+        self.render_settings.integrator.tmax = self.camera.far;
+        self.input.move_speed = self.config.move_speed * radius * 2.0 / config::REFERENCE_SIZE;
+        log::debug!(
+            "scene radius {}, move speed {}",
+            radius,
+            self.input.move_speed
+        );
+    }
+
+    /// Looks at `bounds` whole from the current direction with their centre
+    /// in focus, and orbits around them in orbit mode.
+    fn frame_bounds(&mut self, bounds: bounds::Bounds) {
+        if bounds.is_empty() {
+            return;
+        }
+        self.orbit
+            .frame(&mut self.camera, bounds.center(), bounds.radius());
+        self.camera.lens.focus_distance = self.orbit.distance;
+    }
+
+    /// Looks through a camera of the scene, keeping the window's aspect
+    /// ratio.
+    pub fn view_through(&mut self, name: &str) {
+        let aspect_ratio = self.width as f32 / self.height.max(1) as f32;
+        let camera = match scene_camera::SceneCamera::find(&self.scene_cameras, name) {
+            Some(scene_camera) => scene_camera.camera(aspect_ratio),
+            None => Err(format!("no camera {}", name)),
+        };
+        match camera {
+            Ok(camera) => self.look_from(camera),
+            Err(e) => log::error!("cannot view through camera: {}", e),
+        }
+    }
+
+    /// Replaces the camera, in orbit mode the pivot moves in front of it.
+    fn look_from(&mut self, camera: Camera) {
+        self.camera = camera;
+        if self.camera_mode == CameraMode::Orbit {
+            self.orbit = Orbit::in_front_of(&self.camera, self.orbit.distance);
+        }
+    }
+
+    /// Looks through the calibrated sensor described by the OpenCV file
+    /// `path`, the image stretches to the window when their aspect ratios
+    /// differ.
+    pub fn load_calibration(&mut self, path: &std::path::Path) -> Result<(), String> {
+        let intrinsics = calibration::Intrinsics::from_file(path)?;
+        self.camera.fov = intrinsics.fov();
+        self.camera.projection = Projection::Calibrated(intrinsics);
+        Ok(())
+    }
+
+    pub fn frame_all(&mut self) {
+        self.frame_bounds(self.scene_bounds.all());
+    }
+
+    pub fn focus_selection(&mut self) {
+        if let Some(node) = self.selection.and_then(|i| self.scene_bounds.nodes.get(i)) {
+            self.frame_bounds(node.bounds);
+        }
+    }
+
+    /// Focuses the lens on the surface under `ndc`, y up.
+    pub fn focus_at(&mut self, ndc: glam::Vec2) {
+        let scene = match &self.scene {
+            Some(scene) => scene,
+            None => return,
+        };
+        match self
+            .po
+            .pick(scene, &self.camera, self.render_settings.integrator, ndc)
+        {
+            Some(distance) => self.camera.lens.focus_distance = distance,
+            None => log::info!("nothing to focus on"),
+        }
+    }
+
+    /// Where the session is saved on exit.
+    pub fn session_path(&self) -> Option<&std::path::Path> {
+        self.session_path.as_deref()
+    }
+
+    /// Blocks until the GPU has finished every submitted frame.
+    pub fn wait_idle(&self) {
+        self.frames.wait_idle();
+    }
+
+    /// Writes the pipeline cache back to disk, called once on shutdown.
+    pub fn save_pipeline_cache(&self) {
+        if let Err(e) = pipeline_cache::save(&self.pipeline_cache_key, &self.pipeline_cache.data())
+        {
+            log::warn!("cannot save pipeline cache: {}", e);
+        }
+    }
+
+    /// Replaces the scene, or keeps the current one and reports why `path`
+    /// cannot be loaded.
+    pub fn open_scene(&mut self, path: &std::path::Path) {
+        log::info!("open {}", path.display());
+        match load_scene_with_info(&self.device, path) {
+            Ok((scene, scene_bounds, scene_cameras)) => {
+                self.frames.wait_idle();
+                self.scene = Some(scene);
+                self.scene_path = Some(path.to_owned());
+                self.scene_bounds = scene_bounds;
+                self.scene_cameras = scene_cameras;
+                self.reload_bookmarks();
+                self.selection = None;
+                self.fit_to_scene();
+                self.frame_all();
+            }
+            Err(e) => self.report_load_error(e),
+        }
+    }
+
+    pub fn open_skymap(&mut self, path: &std::path::Path) {
+        match load_skymap(&self.device, Some(path)) {
+            Ok(skymap) => {
+                self.skymap = skymap;
+                self.skymap_view = self.skymap.create_view();
+                self.skymap_path = Some(path.to_owned());
+            }
+            Err(e) => self.report_load_error(e),
+        }
+    }
+
+    fn report_load_error(&mut self, e: LoadError) {
+        log::error!("{}", e);
+        self.load_errors.push(e);
+    }
+
+    /// Points the ray tracing pass at the integrator described by `manifest`.
+    pub fn load_integrator(&mut self, manifest: &std::path::Path) -> Result<(), String> {
+        let manifest = integrator::IntegratorManifest::from_file(manifest)?;
+        self.ray_tracing
+            .borrow_mut()
+            .set_integrator(manifest.source()?, manifest.entry_points())?;
+        self.shading = integrator::ShadingVariant::PathTraced;
+        Ok(())
+    }
+
+    /// Switches back to the bundled integrator, or keeps the current one when
+    /// it cannot be built.
+    pub fn reset_integrator(&mut self) {
+        let result = self.ray_tracing.borrow_mut().set_integrator(
+            util::ShaderSource::Bundled("ray-tracing".to_owned()),
+            integrator::EntryPoints::new("main", "miss", "closest_hit"),
+        );
+        match result {
+            Ok(()) => self.shading = integrator::ShadingVariant::PathTraced,
+            Err(e) => log::error!("cannot reset integrator: {}", e),
+        }
+    }
+
+    /// Follows the window to a new physical size. A zero sized window is
+    /// minimised, nothing is rendered until it is restored.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        log::debug!("resizing to {}x{}", width, height);
+        self.width = width;
+        self.height = height;
+        self.swapchain_outdated = true;
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.frames.wait_idle();
+        self.camera.aspect_ratio = width as f32 / height as f32;
+        self.wireframe.borrow_mut().resize(width, height);
+        self.ray_tracing.borrow_mut().resize(width, height);
+    }
+
+    fn recreate_swapchain(&mut self) {
+        self.frames.wait_idle();
+        self.swapchain = self.device.create_swapchain(
+            self.surface.clone(),
+            self.present_mode.to_vk(&self.present_modes),
+        );
+        self.swapchain_outdated = false;
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: frame::PresentMode) {
+        if present_mode != self.present_mode {
+            log::info!("switching to {} present mode", present_mode.label());
+            self.present_mode = present_mode;
+            self.swapchain_outdated = true;
         }
     }
 
     pub fn update(&mut self, event: &winit::event::Event<()>) {
+        if let winit::event::Event::WindowEvent { event, .. } = event {
+            match event {
+                winit::event::WindowEvent::Resized(size) => {
+                    self.resize(size.width, size.height);
+                }
+                winit::event::WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    self.scale_factor = *scale_factor;
+                    self.resize(new_inner_size.width, new_inner_size.height);
+                }
+                _ => {}
+            }
+        }
         self.scene_pass.borrow_mut().update();
+        self.po.update();
         self.ui_instance.handle_event(event);
         self.ui_instance
             .update_time(self.start_instant.elapsed().as_secs_f64());
@@ -183,35 +657,51 @@ impl Engine {
                     log::info!("start rendering");
                     if let Some(scene) = self.scene.as_ref() {
                         let results = self.po.render(
-                            &RenderSettings::default(),
+                            &self.render_settings,
                             scene,
                             &self.skymap_view,
                             &self.camera,
                         );
                         for result in results {
-                            match result.image.format() {
-                                maligog::Format::R32G32B32A32_SFLOAT => {}
-                                _ => {}
+                            let path = format!("{}.exr", result.name);
+                            if let Err(e) = result.write_exr(std::path::Path::new(&path)) {
+                                log::error!("cannot write render result: {}", e);
                             }
                         }
                     }
                 }
+                ui::UiMessage::RenderPath(dir) => {
+                    log::info!("start rendering the camera path");
+                    if let Err(e) = self.render_camera_path(&dir) {
+                        log::error!("cannot render camera path: {}", e);
+                    }
+                }
+                ui::UiMessage::Screenshot(path) => {
+                    let target =
+                        render_target::RenderTarget::new(&self.device, self.width, self.height);
+                    self.render_to(&target);
+                    match target.save(&path) {
+                        Ok(()) => log::info!("saved screenshot to {}", path.display()),
+                        Err(e) => log::error!("cannot save screenshot: {}", e),
+                    }
+                }
+                ui::UiMessage::SetPresentMode(present_mode) => {
+                    self.set_present_mode(present_mode);
+                }
+                ui::UiMessage::SetShading(variant) => {
+                    match self.ray_tracing.borrow_mut().set_shading(variant) {
+                        Ok(()) => self.shading = variant,
+                        Err(e) => log::error!("cannot switch shading: {}", e),
+                    }
+                }
             }
         }
+        self.ray_tracing
+            .borrow_mut()
+            .set_options(self.render_settings.integrator);
         let (_, paint_commands) = self.ui_instance.end_frame();
         self.paint_jobs = self.ui_instance.context().tessellate(paint_commands);
 
-        self.ui_pass.update_buffers(
-            &self.paint_jobs,
-            &egui_maligog::ScreenDescriptor {
-                physical_width: self.width,
-                physical_height: self.height,
-                scale_factor: self.scale_factor as f32,
-            },
-        );
-        self.ui_pass
-            .update_texture(&self.ui_instance.context().texture());
-
         self.last_frame_instant = self.frame_instant;
         self.frame_instant = std::time::Instant::now();
         self.frame_time = self.last_frame_instant.elapsed().as_secs_f64();
@@ -228,6 +718,12 @@ impl Engine {
                                 self.input.in_control = false;
                             }
                         }
+                        if button.eq(&MouseButton::Middle) {
+                            self.input.panning = state.eq(&ElementState::Pressed);
+                        }
+                    }
+                    winit::event::WindowEvent::MouseWheel { delta, .. } => {
+                        self.process_scroll(delta);
                     }
                     _ => {}
                 }
@@ -235,12 +731,7 @@ impl Engine {
             winit::event::Event::DeviceEvent { device_id, event } => {
                 match event {
                     winit::event::DeviceEvent::MouseMotion { delta } => {
-                        if self.input.in_control {
-                            self.camera.process_mouse_movement(
-                                delta.0 as f32 / 500.0,
-                                delta.1 as f32 / 500.0,
-                            );
-                        }
+                        self.process_mouse_motion(*delta);
                     }
                     winit::event::DeviceEvent::Key(input) => {
                         self.process_key(input);
@@ -252,6 +743,7 @@ impl Engine {
         }
 
         self.process_move();
+        self.advance_path_playback();
 
         // log::info!(
         //     "{} {}",
@@ -260,56 +752,156 @@ impl Engine {
         // );
     }
 
+    /// Renders the current scene pass into `target` without the UI overlay.
+    pub fn render_to(&mut self, target: &render_target::RenderTarget) {
+        self.frames.wait_idle();
+        if let Some(scene) = &self.scene {
+            render_target::render_pass_to(
+                &self.device,
+                &mut *self.scene_pass.borrow_mut(),
+                scene,
+                &self.camera,
+                &self.skymap_view,
+                target,
+            );
+        }
+    }
+
     pub fn render(&mut self) {
-        if let Ok(index) = self.swapchain.acquire_next_image() {
-            let frame = self.swapchain.get_image(index);
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        if self.swapchain_outdated {
+            self.recreate_swapchain();
+        }
+        let frame_index = self.frames.begin();
+        let index = match self
+            .swapchain
+            .acquire_next_image_with_semaphore(&self.frames.current().image_available)
+        {
+            Ok(index) => index,
+            Err(e) => {
+                log::debug!("cannot acquire swapchain image: {:?}", e);
+                self.swapchain_outdated = true;
+                return;
+            }
+        };
+        let frame = self.swapchain.get_image(index);
+        let screen_descriptor = egui_maligog::ScreenDescriptor {
+            physical_width: self.width,
+            physical_height: self.height,
+            scale_factor: self.scale_factor as f32,
+        };
+        {
+            let ui_pass = &mut self.frames.current_mut().ui_pass;
+            ui_pass.update_buffers(&self.paint_jobs, &screen_descriptor);
+            ui_pass.update_texture(&self.ui_instance.context().texture());
+        }
+        self.scene_pass.borrow_mut().begin_frame(frame_index);
 
-            let mut cmd_buf = self.device.create_command_buffer(
-                Some("main cmd buf"),
-                self.device.graphics_queue_family_index(),
-            );
-            cmd_buf.encode(|rec| {
-                if let Some(scene) = &self.scene {
-                    self.scene_pass.borrow_mut().prepare_scene(scene);
-                    self.scene_pass.borrow_mut().execute(
-                        rec,
-                        &frame.create_view(),
-                        &self.camera,
-                        Some(maligog::ClearColorValue {
-                            float32: [1.0, 1.0, 1.0, 1.0],
-                        }),
-                        &self.skymap_view,
-                    );
-                    self.ui_pass.execute(
-                        rec,
-                        &frame,
-                        &self.paint_jobs,
-                        &egui_maligog::ScreenDescriptor {
-                            physical_width: self.width,
-                            physical_height: self.height,
-                            scale_factor: self.scale_factor as f32,
-                        },
-                        None,
-                    );
-                } else {
-                    self.ui_pass.execute(
-                        rec,
-                        &frame,
-                        &self.paint_jobs,
-                        &egui_maligog::ScreenDescriptor {
-                            physical_width: self.width,
-                            physical_height: self.height,
-                            scale_factor: self.scale_factor as f32,
-                        },
-                        Some(maligog::ClearColorValue {
-                            float32: [1.0, 1.0, 1.0, 1.0],
-                        }),
-                    );
-                }
-            });
-            self.device.graphics_queue().submit_blocking(&[cmd_buf]);
-            self.swapchain
-                .present(index, &[&self.swapchain.image_available_semaphore()]);
+        let mut cmd_buf = self.frames.current().command_buffer();
+        let ui_pass = &self.frames.current().ui_pass;
+        cmd_buf.encode(|rec| {
+            if let Some(scene) = &self.scene {
+                self.scene_pass.borrow_mut().prepare_scene(scene);
+                self.scene_pass.borrow_mut().execute(
+                    rec,
+                    &frame.create_view(),
+                    &self.camera,
+                    Some(maligog::ClearColorValue {
+                        float32: [1.0, 1.0, 1.0, 1.0],
+                    }),
+                    &self.skymap_view,
+                );
+                ui_pass.execute(rec, &frame, &self.paint_jobs, &screen_descriptor, None);
+            } else {
+                ui_pass.execute(
+                    rec,
+                    &frame,
+                    &self.paint_jobs,
+                    &screen_descriptor,
+                    Some(maligog::ClearColorValue {
+                        float32: [1.0, 1.0, 1.0, 1.0],
+                    }),
+                );
+            }
+        });
+        let current = self.frames.current_mut();
+        current.submit(&self.device, cmd_buf);
+        match self.swapchain.present(index, &[&current.render_finished]) {
+            Ok(false) => {}
+            // suboptimal, still presented but worth recreating
+            Ok(true) => self.swapchain_outdated = true,
+            Err(e) => {
+                log::debug!("cannot present swapchain image: {:?}", e);
+                self.swapchain_outdated = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `contents` to a file of this test run, so the tests don't
+    /// depend on the working directory.
+    fn write_temp(name: &str, contents: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("po-load-scene-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let path = std::env::temp_dir().join("po-load-scene-missing.gltf");
+        match read_scene(&path) {
+            Err(LoadError::Io { path: p, .. }) => assert_eq!(p, path),
+            other => panic!("expected an io error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn malformed_file_is_a_parse_error() {
+        let path = write_temp("malformed.gltf", "{ \"asset\": ");
+        assert!(matches!(read_scene(&path), Err(LoadError::Parse { .. })));
+    }
+
+    #[test]
+    fn missing_buffer_is_reported() {
+        let path = write_temp(
+            "missing-buffer.gltf",
+            r#"{
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": 12, "uri": "missing.bin" }]
+            }"#,
+        );
+        let error = read_scene(&path).map(|_| ()).unwrap_err();
+        assert!(matches!(error, LoadError::Io { .. }));
+        assert_eq!(error.path(), path);
+    }
+
+    #[test]
+    fn missing_image_is_reported() {
+        let path = write_temp(
+            "missing-image.gltf",
+            r#"{
+                "asset": { "version": "2.0" },
+                "images": [{ "uri": "missing.png" }]
+            }"#,
+        );
+        let error = read_scene(&path).map(|_| ()).unwrap_err();
+        assert!(matches!(error, LoadError::Io { .. }));
+        assert!(error.to_string().contains("missing.png"));
+    }
+
+    #[test]
+    fn reads_the_test_scenes() {
+        let scenes = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenes");
+        for name in &["quad.gltf", "cubes.gltf"] {
+            assert!(read_scene(&scenes.join(name)).is_ok(), "{}", name);
         }
     }
 }
@@ -0,0 +1,276 @@
+//! Vulkan pipeline cache persisted between runs.
+//!
+//! The cache blob is stored in `pipeline.cache` under the per-user cache
+//! directory (or `PO_PIPELINE_CACHE`), behind a small header recording the
+//! device it was produced on. A blob from another device or driver, a
+//! truncated file or one failing its checksum is discarded and the cache
+//! starts empty, so a bad file never costs more than one cold start.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use maligog::vk;
+
+use super::shader_cache::content_hash;
+
+/// Blobs larger than this are not written back; drivers keep appending to
+/// the cache and a stale one only slows down loading.
+pub const MAX_SIZE: usize = 64 * 1024 * 1024;
+
+const MAGIC: &[u8; 4] = b"POPC";
+const VERSION: u32 = 1;
+/// magic, version, vendor id, device id, driver version, uuid, length, hash
+const HEADER_SIZE: usize = 4 + 4 * 4 + 16 + 8 + 8;
+/// `VkPipelineCacheHeaderVersionOne`
+const VK_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+/// Identifies the device and driver a cache blob is valid for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheKey {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_version: u32,
+    pub uuid: [u8; vk::UUID_SIZE],
+}
+
+impl CacheKey {
+    pub fn new(properties: &vk::PhysicalDeviceProperties) -> Self {
+        Self {
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            driver_version: properties.driver_version,
+            uuid: properties.pipeline_cache_uuid,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CacheError {
+    Truncated,
+    BadMagic,
+    Version(u32),
+    OtherDevice,
+    Checksum,
+    VulkanHeader,
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::Truncated => write!(f, "file is truncated"),
+            CacheError::BadMagic => write!(f, "not a pipeline cache file"),
+            CacheError::Version(v) => write!(f, "unsupported version {}", v),
+            CacheError::OtherDevice => write!(f, "written by another device or driver"),
+            CacheError::Checksum => write!(f, "checksum mismatch"),
+            CacheError::VulkanHeader => write!(f, "invalid Vulkan cache header"),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+pub fn cache_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("PO_PIPELINE_CACHE") {
+        return Some(PathBuf::from(path));
+    }
+    dirs::cache_dir().map(|dir| dir.join("po-renderer").join("pipeline.cache"))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(word)
+}
+
+pub fn encode(key: &CacheKey, data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + data.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&key.vendor_id.to_le_bytes());
+    bytes.extend_from_slice(&key.device_id.to_le_bytes());
+    bytes.extend_from_slice(&key.driver_version.to_le_bytes());
+    bytes.extend_from_slice(&key.uuid);
+    bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&content_hash(data).to_le_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// Returns the Vulkan cache blob stored in `bytes` if it was written for `key`.
+pub fn decode<'a>(key: &CacheKey, bytes: &'a [u8]) -> Result<&'a [u8], CacheError> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(CacheError::Truncated);
+    }
+    if &bytes[0..4] != MAGIC {
+        return Err(CacheError::BadMagic);
+    }
+    let version = read_u32(bytes, 4);
+    if version != VERSION {
+        return Err(CacheError::Version(version));
+    }
+    let mut uuid = [0; vk::UUID_SIZE];
+    uuid.copy_from_slice(&bytes[20..36]);
+    let stored = CacheKey {
+        vendor_id: read_u32(bytes, 8),
+        device_id: read_u32(bytes, 12),
+        driver_version: read_u32(bytes, 16),
+        uuid,
+    };
+    if stored != *key {
+        return Err(CacheError::OtherDevice);
+    }
+    let len = read_u64(bytes, 36) as usize;
+    let data = &bytes[HEADER_SIZE..];
+    if data.len() != len {
+        return Err(CacheError::Truncated);
+    }
+    if content_hash(data) != read_u64(bytes, 44) {
+        return Err(CacheError::Checksum);
+    }
+    // the driver validates this too, but not every driver does it gracefully
+    if data.len() < VK_HEADER_SIZE
+        || read_u32(data, 0) as usize != VK_HEADER_SIZE
+        || read_u32(data, 4) != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        || read_u32(data, 8) != key.vendor_id
+        || read_u32(data, 12) != key.device_id
+        || data[16..VK_HEADER_SIZE] != key.uuid
+    {
+        return Err(CacheError::VulkanHeader);
+    }
+    Ok(data)
+}
+
+/// Loads the cache blob for `key`, or an empty one if there is no usable
+/// file. Unusable files are removed.
+pub fn load(key: &CacheKey) -> Vec<u8> {
+    let path = match cache_path() {
+        Some(path) => path,
+        None => return Vec::new(),
+    };
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::warn!("cannot read pipeline cache {}: {}", path.display(), e);
+            }
+            return Vec::new();
+        }
+    };
+    match decode(key, &bytes) {
+        Ok(data) => {
+            log::info!("loaded {} byte pipeline cache", data.len());
+            data.to_vec()
+        }
+        Err(e) => {
+            log::warn!("discarding pipeline cache {}: {}", path.display(), e);
+            let _ = std::fs::remove_file(&path);
+            Vec::new()
+        }
+    }
+}
+
+/// Writes `data` for `key`, replacing the previous file atomically so a
+/// crash mid-write leaves the old cache intact.
+pub fn save(key: &CacheKey, data: &[u8]) -> io::Result<()> {
+    let path = match cache_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if data.len() > MAX_SIZE {
+        log::warn!(
+            "pipeline cache is {} bytes, over the {} byte cap, dropping it",
+            data.len(),
+            MAX_SIZE
+        );
+        let _ = std::fs::remove_file(&path);
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, encode(key, data))?;
+    std::fs::rename(&tmp, &path)?;
+    log::info!("saved {} byte pipeline cache", data.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> CacheKey {
+        CacheKey {
+            vendor_id: 0x10de,
+            device_id: 0x2204,
+            driver_version: 470,
+            uuid: [7; vk::UUID_SIZE],
+        }
+    }
+
+    /// A blob as the driver returns it: its own header, then opaque data.
+    fn blob(key: &CacheKey) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(VK_HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(
+            &(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_le_bytes(),
+        );
+        data.extend_from_slice(&key.vendor_id.to_le_bytes());
+        data.extend_from_slice(&key.device_id.to_le_bytes());
+        data.extend_from_slice(&key.uuid);
+        data.extend_from_slice(b"pipelines");
+        data
+    }
+
+    #[test]
+    fn round_trips() {
+        let data = blob(&key());
+        let bytes = encode(&key(), &data);
+        assert_eq!(bytes.len(), HEADER_SIZE + data.len());
+        assert_eq!(decode(&key(), &bytes).unwrap(), &data[..]);
+    }
+
+    #[test]
+    fn rejects_other_devices() {
+        let bytes = encode(&key(), &blob(&key()));
+        let others = [
+            CacheKey {
+                vendor_id: 0x1002,
+                ..key()
+            },
+            CacheKey {
+                device_id: 0x2206,
+                ..key()
+            },
+            CacheKey {
+                uuid: [8; vk::UUID_SIZE],
+                ..key()
+            },
+        ];
+        for other in &others {
+            match decode(other, &bytes) {
+                Err(CacheError::OtherDevice) => {}
+                result => panic!("expected another device for {:?}, got {:?}", other, result),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        let mut bytes = encode(&key(), &blob(&key()));
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(decode(&key(), &bytes), Err(CacheError::Checksum)));
+        assert!(matches!(
+            decode(&key(), &bytes[..last]),
+            Err(CacheError::Truncated)
+        ));
+    }
+}
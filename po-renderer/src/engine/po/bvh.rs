@@ -0,0 +1,228 @@
+//! Bounding volume hierarchy over world space triangles, used by the CPU
+//! reference renderer in place of the acceleration structures.
+
+use std::cmp::Ordering;
+
+use glam::{Vec2, Vec3};
+
+const MAX_LEAF_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Triangle {
+    pub v0: Vec3,
+    pub v1: Vec3,
+    pub v2: Vec3,
+    /// Index of its shading inputs, which the reordering leaves alone.
+    pub surface: usize,
+}
+
+impl Triangle {
+    fn centroid(&self) -> Vec3 {
+        (self.v0 + self.v1 + self.v2) / 3.0
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::empty().grow(self.v0).grow(self.v1).grow(self.v2)
+    }
+
+    /// Möller-Trumbore. Returns the distance and the barycentrics of v1 and v2,
+    /// matching the hit attributes of the GPU pass.
+    fn intersect(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        tmin: f32,
+        tmax: f32,
+    ) -> Option<(f32, Vec2)> {
+        let e1 = self.v1 - self.v0;
+        let e2 = self.v2 - self.v0;
+        let p = direction.cross(e2);
+        let det = e1.dot(p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = origin - self.v0;
+        let u = s.dot(p) * inv_det;
+        if u < 0.0 || u > 1.0 {
+            return None;
+        }
+        let q = s.cross(e1);
+        let v = direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = e2.dot(q) * inv_det;
+        if t < tmin || t > tmax {
+            return None;
+        }
+        Some((t, Vec2::new(u, v)))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    fn empty() -> Self {
+        Self {
+            min: Vec3::splat(f32::INFINITY),
+            max: Vec3::splat(f32::NEG_INFINITY),
+        }
+    }
+
+    fn grow(self, point: Vec3) -> Self {
+        Self {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    fn union(self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    fn largest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    fn hit(&self, origin: Vec3, inv_direction: Vec3, tmin: f32, tmax: f32) -> bool {
+        let t0 = (self.min - origin) * inv_direction;
+        let t1 = (self.max - origin) * inv_direction;
+        let near = t0.min(t1).max_element().max(tmin);
+        let far = t0.max(t1).min_element().min(tmax);
+        near <= far
+    }
+}
+
+fn component(v: Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    /// First triangle of a leaf, or the right child of an interior node. The
+    /// left child always follows its parent.
+    start: u32,
+    /// Zero for interior nodes.
+    count: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    pub t: f32,
+    pub triangle: usize,
+    pub barycentrics: Vec2,
+}
+
+pub struct Bvh {
+    nodes: Vec<Node>,
+    triangles: Vec<Triangle>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy by median splits along the largest centroid
+    /// extent. Reorders `triangles`; `Hit::triangle` indexes `triangles()`.
+    pub fn new(triangles: Vec<Triangle>) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * triangles.len() / MAX_LEAF_SIZE + 1),
+            triangles,
+        };
+        if !bvh.triangles.is_empty() {
+            bvh.build(0, bvh.triangles.len());
+        }
+        bvh
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let index = self.nodes.len();
+        let bounds = self.triangles[start..end]
+            .iter()
+            .fold(Aabb::empty(), |b, t| b.union(&t.bounds()));
+        self.nodes.push(Node {
+            bounds,
+            start: start as u32,
+            count: (end - start) as u32,
+        });
+        if end - start <= MAX_LEAF_SIZE {
+            return index;
+        }
+        let centroids = self.triangles[start..end]
+            .iter()
+            .fold(Aabb::empty(), |b, t| b.grow(t.centroid()));
+        let axis = centroids.largest_axis();
+        if component(centroids.max, axis) <= component(centroids.min, axis) {
+            // every centroid coincides, no split can separate them
+            return index;
+        }
+        let mid = (start + end) / 2;
+        self.triangles[start..end].select_nth_unstable_by(mid - start, |a, b| {
+            component(a.centroid(), axis)
+                .partial_cmp(&component(b.centroid(), axis))
+                .unwrap_or(Ordering::Equal)
+        });
+        self.build(start, mid);
+        let right = self.build(mid, end);
+        self.nodes[index].start = right as u32;
+        self.nodes[index].count = 0;
+        index
+    }
+
+    /// Closest hit along the ray within `[tmin, tmax]`.
+    pub fn intersect(&self, origin: Vec3, direction: Vec3, tmin: f32, tmax: f32) -> Option<Hit> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inv_direction = Vec3::splat(1.0) / direction;
+        let mut tmax = tmax;
+        let mut closest = None;
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.hit(origin, inv_direction, tmin, tmax) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.start as usize);
+                stack.push(index + 1);
+                continue;
+            }
+            let start = node.start as usize;
+            let end = start + node.count as usize;
+            for (i, triangle) in self.triangles[start..end].iter().enumerate() {
+                if let Some((t, barycentrics)) = triangle.intersect(origin, direction, tmin, tmax) {
+                    tmax = t;
+                    closest = Some(Hit {
+                        t,
+                        triangle: start + i,
+                        barycentrics,
+                    });
+                }
+            }
+        }
+        closest
+    }
+}
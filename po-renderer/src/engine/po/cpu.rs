@@ -0,0 +1,424 @@
+//! CPU reference renderer. Runs the integrator of the `po` shader over a BVH
+//! on rayon threads, calling the same ray, rng and shading functions as its
+//! raygen, closest hit and miss shaders, so image output can be checked on
+//! machines without a ray tracing capable GPU.
+
+use std::path::Path;
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use gltf::texture::WrappingMode;
+use rayon::prelude::*;
+
+use super::bvh::{Bvh, Triangle};
+use super::{CameraInfo, RenderInfo, RenderResult, RenderSettings};
+use crate::engine::integrator::IntegratorOptions;
+use crate::engine::{interop, LoadError};
+
+/// What the closest hit shader reads from the geometry and material buffers
+/// for one triangle.
+struct Surface {
+    base_color_factor: Vec4,
+    /// Vertex colours, none for white.
+    colors: Option<[Vec4; 3]>,
+    /// Base colour texture and the texture coordinates it is sampled at.
+    texture: Option<(usize, [Vec2; 3])>,
+}
+
+impl Surface {
+    fn base_color(&self, barycentrics: Vec3, textures: &[Option<Texture>]) -> Vec3 {
+        let interpolate =
+            |v: [Vec4; 3]| v[0] * barycentrics.x + v[1] * barycentrics.y + v[2] * barycentrics.z;
+        let vertex_color = self.colors.map_or(Vec4::ONE, interpolate);
+        let texel = match self.texture {
+            Some((index, tex_coords)) => {
+                match &textures[index] {
+                    Some(texture) => {
+                        let tex_coord = tex_coords[0] * barycentrics.x
+                            + tex_coords[1] * barycentrics.y
+                            + tex_coords[2] * barycentrics.z;
+                        texture.sample(tex_coord)
+                    }
+                    None => Vec4::ONE,
+                }
+            }
+            None => Vec4::ONE,
+        };
+        interop::from_shader_vec3(po_shader::base_color(
+            interop::to_shader_vec4(self.base_color_factor),
+            interop::to_shader_vec4(vertex_color),
+            interop::to_shader_vec4(texel),
+        ))
+    }
+}
+
+/// A base colour texture decoded to linear RGBA, wrapping like its sampler.
+struct Texture {
+    width: u32,
+    height: u32,
+    texels: Vec<Vec4>,
+    wrap_s: WrappingMode,
+    wrap_t: WrappingMode,
+}
+
+impl Texture {
+    /// `None` for 16 bit and BGR images, which are left white.
+    fn new(image: &gltf::image::Data, sampler: &gltf::texture::Sampler) -> Option<Self> {
+        use gltf::image::Format;
+        let channels = match image.format {
+            Format::R8 => 1,
+            Format::R8G8 => 2,
+            Format::R8G8B8 => 3,
+            Format::R8G8B8A8 => 4,
+            _ => return None,
+        };
+        // missing channels read like Vulkan's, zero colour and opaque
+        let texels = image
+            .pixels
+            .chunks_exact(channels)
+            .map(|p| {
+                let color = |c: usize| p.get(c).map_or(0.0, |&v| srgb_to_linear(v));
+                let alpha = p.get(3).map_or(1.0, |&v| v as f32 / 255.0);
+                Vec4::new(color(0), color(1), color(2), alpha)
+            })
+            .collect();
+        Some(Self {
+            width: image.width,
+            height: image.height,
+            texels,
+            wrap_s: sampler.wrap_s(),
+            wrap_t: sampler.wrap_t(),
+        })
+    }
+
+    /// Bilinear lookup, texel centres at half steps.
+    fn sample(&self, uv: Vec2) -> Vec4 {
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = uv.y * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |x: f32, y: f32| {
+            let x = wrap(x as i64, self.width, self.wrap_s);
+            let y = wrap(y as i64, self.height, self.wrap_t);
+            self.texels[(y * self.width + x) as usize]
+        };
+        let top = texel(x0, y0).lerp(texel(x0 + 1.0, y0), fx);
+        let bottom = texel(x0, y0 + 1.0).lerp(texel(x0 + 1.0, y0 + 1.0), fx);
+        top.lerp(bottom, fy)
+    }
+}
+
+fn wrap(i: i64, size: u32, mode: WrappingMode) -> u32 {
+    let size = size as i64;
+    let i = match mode {
+        WrappingMode::ClampToEdge => i.clamp(0, size - 1),
+        WrappingMode::Repeat => i.rem_euclid(size),
+        WrappingMode::MirroredRepeat => {
+            let i = i.rem_euclid(2 * size);
+            if i < size {
+                i
+            } else {
+                2 * size - 1 - i
+            }
+        }
+    };
+    i as u32
+}
+
+/// Base colour textures are sRGB encoded, glTF 2.0 section 3.9.2.
+fn srgb_to_linear(v: u8) -> f32 {
+    let c = v as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub struct CpuScene {
+    bvh: Bvh,
+    /// Indexed by `Triangle::surface`.
+    surfaces: Vec<Surface>,
+    /// Indexed like the glTF textures.
+    textures: Vec<Option<Texture>>,
+}
+
+impl CpuScene {
+    /// Loads the default scene of a glTF file, flattening the node hierarchy
+    /// into world space triangles.
+    pub fn from_file(path: &Path) -> Result<Self, LoadError> {
+        let (document, buffers, images) =
+            gltf::import(path).map_err(|e| LoadError::from_gltf(path, e))?;
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or_else(|| {
+                LoadError::Parse {
+                    path: path.to_owned(),
+                    message: "no scene".to_owned(),
+                }
+            })?;
+        let mut triangles = Vec::new();
+        let mut surfaces = Vec::new();
+        for node in scene.nodes() {
+            collect_triangles(
+                &node,
+                Mat4::IDENTITY,
+                &buffers,
+                &mut triangles,
+                &mut surfaces,
+            );
+        }
+        let textures = document
+            .textures()
+            .map(|texture| Texture::new(&images[texture.source().index()], &texture.sampler()))
+            .collect();
+        log::info!("building BVH over {} triangles", triangles.len());
+        Ok(Self {
+            bvh: Bvh::new(triangles),
+            surfaces,
+            textures,
+        })
+    }
+
+    /// The closest hit and miss shaders of the `po` crate: the radiance
+    /// arriving along the ray and the distance to its hit, zero when it
+    /// escapes.
+    fn trace(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        depth: u32,
+        rng_state: &mut u32,
+        options: &IntegratorOptions,
+        skymap: Option<&image::RgbaImage>,
+    ) -> (Vec3, f32) {
+        let hit = match self
+            .bvh
+            .intersect(origin, direction, options.tmin, options.tmax)
+        {
+            Some(hit) => hit,
+            None => {
+                let texel = skymap.map_or(Vec4::ZERO, |skymap| sample_skymap(skymap, direction));
+                let color =
+                    po_shader::sky_radiance(interop::to_shader_vec4(texel), options.sky_intensity);
+                return (interop::from_shader_vec3(color), 0.0);
+            }
+        };
+        let triangle = &self.bvh.triangles()[hit.triangle];
+        let barycentrics = Vec3::new(
+            1.0 - hit.barycentrics.x - hit.barycentrics.y,
+            hit.barycentrics.x,
+            hit.barycentrics.y,
+        );
+        let position = triangle.v0 * barycentrics.x
+            + triangle.v1 * barycentrics.y
+            + triangle.v2 * barycentrics.z;
+        let normal = po_shader::shading_normal(
+            interop::to_shader_vec3(triangle.v0),
+            interop::to_shader_vec3(triangle.v1),
+            interop::to_shader_vec3(triangle.v2),
+            interop::to_shader_vec3(direction),
+        );
+        let albedo = self.surfaces[triangle.surface].base_color(barycentrics, &self.textures);
+        if depth <= 1 {
+            return (Vec3::ZERO, hit.t);
+        }
+        let scattered = po_shader::scatter_direction(normal, rng_state);
+        let (incoming, _) = self.trace(
+            position,
+            interop::from_shader_vec3(scattered),
+            depth - 1,
+            rng_state,
+            options,
+            skymap,
+        );
+        let color = po_shader::shade(
+            interop::to_shader_vec3(albedo),
+            interop::to_shader_vec3(incoming),
+        );
+        (interop::from_shader_vec3(color), hit.t)
+    }
+}
+
+fn collect_triangles(
+    node: &gltf::Node,
+    parent: Mat4,
+    buffers: &[gltf::buffer::Data],
+    triangles: &mut Vec<Triangle>,
+    surfaces: &mut Vec<Surface>,
+) {
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<Vec3> = match reader.read_positions() {
+                Some(positions) => {
+                    positions
+                        .map(|p| transform.transform_point3(Vec3::from(p)))
+                        .collect()
+                }
+                None => continue,
+            };
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            let colors: Option<Vec<Vec4>> = reader
+                .read_colors(0)
+                .map(|colors| colors.into_rgba_f32().map(Vec4::from).collect());
+            let material = primitive.material().pbr_metallic_roughness();
+            let texture = material.base_color_texture().and_then(|info| {
+                let tex_coords: Vec<Vec2> = reader
+                    .read_tex_coords(info.tex_coord())?
+                    .into_f32()
+                    .map(Vec2::from)
+                    .collect();
+                Some((info.texture().index(), tex_coords))
+            });
+            for face in indices.chunks_exact(3) {
+                let [i0, i1, i2] = [face[0] as usize, face[1] as usize, face[2] as usize];
+                triangles.push(Triangle {
+                    v0: positions[i0],
+                    v1: positions[i1],
+                    v2: positions[i2],
+                    surface: surfaces.len(),
+                });
+                surfaces.push(Surface {
+                    base_color_factor: Vec4::from(material.base_color_factor()),
+                    colors: colors.as_ref().map(|c| [c[i0], c[i1], c[i2]]),
+                    texture: texture.as_ref().map(|(index, tex_coords)| {
+                        (*index, [tex_coords[i0], tex_coords[i1], tex_coords[i2]])
+                    }),
+                });
+            }
+        }
+    }
+    for child in node.children() {
+        collect_triangles(&child, transform, buffers, triangles, surfaces);
+    }
+}
+
+/// Bilinear lookup with clamp to edge, like the sky sampler of the GPU pass.
+fn sample_skymap(skymap: &image::RgbaImage, direction: Vec3) -> Vec4 {
+    let uv = po_shader::sample_sphereical_map(&interop::to_shader_vec3(direction));
+    let (width, height) = skymap.dimensions();
+    let x = (uv.x * width as f32 - 0.5).max(0.0);
+    let y = (uv.y * height as f32 - 0.5).max(0.0);
+    let (x0, y0) = (x as u32, y as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (x0, y0) = (x0.min(width - 1), y0.min(height - 1));
+    let (fx, fy) = (x.fract(), y.fract());
+    let texel = |x, y| {
+        let p = skymap.get_pixel(x, y).0;
+        Vec4::new(p[0] as f32, p[1] as f32, p[2] as f32, p[3] as f32) / 255.0
+    };
+    let top = texel(x0, y0).lerp(texel(x1, y0), fx);
+    let bottom = texel(x0, y1).lerp(texel(x1, y1), fx);
+    top.lerp(bottom, fy)
+}
+
+/// Produces the same results as `Po::render`. Misses sample `skymap`, or
+/// see black without one.
+pub fn render(
+    settings: &RenderSettings,
+    scene: &CpuScene,
+    skymap: Option<&image::RgbaImage>,
+    camera: &crate::engine::Camera,
+) -> Vec<RenderResult> {
+    let camera_info = CameraInfo::new(camera);
+    let render_info = RenderInfo::new(
+        camera,
+        settings.integrator,
+        super::LENS_SAMPLES,
+        super::RENDER_SEED,
+    );
+    let view_inv = interop::to_shader_mat4(camera_info.view_inv);
+    let proj_inv = interop::to_shader_mat4(camera_info.proj_inv);
+    let projection = render_info.projection.to_shader();
+    let lens = render_info.lens.to_shader();
+    let through_lens = po_shader::has_lens(&lens, &projection);
+    let launch_size = po_shader::glam::UVec2::new(settings.width, settings.height);
+    let options = render_info.options;
+
+    let pixel_count = (settings.width * settings.height) as usize;
+    let mut depth = vec![0.0; pixel_count];
+    let mut beauty = vec![0.0; pixel_count * 4];
+    depth
+        .par_chunks_mut(settings.width as usize)
+        .zip(beauty.par_chunks_mut(settings.width as usize * 4))
+        .enumerate()
+        .for_each(|(row, (depth, beauty))| {
+            // the GPU pass flips rows when storing
+            let y = settings.height - 1 - row as u32;
+            for (x, (depth, beauty)) in depth.iter_mut().zip(beauty.chunks_exact_mut(4)).enumerate()
+            {
+                let pixel = po_shader::glam::UVec2::new(x as u32, y);
+                let (origin, direction) =
+                    po_shader::primary_ray(view_inv, proj_inv, &projection, pixel, launch_size);
+                let origin = interop::from_shader_vec3(origin);
+                let direction = interop::from_shader_vec3(direction);
+                let (color, t) = if direction == Vec3::ZERO {
+                    (Vec3::ZERO, 0.0)
+                } else {
+                    let mut rng_state = po_shader::pixel_seed(pixel, launch_size, lens.seed);
+                    // depth is always through the lens centre
+                    let (mut color, t) = scene.trace(
+                        origin,
+                        direction,
+                        options.max_depth,
+                        &mut rng_state,
+                        &options,
+                        skymap,
+                    );
+                    if through_lens {
+                        color = Vec3::ZERO;
+                        for _ in 0..lens.samples {
+                            let (origin, direction) = po_shader::lens_ray(
+                                view_inv,
+                                proj_inv,
+                                &projection,
+                                &lens,
+                                pixel,
+                                launch_size,
+                                &mut rng_state,
+                            );
+                            color += scene
+                                .trace(
+                                    interop::from_shader_vec3(origin),
+                                    interop::from_shader_vec3(direction),
+                                    options.max_depth,
+                                    &mut rng_state,
+                                    &options,
+                                    skymap,
+                                )
+                                .0;
+                        }
+                        color /= lens.samples as f32;
+                    }
+                    (color, t)
+                };
+                *depth = t;
+                beauty.copy_from_slice(&color.extend(1.0).to_array());
+            }
+        });
+
+    vec![
+        RenderResult {
+            name: "depth".to_owned(),
+            width: settings.width,
+            height: settings.height,
+            channels: 1,
+            pixels: depth,
+        },
+        RenderResult {
+            name: "beauty".to_owned(),
+            width: settings.width,
+            height: settings.height,
+            channels: 4,
+            pixels: beauty,
+        },
+    ]
+}
@@ -0,0 +1,258 @@
+//! Golden image tests. Renders the scenes in `tests/scenes` with the CPU
+//! renderer under a generated sky and compares every result, the depth and
+//! the path traced beauty, against `tests/golden/<case>.<result>.exr`.
+//!
+//! Failures write the actual image, a false colour diff and `report.md` to
+//! `target/golden`. After an intended change, rerun with `PO_BLESS=1` to
+//! record new references and commit them.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::cpu::{self, CpuScene};
+use super::{RenderResult, RenderSettings};
+use crate::engine::integrator::IntegratorOptions;
+use crate::engine::{compare, Camera};
+use crate::{vec3, Vec3};
+
+struct Case {
+    name: &'static str,
+    location: Vec3,
+    look_at: Vec3,
+    width: u32,
+    height: u32,
+    min_ssim: f32,
+    /// Fraction of pixels allowed to be off by more than 1% of the
+    /// reference range, for rays grazing triangle edges.
+    max_bad_pixels: f32,
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        Case {
+            name: "quad",
+            location: vec3(0.0, 0.0, 10.0),
+            look_at: vec3(0.0, 0.0, 0.0),
+            width: 64,
+            height: 48,
+            min_ssim: 0.99,
+            max_bad_pixels: 0.005,
+        },
+        Case {
+            name: "cubes",
+            location: vec3(-4.0, 3.0, 9.0),
+            look_at: vec3(0.0, -0.5, 0.0),
+            width: 96,
+            height: 64,
+            min_ssim: 0.99,
+            max_bad_pixels: 0.005,
+        },
+    ]
+}
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn output_dir() -> PathBuf {
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../target"))
+        .join("golden")
+}
+
+/// Bright overhead and dark below the horizon, so the beauty shows both
+/// bounces off the ground and sky light. Generated to keep the test free of
+/// image files other than the references.
+fn sky() -> image::RgbaImage {
+    image::RgbaImage::from_fn(16, 8, |_, y| {
+        // the last row is straight up
+        let t = y as f32 / 7.0;
+        image::Rgba([
+            (40.0 + 150.0 * t) as u8,
+            (50.0 + 160.0 * t) as u8,
+            (60.0 + 190.0 * t) as u8,
+            255,
+        ])
+    })
+}
+
+fn render_case(case: &Case) -> Vec<RenderResult> {
+    let scene_path = tests_dir()
+        .join("scenes")
+        .join(format!("{}.gltf", case.name));
+    let scene = CpuScene::from_file(&scene_path).unwrap();
+    let settings = RenderSettings {
+        width: case.width,
+        height: case.height,
+        integrator: IntegratorOptions::default(),
+        camera: Camera::new(
+            case.location,
+            case.look_at,
+            case.width as f32 / case.height as f32,
+            std::f32::consts::FRAC_PI_3,
+        ),
+    };
+    cpu::render(&settings, &scene, Some(&sky()), &settings.camera)
+}
+
+struct Failure {
+    file: String,
+    message: String,
+    images: bool,
+}
+
+/// Compares `actual` against `reference`, writing the actual image and a
+/// diff to `out_dir` when they differ too much.
+fn check(
+    case: &Case,
+    file: &str,
+    actual: &RenderResult,
+    reference: &RenderResult,
+    out_dir: &Path,
+) -> Option<Failure> {
+    if (actual.width, actual.height) != (reference.width, reference.height) {
+        return Some(Failure {
+            file: file.to_owned(),
+            message: format!(
+                "size {}x{}, reference is {}x{}",
+                actual.width, actual.height, reference.width, reference.height
+            ),
+            images: false,
+        });
+    }
+    let a = compare::luminance(actual);
+    let b = compare::luminance(reference);
+    let (width, height) = (actual.width as usize, actual.height as usize);
+    let range = b.iter().cloned().fold(0.0f32, f32::max).max(1e-6);
+    let ssim = compare::ssim(&a, &b, width, height, range);
+    let bad = a
+        .iter()
+        .zip(&b)
+        .filter(|(a, b)| (*a - *b).abs() > 0.01 * range)
+        .count() as f32
+        / a.len() as f32;
+    if ssim >= case.min_ssim && bad <= case.max_bad_pixels {
+        return None;
+    }
+
+    let stem = file.trim_end_matches(".exr");
+    actual
+        .write_exr(&out_dir.join(format!("{}.actual.exr", stem)))
+        .unwrap();
+    let diff = image::RgbImage::from_fn(actual.width, actual.height, |x, y| {
+        let i = y as usize * width + x as usize;
+        // 10% of the range saturates the ramp
+        image::Rgb(compare::false_colour((a[i] - b[i]).abs() / range * 10.0))
+    });
+    diff.save(out_dir.join(format!("{}.diff.png", stem)))
+        .unwrap();
+    Some(Failure {
+        file: file.to_owned(),
+        message: format!(
+            "SSIM {:.4} (min {}), {:.2}% bad pixels (max {}%)",
+            ssim,
+            case.min_ssim,
+            bad * 100.0,
+            case.max_bad_pixels * 100.0
+        ),
+        images: true,
+    })
+}
+
+fn write_report(out_dir: &Path, failures: &[Failure]) -> PathBuf {
+    let mut report =
+        String::from("# Golden image failures\n\n| result | problem | images |\n|---|---|---|\n");
+    for failure in failures {
+        let stem = failure.file.trim_end_matches(".exr");
+        let images = if failure.images {
+            format!(
+                "[actual]({0}.actual.exr) [diff]({0}.diff.png) ![diff]({0}.diff.png)",
+                stem
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            report,
+            "| {} | {} | {} |",
+            failure.file, failure.message, images
+        )
+        .unwrap();
+    }
+    let path = out_dir.join("report.md");
+    std::fs::write(&path, report).unwrap();
+    path
+}
+
+#[test]
+fn golden_images() {
+    let bless = std::env::var_os("PO_BLESS").is_some();
+    let out_dir = output_dir();
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let mut failures = Vec::new();
+    for case in cases() {
+        for actual in render_case(&case) {
+            let file = format!("{}.{}.exr", case.name, actual.name);
+            let reference_path = tests_dir().join("golden").join(&file);
+            if bless {
+                actual.write_exr(&reference_path).unwrap();
+                continue;
+            }
+            match compare::read_exr(&reference_path) {
+                Ok(reference) => {
+                    failures.extend(check(&case, &file, &actual, &reference, &out_dir));
+                }
+                Err(e) => {
+                    failures.push(Failure {
+                        file,
+                        message: format!("cannot read reference, run with PO_BLESS=1: {}", e),
+                        images: false,
+                    });
+                }
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        let report = write_report(&out_dir, &failures);
+        panic!(
+            "{} golden image(s) differ, see {}",
+            failures.len(),
+            report.display()
+        );
+    }
+}
+
+#[test]
+fn open_aperture_blurs_only_the_beauty() {
+    let case = &cases()[1];
+    let pinhole = render_case(case);
+    let scene = CpuScene::from_file(
+        &tests_dir()
+            .join("scenes")
+            .join(format!("{}.gltf", case.name)),
+    )
+    .unwrap();
+    let mut camera = Camera::new(
+        case.location,
+        case.look_at,
+        case.width as f32 / case.height as f32,
+        std::f32::consts::FRAC_PI_3,
+    );
+    camera.lens.aperture_radius = 0.3;
+    camera.lens.focus_distance = 10.0;
+    let settings = RenderSettings {
+        width: case.width,
+        height: case.height,
+        integrator: IntegratorOptions::default(),
+        camera,
+    };
+    let lens = cpu::render(&settings, &scene, Some(&sky()), &settings.camera);
+
+    // depth is traced through the lens centre
+    assert_eq!(pinhole[0].pixels, lens[0].pixels);
+    assert!(lens[1].pixels.iter().all(|p| p.is_finite()));
+    assert_ne!(pinhole[1].pixels, lens[1].pixels);
+}
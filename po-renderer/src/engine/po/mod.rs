@@ -1,4 +1,13 @@
-use super::util;
+mod bvh;
+pub mod cpu;
+#[cfg(test)]
+mod golden;
+
+use std::path::Path;
+
+use super::camera::{LensInfo, ProjectionInfo};
+use super::integrator::IntegratorOptions;
+use super::{reflect, util};
 use glam::Vec3;
 use maligog::{vk, Device};
 use maplit::btreemap;
@@ -12,8 +21,54 @@ pub struct CameraInfo {
     proj_inv: glam::Mat4,
 }
 
+impl CameraInfo {
+    pub fn new(camera: &super::Camera) -> Self {
+        Self {
+            view_inv: camera.view().inverse(),
+            proj_inv: camera.projection_matrix().inverse(),
+        }
+    }
+}
+
+/// What doesn't fit next to [`CameraInfo`] in the push constants, in a
+/// uniform buffer at set 1, binding 4. Po and the interactive ray tracing
+/// pass share the layout.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Zeroable, Pod)]
+pub struct RenderInfo {
+    options: IntegratorOptions,
+    lens: LensInfo,
+    projection: ProjectionInfo,
+}
+
+/// Rays per pixel through a lens with an open aperture, enough for smooth
+/// bokeh in a single render.
+const LENS_SAMPLES: u32 = 64;
+
+/// Fixed, so renders of the same settings are identical.
+const RENDER_SEED: u32 = 0;
+
+impl RenderInfo {
+    /// `samples` rays per pixel through an open aperture, `seed` varies
+    /// their rng.
+    pub fn new(
+        camera: &super::Camera,
+        options: IntegratorOptions,
+        samples: u32,
+        seed: u32,
+    ) -> Self {
+        Self {
+            options,
+            lens: LensInfo::new(&camera.lens, samples, seed),
+            projection: ProjectionInfo::new(camera),
+        }
+    }
+}
+
+// Only the shaders read these, the host just uploads them.
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
+#[allow(dead_code)]
 pub struct GeometryInfo {
     pub index_offset: u64,
     pub vertex_offset: u64,
@@ -28,6 +83,7 @@ pub struct GeometryInfo {
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Zeroable, Pod)]
+#[allow(dead_code)]
 pub struct MaterialInfo {
     base_color_factor: glam::Vec4,
     has_base_color_texture: u32,
@@ -39,11 +95,12 @@ pub struct MaterialInfo {
     padding: u64,
 }
 
+/// Output size, integrator parameters and camera of an offline render.
 pub struct RenderSettings {
-    width: u32,
-    height: u32,
-    max_bounce: u32,
-    camera: super::Camera,
+    pub width: u32,
+    pub height: u32,
+    pub integrator: IntegratorOptions,
+    pub camera: super::Camera,
 }
 
 impl Default for RenderSettings {
@@ -51,7 +108,7 @@ impl Default for RenderSettings {
         Self {
             width: 800,
             height: 600,
-            max_bounce: 5,
+            integrator: IntegratorOptions::default(),
             camera: super::Camera::new(
                 Vec3::new(0.0, 0.0, 10.0),
                 Vec3::splat(0.0),
@@ -62,24 +119,103 @@ impl Default for RenderSettings {
     }
 }
 
+impl RenderSettings {
+    /// Renders through the scene camera `name`, at the aspect ratio of the
+    /// output size.
+    pub fn use_scene_camera(
+        &mut self,
+        cameras: &[super::scene_camera::SceneCamera],
+        name: &str,
+    ) -> Result<(), String> {
+        let scene_camera = super::scene_camera::SceneCamera::find(cameras, name)
+            .ok_or_else(|| format!("no camera {}", name))?;
+        self.camera = scene_camera.camera(self.width as f32 / self.height as f32)?;
+        Ok(())
+    }
+
+    /// Renders from the bookmark `name`, at the aspect ratio of the output
+    /// size.
+    pub fn use_bookmark(
+        &mut self,
+        bookmarks: &super::bookmark::Bookmarks,
+        name: &str,
+    ) -> Result<(), String> {
+        let bookmark = bookmarks
+            .get(name)
+            .ok_or_else(|| format!("no bookmark {}", name))?;
+        self.camera = bookmark.pose.camera(self.width as f32 / self.height as f32);
+        Ok(())
+    }
+
+    /// Renders through the calibrated sensor `intrinsics` from the current
+    /// pose, the output size should match the calibrated image size.
+    pub fn use_calibration(&mut self, intrinsics: &super::calibration::Intrinsics) {
+        self.camera.fov = intrinsics.fov();
+        self.camera.aspect_ratio = self.width as f32 / self.height as f32;
+        self.camera.projection = super::Projection::Calibrated(*intrinsics);
+    }
+}
+
+/// One output image of a render, read back to the host.
 pub struct RenderResult {
     pub name: String,
-    pub image: maligog::Image,
+    pub width: u32,
+    pub height: u32,
+    pub channels: usize,
+    /// Row major, top row first, `channels` floats per pixel.
+    pub pixels: Vec<f32>,
+}
+
+impl RenderResult {
+    pub fn pixel(&self, x: usize, y: usize) -> &[f32] {
+        let i = (y * self.width as usize + x) * self.channels;
+        &self.pixels[i..i + self.channels]
+    }
+
+    pub fn write_exr(&self, path: &Path) -> Result<(), String> {
+        exr::prelude::write_rgb_file(path, self.width as usize, self.height as usize, |x, y| {
+            let p = self.pixel(x, y);
+            match p.len() {
+                1 | 2 => (p[0], p[0], p[0]),
+                _ => (p[0], p[1], p[2]),
+            }
+        })
+        .map_err(|e| format!("{}: {}", path.display(), e))
+    }
 }
 
+/// Offline renderer tracing the whole frame in one dispatch and reading the
+/// results back to the host.
 pub struct Po {
-    depth_pipeline: maligog::RayTracingPipeline,
-    rx: crossbeam::channel::Receiver<Vec<u8>>,
+    pipeline: maligog::RayTracingPipeline,
+    shader: util::ShaderWatcher,
+    host_layout: Vec<reflect::DescriptorBinding>,
     device: Device,
     pipeline_layout: maligog::PipelineLayout,
+    pipeline_cache: maligog::PipelineCache,
+    #[allow(dead_code)]
     descriptor_pool: maligog::DescriptorPool,
+    #[allow(dead_code)]
     as_descriptor_set_layout: maligog::DescriptorSetLayout,
+    #[allow(dead_code)]
     skymap_descriptor_set_layout: maligog::DescriptorSetLayout,
+    #[allow(dead_code)]
     image_descriptor_set_layout: maligog::DescriptorSetLayout,
+    // allocated once and rewritten by every render, the pool only holds one
+    // of each
+    as_descriptor_set: maligog::DescriptorSet,
+    image_descriptor_set: maligog::DescriptorSet,
+    skymap_descriptor_set: maligog::DescriptorSet,
+    /// The sky of [`Po::pick`], which only reads depth.
+    #[allow(dead_code)]
+    black_sky: maligog::Image,
+    black_sky_view: maligog::ImageView,
 }
 
 impl Po {
-    pub fn new(device: &Device) -> Self {
+    /// `pipeline_cache` may be empty, see [`crate::pipeline_cache`] for
+    /// persisting it.
+    pub fn new(device: &Device, pipeline_cache: &maligog::PipelineCache) -> Result<Self, String> {
         let sky_sampler = device.create_sampler(
             Some("sky"),
             maligog::Filter::LINEAR,
@@ -92,135 +228,145 @@ impl Po {
         log::debug!("creating as descriptor set layout");
 
         // descriptor set 0
-        let as_descriptor_set_layout = device.create_descriptor_set_layout(
-            Some("ray tracing as"),
-            &[
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 0,
-                    descriptor_type: maligog::DescriptorType::AccelerationStructure,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 1,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 2,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 3,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 4,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 5,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 6,
-                    descriptor_type: maligog::DescriptorType::Sampler(None),
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 500,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 7,
-                    descriptor_type: maligog::DescriptorType::SampledImage,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 500,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 8,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 9,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 10,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-            ],
-        );
+        let as_bindings = [
+            maligog::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: maligog::DescriptorType::AccelerationStructure,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 2,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 3,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 4,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 5,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 6,
+                descriptor_type: maligog::DescriptorType::Sampler(None),
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 500,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 7,
+                descriptor_type: maligog::DescriptorType::SampledImage,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 500,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 8,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 9,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 10,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+        ];
+        let as_descriptor_set_layout =
+            device.create_descriptor_set_layout(Some("ray tracing as"), &as_bindings);
 
         // descriptor set 1
-        let image_descriptor_set_layout = device.create_descriptor_set_layout(
-            Some("ray tracing image"),
-            &[
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 0,
-                    descriptor_type: maligog::DescriptorType::StorageImage,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 1,
-                    descriptor_type: maligog::DescriptorType::StorageImage,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 3,
-                    descriptor_type: maligog::DescriptorType::StorageImage,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 2,
-                    descriptor_type: maligog::DescriptorType::Sampler(Some(sky_sampler)),
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-            ],
-        );
-
-        log::debug!("creating skymap descriptor set layout");
-        let skymap_descriptor_set_layout = device.create_descriptor_set_layout(
-            Some("ray tracing skymap"),
-            &[maligog::DescriptorSetLayoutBinding {
+        let image_bindings = [
+            maligog::DescriptorSetLayoutBinding {
                 binding: 0,
-                descriptor_type: maligog::DescriptorType::SampledImage,
-                stage_flags: maligog::ShaderStageFlags::MISS_KHR,
+                descriptor_type: maligog::DescriptorType::StorageImage,
+                stage_flags: maligog::ShaderStageFlags::ALL,
                 descriptor_count: 1,
                 variable_count: false,
-            }],
-        );
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: maligog::DescriptorType::StorageImage,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 3,
+                descriptor_type: maligog::DescriptorType::StorageImage,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 2,
+                descriptor_type: maligog::DescriptorType::Sampler(Some(sky_sampler)),
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 4,
+                descriptor_type: maligog::DescriptorType::UniformBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+        ];
+        let image_descriptor_set_layout =
+            device.create_descriptor_set_layout(Some("ray tracing image"), &image_bindings);
+
+        log::debug!("creating skymap descriptor set layout");
+        let skymap_bindings = [maligog::DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: maligog::DescriptorType::SampledImage,
+            stage_flags: maligog::ShaderStageFlags::MISS_KHR,
+            descriptor_count: 1,
+            variable_count: false,
+        }];
+        let skymap_descriptor_set_layout =
+            device.create_descriptor_set_layout(Some("ray tracing skymap"), &skymap_bindings);
+        let host_layout = [
+            reflect::host_bindings(0, &as_bindings),
+            reflect::host_bindings(1, &image_bindings),
+            reflect::host_bindings(2, &skymap_bindings),
+        ]
+        .concat();
         let pipeline_layout = device.create_pipeline_layout(
             Some("ray tracing"),
             &[
@@ -231,31 +377,25 @@ impl Po {
             &[maligog::PushConstantRange::builder()
                 .offset(0)
                 .size(std::mem::size_of::<CameraInfo>() as u32)
-                .stage_flags(
-                    maligog::ShaderStageFlags::RAYGEN_KHR
-                        | maligog::ShaderStageFlags::CLOSEST_HIT_KHR,
-                )
+                .stage_flags(maligog::ShaderStageFlags::RAYGEN_KHR)
                 .build()],
         );
-        let (tx, rx) = crossbeam::channel::bounded(1);
-        let builder = util::spirv_builder("./shaders/po");
-        let tx1 = tx.clone();
-        let result = builder
-            .watch(move |result| {
-                crate::engine::util::handle_shader_compile(result, &tx1);
-            })
-            .unwrap();
-        crate::engine::util::handle_shader_compile(result, &tx);
-
-        let spirv = rx.recv().unwrap();
+        let (shader, spirv) =
+            util::ShaderWatcher::new(util::ShaderSource::Bundled("po".to_owned()))
+                .and_then(|(shader, spirv)| {
+                    reflect::check_module(&spirv, &host_layout)?;
+                    Ok((shader, spirv))
+                })
+                .map_err(|e| format!("po shader: {}", e))?;
 
         log::debug!("creating shader module");
         let module = device.create_shader_module(spirv);
 
         log::debug!("creating pipeline");
-        let depth_pipeline = Self::build_pipeline(
+        let pipeline = Self::build_pipeline(
             device,
             &pipeline_layout,
+            pipeline_cache,
             &maligog::ShaderStage::new(&module, maligog::ShaderStageFlags::RAYGEN_KHR, "main"),
             &[&maligog::ShaderStage::new(
                 &module,
@@ -266,7 +406,7 @@ impl Po {
                 &maligog::ShaderStage::new(
                     &module,
                     maligog::ShaderStageFlags::CLOSEST_HIT_KHR,
-                    "depth_closest_hit",
+                    "closest_hit",
                 ),
                 None,
             )],
@@ -276,7 +416,7 @@ impl Po {
             &[
                 maligog::DescriptorPoolSize::builder()
                     .ty(vk::DescriptorType::STORAGE_IMAGE)
-                    .descriptor_count(2)
+                    .descriptor_count(4)
                     .build(),
                 maligog::DescriptorPoolSize::builder()
                     .ty(vk::DescriptorType::SAMPLED_IMAGE)
@@ -290,17 +430,14 @@ impl Po {
                     .ty(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
                     .descriptor_count(1)
                     .build(),
+                maligog::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                    .descriptor_count(1)
+                    .build(),
             ],
             10,
         );
 
-        let default_sampler = device.create_sampler(
-            Some("rt default sampler"),
-            maligog::Filter::NEAREST,
-            maligog::Filter::NEAREST,
-            maligog::SamplerAddressMode::CLAMP_TO_EDGE,
-            maligog::SamplerAddressMode::CLAMP_TO_EDGE,
-        );
         let skymap_descriptor_set_layout = device.create_descriptor_set_layout(
             Some("ray tracing skymap"),
             &[maligog::DescriptorSetLayoutBinding {
@@ -311,21 +448,54 @@ impl Po {
                 variable_count: false,
             }],
         );
-        Self {
-            depth_pipeline,
-            rx,
+        let black_sky = device.create_image_init(
+            Some("black sky"),
+            maligog::Format::R8G8B8A8_UNORM,
+            1,
+            1,
+            maligog::ImageUsageFlags::SAMPLED,
+            maligog::MemoryLocation::GpuOnly,
+            &[0, 0, 0, 255],
+        );
+        log::debug!("allocating descriptor sets");
+        let as_descriptor_set = device.allocate_descriptor_set(
+            Some("as descriptor set"),
+            &descriptor_pool,
+            &as_descriptor_set_layout,
+        );
+        let image_descriptor_set = device.allocate_descriptor_set(
+            Some("image descriptor set"),
+            &descriptor_pool,
+            &image_descriptor_set_layout,
+        );
+        let skymap_descriptor_set = device.allocate_descriptor_set(
+            Some("skymap descriptor set"),
+            &descriptor_pool,
+            &skymap_descriptor_set_layout,
+        );
+        Ok(Self {
+            pipeline,
+            shader,
+            host_layout,
             device: device.clone(),
             pipeline_layout,
+            pipeline_cache: pipeline_cache.clone(),
             descriptor_pool,
             as_descriptor_set_layout,
             skymap_descriptor_set_layout,
             image_descriptor_set_layout,
-        }
+            as_descriptor_set,
+            image_descriptor_set,
+            skymap_descriptor_set,
+            black_sky_view: black_sky.create_view(),
+            black_sky,
+        })
     }
 
     fn build_pipeline(
         device: &Device,
         pipeline_layout: &maligog::PipelineLayout,
+        pipeline_cache: &maligog::PipelineCache,
         ray_gen_shader: &maligog::ShaderStage,
         miss_shaders: &[&maligog::ShaderStage],
         hit_groups: &[&dyn maligog::HitGroup],
@@ -337,10 +507,20 @@ impl Po {
             miss_shaders,
             hit_groups,
             31,
+            Some(pipeline_cache),
         );
         pipeline
     }
 
+    pub fn shader(&self) -> &util::ShaderWatcher {
+        &self.shader
+    }
+
+    /// Renders `scene` from `camera` at the size in `settings`. Blocks until
+    /// the GPU is done.
+    ///
+    /// The results are the distance to the first hit as `depth`, zero where
+    /// the ray escapes, and the path traced radiance as `beauty`.
     pub fn render(
         &mut self,
         settings: &RenderSettings,
@@ -356,14 +536,20 @@ impl Po {
             maligog::ImageUsageFlags::STORAGE | maligog::ImageUsageFlags::TRANSFER_SRC,
             maligog::MemoryLocation::GpuOnly,
         );
+        let beauty_image = self.device.create_image(
+            Some("beauty"),
+            maligog::Format::R32G32B32A32_SFLOAT,
+            settings.width,
+            settings.height,
+            maligog::ImageUsageFlags::STORAGE | maligog::ImageUsageFlags::TRANSFER_SRC,
+            maligog::MemoryLocation::GpuOnly,
+        );
 
         let mut hit_groups: Vec<u32> = Vec::new();
-        for i in 0..12345 {
+        for _ in 0..12345 {
             hit_groups.push(0);
         }
-        let shader_binding_tables = self
-            .depth_pipeline
-            .create_shader_binding_tables(&hit_groups);
+        let shader_binding_tables = self.pipeline.create_shader_binding_tables(&hit_groups);
 
         let mut cmd_buf = self.device.create_command_buffer(
             Some("render cmd buf"),
@@ -372,27 +558,23 @@ impl Po {
         let mut geometry_infos = Vec::new();
         let mut geometry_info_offsets = vec![0];
 
-        log::debug!("allocating as descriptor set");
-        let as_descriptor_set = self.device.allocate_descriptor_set(
-            Some("as descriptor set"),
-            &self.descriptor_pool,
-            &self.as_descriptor_set_layout,
-        );
-        log::debug!("allocating skymap descriptor set");
-        let skymap_descriptor_set = self.device.allocate_descriptor_set(
-            Some("skymap descriptor set"),
-            &self.descriptor_pool,
-            &self.skymap_descriptor_set_layout,
-        );
-        log::debug!("creating image descriptor set");
-        let image_descriptor_set = self.device.create_descriptor_set(
-            Some("image descriptor set"),
-            &self.descriptor_pool,
-            &self.image_descriptor_set_layout,
-            btreemap! {
-                3 => maligog::DescriptorUpdate::Image(vec![depth_image.create_view()]),
-            },
+        let render_info_buffer = self.device.create_buffer_init(
+            Some("render info"),
+            bytemuck::cast_slice(&[RenderInfo::new(
+                camera,
+                settings.integrator,
+                LENS_SAMPLES,
+                RENDER_SEED,
+            )]),
+            maligog::BufferUsageFlags::UNIFORM_BUFFER,
+            maligog::MemoryLocation::GpuOnly,
         );
+        log::debug!("updating image descriptor set");
+        self.image_descriptor_set.update(btreemap! {
+            0 => maligog::DescriptorUpdate::Image(vec![beauty_image.create_view()]),
+            3 => maligog::DescriptorUpdate::Image(vec![depth_image.create_view()]),
+            4 => maligog::DescriptorUpdate::Buffer(vec![maligog::BufferView { buffer: render_info_buffer, offset: 0 }]),
+        });
 
         for (i, mesh) in scene.mesh_infos().iter().enumerate() {
             let convert = mesh.primitive_infos.iter().map(|i| {
@@ -475,7 +657,7 @@ impl Po {
         );
 
         log::debug!("potential problematic update");
-        as_descriptor_set.update(btreemap! {
+        self.as_descriptor_set.update(btreemap! {
             0 => maligog::DescriptorUpdate::AccelerationStructure(vec![scene.tlas().clone()]),
             1 => maligog::DescriptorUpdate::Buffer(vec![scene.index_buffer().clone()]),
             2 => maligog::DescriptorUpdate::Buffer(vec![scene.vertex_buffer().clone()]),
@@ -488,52 +670,48 @@ impl Po {
         log::debug!("update done");
 
         if scene.images().len() > 0 {
-            as_descriptor_set.update(btreemap! {
+            self.as_descriptor_set.update(btreemap! {
                 7 => maligog::DescriptorUpdate::Image(scene.images().iter().map(|i|i.create_view()).collect()),
             });
         }
         if let Some(b) = scene.color_buffer() {
-            as_descriptor_set.update(btreemap! {
+            self.as_descriptor_set.update(btreemap! {
                 9 => maligog::DescriptorUpdate::Buffer(vec![b]),
             });
         }
         if let Some(b) = scene.tex_coord_buffer() {
-            as_descriptor_set.update(btreemap! {
+            self.as_descriptor_set.update(btreemap! {
                 10 => maligog::DescriptorUpdate::Buffer(vec![b]),
             });
         }
-        skymap_descriptor_set.update(btreemap! {
+        self.skymap_descriptor_set.update(btreemap! {
             0 => maligog::DescriptorUpdate::Image(vec![skymap.clone()]),
         });
-        let mut camera_info = CameraInfo {
-            view_inv: glam::Mat4::look_at_lh(
-                camera.location,
-                camera.location + camera.front,
-                camera.up,
-            )
-            .inverse(),
-            proj_inv: glam::Mat4::perspective_lh(camera.fov, camera.aspect_ratio, 0.001, 10000.0)
-                .inverse(),
-        };
+        let camera_info = CameraInfo::new(camera);
         let depth_image_buffer = self.device.create_buffer(
             Some("depth image buffer"),
             depth_image.linear_size(),
             maligog::BufferUsageFlags::empty(),
             maligog::MemoryLocation::GpuToCpu,
         );
+        let beauty_image_buffer = self.device.create_buffer(
+            Some("beauty image buffer"),
+            beauty_image.linear_size(),
+            maligog::BufferUsageFlags::empty(),
+            maligog::MemoryLocation::GpuToCpu,
+        );
         cmd_buf.encode(|rec| {
-            rec.bind_ray_tracing_pipeline(&self.depth_pipeline, |rec| {
+            rec.bind_ray_tracing_pipeline(&self.pipeline, |rec| {
                 rec.bind_descriptor_sets(
                     vec![
-                        &as_descriptor_set,
-                        &image_descriptor_set,
-                        &skymap_descriptor_set,
+                        &self.as_descriptor_set,
+                        &self.image_descriptor_set,
+                        &self.skymap_descriptor_set,
                     ],
                     0,
                 );
                 rec.push_constants(
-                    maligog::ShaderStageFlags::RAYGEN_KHR
-                        | maligog::ShaderStageFlags::CLOSEST_HIT_KHR,
+                    maligog::ShaderStageFlags::RAYGEN_KHR,
                     &bytemuck::cast_slice(&[camera_info]),
                 );
                 rec.trace_ray(
@@ -550,34 +728,91 @@ impl Po {
                     maligog::ImageLayout::TRANSFER_SRC_OPTIMAL,
                     &depth_image_buffer,
                 );
+                rec.copy_image_to_buffer(
+                    &beauty_image,
+                    maligog::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    &beauty_image_buffer,
+                );
             });
         });
         self.device.graphics_queue().submit_blocking(&[cmd_buf]);
+        let mut results = Vec::new();
         if let Some(data) = depth_image_buffer.lock_memory().unwrap().mapped_slice() {
             let pixels: &[f32] = bytemuck::cast_slice(data);
-            let width = depth_image.width() as usize;
-            let height = depth_image.height() as usize;
-            exr::prelude::write_rgb_file("depth.exr", width, height, |x, y| {
-                (
-                    // generate (or lookup in your own image) an f32 rgb color for each of the 2048x2048 pixels
-                    pixels[y * width + x], // red
-                    pixels[y * width + x], // green
-                    pixels[y * width + x], // blue
-                )
+            results.push(RenderResult {
+                name: "depth".to_owned(),
+                width: depth_image.width(),
+                height: depth_image.height(),
+                channels: 1,
+                pixels: pixels.to_vec(),
+            });
+        }
+        if let Some(data) = beauty_image_buffer.lock_memory().unwrap().mapped_slice() {
+            let pixels: &[f32] = bytemuck::cast_slice(data);
+            results.push(RenderResult {
+                name: "beauty".to_owned(),
+                width: beauty_image.width(),
+                height: beauty_image.height(),
+                channels: 4,
+                pixels: pixels.to_vec(),
             });
         }
+        results
+    }
 
-        Vec::new()
+    /// Distance along the view axis of `camera` to the surface under `ndc`,
+    /// y up, or `None` when the ray escapes. Traces a single pixel and reads
+    /// its depth. Reuses the render's descriptor sets and sky view, so it is
+    /// cheap enough to call on every click.
+    pub fn pick(
+        &mut self,
+        scene: &maligog_gltf::Scene,
+        camera: &crate::engine::Camera,
+        integrator: IntegratorOptions,
+        ndc: glam::Vec2,
+    ) -> Option<f32> {
+        let (origin, direction) = camera.ray(ndc)?;
+        // a pinhole at the ray origin looking down it
+        let mut ray_camera = crate::engine::Camera::from_angles(
+            origin,
+            direction.z.atan2(direction.x),
+            direction.y.clamp(-1.0, 1.0).asin(),
+            1.0,
+            camera.fov,
+        );
+        ray_camera.near = camera.near;
+        ray_camera.far = camera.far;
+        let settings = RenderSettings {
+            width: 1,
+            height: 1,
+            integrator,
+            camera: ray_camera.clone(),
+        };
+        let sky = self.black_sky_view.clone();
+        let depth = self
+            .render(&settings, scene, &sky, &ray_camera)
+            .first()?
+            .pixels[0];
+        if depth > 0.0 {
+            Some(depth * direction.dot(camera.front))
+        } else {
+            None
+        }
     }
 
-    fn update(&mut self) {
-        if let Ok(spirv) = self.rx.try_recv() {
+    pub fn update(&mut self) {
+        if let Some(spirv) = self.shader.try_recv() {
+            if let Err(e) = reflect::check_module(&spirv, &self.host_layout) {
+                self.shader.reject(e);
+                return;
+            }
             log::info!("updating shader");
             let module = self.device.create_shader_module(spirv);
 
-            self.depth_pipeline = Self::build_pipeline(
+            self.pipeline = Self::build_pipeline(
                 &self.device,
                 &self.pipeline_layout,
+                &self.pipeline_cache,
                 &maligog::ShaderStage::new(&module, maligog::ShaderStageFlags::RAYGEN_KHR, "main"),
                 &[&maligog::ShaderStage::new(
                     &module,
@@ -588,7 +823,7 @@ impl Po {
                     &maligog::ShaderStage::new(
                         &module,
                         maligog::ShaderStageFlags::CLOSEST_HIT_KHR,
-                        "depth_closest_hit",
+                        "closest_hit",
                     ),
                     None,
                 )],
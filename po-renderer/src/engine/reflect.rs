@@ -0,0 +1,623 @@
+//! Minimal SPIR-V reflection, just enough to recover the descriptor bindings a
+//! shader module declares so they can be checked against the host side
+//! descriptor set layouts before a pipeline is created.
+
+use std::collections::HashMap;
+use std::fmt;
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const EXECUTION_MODEL_VERTEX: u32 = 0;
+const EXECUTION_MODEL_FRAGMENT: u32 = 4;
+const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+const EXECUTION_MODEL_RAY_GENERATION: u32 = 5313;
+const EXECUTION_MODEL_INTERSECTION: u32 = 5314;
+const EXECUTION_MODEL_ANY_HIT: u32 = 5315;
+const EXECUTION_MODEL_CLOSEST_HIT: u32 = 5316;
+const EXECUTION_MODEL_MISS: u32 = 5317;
+const EXECUTION_MODEL_CALLABLE: u32 = 5318;
+
+const DIM_BUFFER: u32 = 5;
+const IMAGE_SAMPLED_STORAGE: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorKind {
+    Sampler,
+    SampledImage,
+    CombinedImageSampler,
+    StorageImage,
+    UniformTexelBuffer,
+    StorageTexelBuffer,
+    UniformBuffer,
+    StorageBuffer,
+    AccelerationStructure,
+}
+
+impl DescriptorKind {
+    pub fn from_host(ty: &maligog::DescriptorType) -> Option<Self> {
+        match ty {
+            maligog::DescriptorType::Sampler(_) => Some(Self::Sampler),
+            maligog::DescriptorType::SampledImage => Some(Self::SampledImage),
+            maligog::DescriptorType::StorageImage => Some(Self::StorageImage),
+            maligog::DescriptorType::UniformBuffer => Some(Self::UniformBuffer),
+            maligog::DescriptorType::StorageBuffer => Some(Self::StorageBuffer),
+            maligog::DescriptorType::AccelerationStructure => Some(Self::AccelerationStructure),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionModel {
+    Vertex,
+    Fragment,
+    GLCompute,
+    RayGeneration,
+    Intersection,
+    AnyHit,
+    ClosestHit,
+    Miss,
+    Callable,
+    Other(u32),
+}
+
+impl ExecutionModel {
+    fn from_word(word: u32) -> Self {
+        match word {
+            EXECUTION_MODEL_VERTEX => Self::Vertex,
+            EXECUTION_MODEL_FRAGMENT => Self::Fragment,
+            EXECUTION_MODEL_GL_COMPUTE => Self::GLCompute,
+            EXECUTION_MODEL_RAY_GENERATION => Self::RayGeneration,
+            EXECUTION_MODEL_INTERSECTION => Self::Intersection,
+            EXECUTION_MODEL_ANY_HIT => Self::AnyHit,
+            EXECUTION_MODEL_CLOSEST_HIT => Self::ClosestHit,
+            EXECUTION_MODEL_MISS => Self::Miss,
+            EXECUTION_MODEL_CALLABLE => Self::Callable,
+            other => Self::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub model: ExecutionModel,
+    pub name: String,
+}
+
+/// `count` is `None` for runtime sized arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub kind: DescriptorKind,
+    pub count: Option<u32>,
+}
+
+impl fmt::Display for DescriptorBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "set = {}, binding = {}: {:?}",
+            self.set, self.binding, self.kind
+        )?;
+        match self.count {
+            Some(1) => Ok(()),
+            Some(count) => write!(f, "[{}]", count),
+            None => write!(f, "[]"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReflectError {
+    TooShort,
+    BadMagic(u32),
+    Truncated { offset: usize },
+    UnresolvedType { variable: u32 },
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectError::TooShort => write!(f, "module is shorter than a SPIR-V header"),
+            ReflectError::BadMagic(magic) => write!(f, "bad SPIR-V magic number {:#010x}", magic),
+            ReflectError::Truncated { offset } => {
+                write!(f, "instruction at word {} runs past the end", offset)
+            }
+            ReflectError::UnresolvedType { variable } => {
+                write!(f, "cannot resolve the type of variable %{}", variable)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutMismatch {
+    Missing(DescriptorBinding),
+    WrongKind {
+        shader: DescriptorBinding,
+        host: DescriptorBinding,
+    },
+    TooFew {
+        shader: DescriptorBinding,
+        host: DescriptorBinding,
+    },
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutMismatch::Missing(shader) => {
+                write!(
+                    f,
+                    "shader uses ({}) but the host layout has no such binding",
+                    shader
+                )
+            }
+            LayoutMismatch::WrongKind { shader, host } => {
+                write!(
+                    f,
+                    "shader declares ({}) but the host layout has ({})",
+                    shader, host
+                )
+            }
+            LayoutMismatch::TooFew { shader, host } => {
+                write!(
+                    f,
+                    "shader declares ({}) but the host layout only provides ({})",
+                    shader, host
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutError {
+    pub mismatches: Vec<LayoutMismatch>,
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "descriptor layout does not match the shader:")?;
+        for mismatch in &self.mismatches {
+            writeln!(f, "  {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+#[derive(Clone, Copy)]
+enum Type {
+    Sampler,
+    Image { dim: u32, sampled: u32 },
+    SampledImage,
+    AccelerationStructure,
+    Struct,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Pointer { storage_class: u32, pointee: u32 },
+}
+
+fn parse_words(spirv: &[u8]) -> Result<Vec<u32>, ReflectError> {
+    if spirv.len() < HEADER_WORDS * 4 {
+        return Err(ReflectError::TooShort);
+    }
+    let words = spirv
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect::<Vec<_>>();
+    if words[0] != MAGIC {
+        return Err(ReflectError::BadMagic(words[0]));
+    }
+    Ok(words)
+}
+
+/// Splits the module after the header into `(opcode, operands)` pairs.
+fn instructions(words: &[u32]) -> Result<Vec<(u32, &[u32])>, ReflectError> {
+    let mut result = Vec::new();
+    let mut offset = HEADER_WORDS;
+    while offset < words.len() {
+        let word_count = (words[offset] >> 16) as usize;
+        let opcode = words[offset] & 0xffff;
+        if word_count == 0 || offset + word_count > words.len() {
+            return Err(ReflectError::Truncated { offset });
+        }
+        result.push((opcode, &words[offset + 1..offset + word_count]));
+        offset += word_count;
+    }
+    Ok(result)
+}
+
+/// Decodes a nul terminated literal string operand.
+fn literal_string(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes().to_vec())
+        .take_while(|b| *b != 0)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+pub fn reflect_entry_points(spirv: &[u8]) -> Result<Vec<EntryPoint>, ReflectError> {
+    let words = parse_words(spirv)?;
+    Ok(instructions(&words)?
+        .into_iter()
+        .filter_map(|(opcode, operands)| {
+            match (opcode, operands) {
+                (OP_ENTRY_POINT, [model, _, name @ ..]) => {
+                    Some(EntryPoint {
+                        model: ExecutionModel::from_word(*model),
+                        name: literal_string(name),
+                    })
+                }
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/// Every descriptor binding declared in `spirv`, sorted by set and binding.
+pub fn reflect_bindings(spirv: &[u8]) -> Result<Vec<DescriptorBinding>, ReflectError> {
+    let words = parse_words(spirv)?;
+
+    let mut sets = HashMap::new();
+    let mut bindings = HashMap::new();
+    let mut blocks = HashMap::new();
+    let mut types = HashMap::new();
+    let mut constants = HashMap::new();
+    let mut variables = Vec::new();
+
+    for (opcode, operands) in instructions(&words)? {
+        match (opcode, operands) {
+            (OP_DECORATE, [target, DECORATION_DESCRIPTOR_SET, set, ..]) => {
+                sets.insert(*target, *set);
+            }
+            (OP_DECORATE, [target, DECORATION_BINDING, binding, ..]) => {
+                bindings.insert(*target, *binding);
+            }
+            (OP_DECORATE, [target, decoration @ DECORATION_BLOCK, ..])
+            | (OP_DECORATE, [target, decoration @ DECORATION_BUFFER_BLOCK, ..]) => {
+                blocks.insert(*target, *decoration);
+            }
+            (OP_TYPE_SAMPLER, [id, ..]) => {
+                types.insert(*id, Type::Sampler);
+            }
+            (OP_TYPE_IMAGE, [id, _, dim, _, _, _, sampled, ..]) => {
+                types.insert(
+                    *id,
+                    Type::Image {
+                        dim: *dim,
+                        sampled: *sampled,
+                    },
+                );
+            }
+            (OP_TYPE_SAMPLED_IMAGE, [id, ..]) => {
+                types.insert(*id, Type::SampledImage);
+            }
+            (OP_TYPE_ACCELERATION_STRUCTURE, [id, ..]) => {
+                types.insert(*id, Type::AccelerationStructure);
+            }
+            (OP_TYPE_STRUCT, [id, ..]) => {
+                types.insert(*id, Type::Struct);
+            }
+            (OP_TYPE_ARRAY, [id, element, length, ..]) => {
+                types.insert(
+                    *id,
+                    Type::Array {
+                        element: *element,
+                        length: *length,
+                    },
+                );
+            }
+            (OP_TYPE_RUNTIME_ARRAY, [id, element, ..]) => {
+                types.insert(*id, Type::RuntimeArray { element: *element });
+            }
+            (OP_TYPE_POINTER, [id, storage_class, pointee, ..]) => {
+                types.insert(
+                    *id,
+                    Type::Pointer {
+                        storage_class: *storage_class,
+                        pointee: *pointee,
+                    },
+                );
+            }
+            (OP_CONSTANT, [_, id, value, ..]) => {
+                constants.insert(*id, *value);
+            }
+            (OP_VARIABLE, [ty, id, storage_class, ..]) => {
+                if matches!(
+                    *storage_class,
+                    STORAGE_CLASS_UNIFORM_CONSTANT
+                        | STORAGE_CLASS_UNIFORM
+                        | STORAGE_CLASS_STORAGE_BUFFER
+                ) {
+                    variables.push((*id, *ty));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut result = Vec::new();
+    for (variable, ty) in variables {
+        let (set, binding) = match (sets.get(&variable), bindings.get(&variable)) {
+            (Some(set), Some(binding)) => (*set, *binding),
+            _ => continue,
+        };
+        let (storage_class, mut ty) = match types.get(&ty) {
+            Some(Type::Pointer {
+                storage_class,
+                pointee,
+            }) => (*storage_class, *pointee),
+            _ => return Err(ReflectError::UnresolvedType { variable }),
+        };
+
+        let mut count = Some(1);
+        loop {
+            match types.get(&ty) {
+                Some(Type::Array { element, length }) => {
+                    let length = constants
+                        .get(length)
+                        .ok_or(ReflectError::UnresolvedType { variable })?;
+                    count = count.map(|c| c * length);
+                    ty = *element;
+                }
+                Some(Type::RuntimeArray { element }) => {
+                    count = None;
+                    ty = *element;
+                }
+                _ => break,
+            }
+        }
+
+        let kind = match (types.get(&ty), storage_class) {
+            (Some(Type::Struct), STORAGE_CLASS_STORAGE_BUFFER) => DescriptorKind::StorageBuffer,
+            (Some(Type::Struct), STORAGE_CLASS_UNIFORM) => {
+                match blocks.get(&ty) {
+                    Some(&DECORATION_BUFFER_BLOCK) => DescriptorKind::StorageBuffer,
+                    _ => DescriptorKind::UniformBuffer,
+                }
+            }
+            (Some(Type::Sampler), _) => DescriptorKind::Sampler,
+            (Some(Type::SampledImage), _) => DescriptorKind::CombinedImageSampler,
+            (Some(Type::AccelerationStructure), _) => DescriptorKind::AccelerationStructure,
+            (Some(Type::Image { dim, sampled }), _) => {
+                match (*dim, *sampled) {
+                    (DIM_BUFFER, IMAGE_SAMPLED_STORAGE) => DescriptorKind::StorageTexelBuffer,
+                    (DIM_BUFFER, _) => DescriptorKind::UniformTexelBuffer,
+                    (_, IMAGE_SAMPLED_STORAGE) => DescriptorKind::StorageImage,
+                    _ => DescriptorKind::SampledImage,
+                }
+            }
+            _ => return Err(ReflectError::UnresolvedType { variable }),
+        };
+
+        result.push(DescriptorBinding {
+            set,
+            binding,
+            kind,
+            count,
+        });
+    }
+    result.sort_by_key(|b| (b.set, b.binding));
+    result.dedup();
+    Ok(result)
+}
+
+/// Describes one host side descriptor set layout in the same terms as the
+/// reflected bindings.
+pub fn host_bindings(
+    set: u32,
+    layout_bindings: &[maligog::DescriptorSetLayoutBinding],
+) -> Vec<DescriptorBinding> {
+    layout_bindings
+        .iter()
+        .filter_map(|b| {
+            DescriptorKind::from_host(&b.descriptor_type).map(|kind| {
+                DescriptorBinding {
+                    set,
+                    binding: b.binding,
+                    kind,
+                    count: match b.variable_count {
+                        true => None,
+                        false => Some(b.descriptor_count),
+                    },
+                }
+            })
+        })
+        .collect()
+}
+
+/// Checks that every binding the shader uses exists in the host layout with
+/// the same descriptor type and at least as many descriptors. Host bindings
+/// the shader does not touch are fine.
+pub fn validate_layout(
+    shader: &[DescriptorBinding],
+    host: &[DescriptorBinding],
+) -> Result<(), LayoutError> {
+    let mut mismatches = Vec::new();
+    for s in shader {
+        let h = match host
+            .iter()
+            .find(|h| h.set == s.set && h.binding == s.binding)
+        {
+            Some(h) => h,
+            None => {
+                mismatches.push(LayoutMismatch::Missing(*s));
+                continue;
+            }
+        };
+        if h.kind != s.kind {
+            mismatches.push(LayoutMismatch::WrongKind {
+                shader: *s,
+                host: *h,
+            });
+            continue;
+        }
+        let enough = match (s.count, h.count) {
+            (Some(s_count), Some(h_count)) => h_count >= s_count,
+            (Some(_), None) => true,
+            // a runtime array in the shader accepts whatever the host provides
+            (None, _) => true,
+        };
+        if !enough {
+            mismatches.push(LayoutMismatch::TooFew {
+                shader: *s,
+                host: *h,
+            });
+        }
+    }
+    match mismatches.is_empty() {
+        true => Ok(()),
+        false => Err(LayoutError { mismatches }),
+    }
+}
+
+/// Reflects `spirv` and validates it against `host`, flattening both failure
+/// modes into a printable message.
+pub fn check_module(spirv: &[u8], host: &[DescriptorBinding]) -> Result<(), String> {
+    let shader = reflect_bindings(spirv).map_err(|e| e.to_string())?;
+    for binding in &shader {
+        log::debug!("reflected {}", binding);
+    }
+    validate_layout(&shader, host).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hand assembled with a `main` ray generation and a `sky_miss` miss entry
+    // point, and one binding of every kind the renderer uses:
+    //   set 0 binding 0: acceleration structure
+    //   set 0 binding 1: StorageBuffer struct { u32[] }
+    //   set 0 binding 6: sampler[]
+    //   set 0 binding 7: image2d[4]
+    //   set 1 binding 2: sampler
+    //   set 1 binding 3: r32f storage image
+    //   set 2 binding 0: rgba8 sampled image
+    const BINDINGS_SPV: &[u8] = include_bytes!("../../tests/spirv/bindings.spv");
+
+    fn binding(
+        set: u32,
+        binding: u32,
+        kind: DescriptorKind,
+        count: Option<u32>,
+    ) -> DescriptorBinding {
+        DescriptorBinding {
+            set,
+            binding,
+            kind,
+            count,
+        }
+    }
+
+    #[test]
+    fn reflects_every_binding() {
+        let reflected = reflect_bindings(BINDINGS_SPV).unwrap();
+        assert_eq!(
+            reflected,
+            vec![
+                binding(0, 0, DescriptorKind::AccelerationStructure, Some(1)),
+                binding(0, 1, DescriptorKind::StorageBuffer, Some(1)),
+                binding(0, 6, DescriptorKind::Sampler, None),
+                binding(0, 7, DescriptorKind::SampledImage, Some(4)),
+                binding(1, 2, DescriptorKind::Sampler, Some(1)),
+                binding(1, 3, DescriptorKind::StorageImage, Some(1)),
+                binding(2, 0, DescriptorKind::SampledImage, Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reflects_entry_points() {
+        let entry_points = reflect_entry_points(BINDINGS_SPV).unwrap();
+        assert_eq!(
+            entry_points,
+            vec![
+                EntryPoint {
+                    model: ExecutionModel::RayGeneration,
+                    name: "main".to_owned(),
+                },
+                EntryPoint {
+                    model: ExecutionModel::Miss,
+                    name: "sky_miss".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert_eq!(reflect_bindings(&[0; 8]), Err(ReflectError::TooShort));
+        assert_eq!(reflect_bindings(&[0; 20]), Err(ReflectError::BadMagic(0)));
+        let truncated = &BINDINGS_SPV[..BINDINGS_SPV.len() - 4];
+        assert!(matches!(
+            reflect_bindings(truncated),
+            Err(ReflectError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn validates_against_host_layout() {
+        let shader = reflect_bindings(BINDINGS_SPV).unwrap();
+        let mut host = vec![
+            binding(0, 0, DescriptorKind::AccelerationStructure, Some(1)),
+            binding(0, 1, DescriptorKind::StorageBuffer, Some(1)),
+            binding(0, 6, DescriptorKind::Sampler, Some(500)),
+            binding(0, 7, DescriptorKind::SampledImage, Some(500)),
+            binding(1, 0, DescriptorKind::StorageImage, Some(1)),
+            binding(1, 2, DescriptorKind::Sampler, Some(1)),
+            binding(1, 3, DescriptorKind::StorageImage, Some(1)),
+            binding(2, 0, DescriptorKind::SampledImage, Some(1)),
+        ];
+        assert_eq!(validate_layout(&shader, &host), Ok(()));
+
+        host.retain(|b| !(b.set == 1 && b.binding == 3));
+        host[1].kind = DescriptorKind::UniformBuffer;
+        host[3].count = Some(2);
+        let err = validate_layout(&shader, &host).unwrap_err();
+        assert_eq!(
+            err.mismatches,
+            vec![
+                LayoutMismatch::WrongKind {
+                    shader: shader[1],
+                    host: host[1],
+                },
+                LayoutMismatch::TooFew {
+                    shader: shader[3],
+                    host: host[3],
+                },
+                LayoutMismatch::Missing(shader[5]),
+            ]
+        );
+    }
+}
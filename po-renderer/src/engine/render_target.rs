@@ -0,0 +1,213 @@
+//! Owned images the scene passes can render into in place of a swapchain
+//! image, and a windowless setup of the passes and Po for thumbnails,
+//! automated screenshots, previews and offline renders.
+
+use std::path::Path;
+
+use maligog::Device;
+
+use super::po::{Po, RenderResult, RenderSettings};
+use super::scene_pass::{self, ScenePass};
+use super::Camera;
+use super::LoadError;
+
+pub struct RenderTarget {
+    device: Device,
+    image: maligog::Image,
+    view: maligog::ImageView,
+}
+
+impl RenderTarget {
+    /// The swapchain format, which the wireframe render pass is built for.
+    pub const FORMAT: maligog::Format = maligog::Format::B8G8R8A8_UNORM;
+
+    pub fn new(device: &Device, width: u32, height: u32) -> Self {
+        let image = device.create_image(
+            Some("render target"),
+            Self::FORMAT,
+            width,
+            height,
+            maligog::ImageUsageFlags::COLOR_ATTACHMENT
+                | maligog::ImageUsageFlags::TRANSFER_DST
+                | maligog::ImageUsageFlags::TRANSFER_SRC,
+            maligog::MemoryLocation::GpuOnly,
+        );
+        let view = image.create_view();
+        Self {
+            device: device.clone(),
+            image,
+            view,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    pub fn image(&self) -> &maligog::Image {
+        &self.image
+    }
+
+    pub fn view(&self) -> &maligog::ImageView {
+        &self.view
+    }
+
+    /// Copies the target to the host. Blocks until the GPU is done.
+    pub fn read_back(&self) -> image::RgbaImage {
+        let buffer = self.device.create_buffer(
+            Some("render target readback"),
+            self.image.linear_size(),
+            maligog::BufferUsageFlags::empty(),
+            maligog::MemoryLocation::GpuToCpu,
+        );
+        let mut cmd_buf = self.device.create_command_buffer(
+            Some("render target readback"),
+            self.device.graphics_queue_family_index(),
+        );
+        cmd_buf.encode(|rec| {
+            rec.copy_image_to_buffer(
+                &self.image,
+                maligog::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                &buffer,
+            );
+        });
+        self.device.graphics_queue().submit_blocking(&[cmd_buf]);
+        let mut memory = buffer.lock_memory().unwrap();
+        let bgra = memory.mapped_slice().unwrap();
+        let mut rgba = Vec::with_capacity(bgra.len());
+        for texel in bgra.chunks_exact(4) {
+            rgba.extend_from_slice(&[texel[2], texel[1], texel[0], texel[3]]);
+        }
+        image::RgbaImage::from_raw(self.width(), self.height(), rgba).unwrap()
+    }
+
+    /// Reads the target back and encodes it in the format implied by the
+    /// extension of `path`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        self.read_back()
+            .save(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+/// Records `pass` drawing `scene` into `target` and waits for it.
+pub fn render_pass_to(
+    device: &Device,
+    pass: &mut dyn ScenePass,
+    scene: &maligog_gltf::Scene,
+    camera: &Camera,
+    skymap: &maligog::ImageView,
+    target: &RenderTarget,
+) {
+    let mut cmd_buf = device.create_command_buffer(
+        Some("offscreen cmd buf"),
+        device.graphics_queue_family_index(),
+    );
+    pass.prepare_scene(scene);
+    cmd_buf.encode(|rec| {
+        pass.execute(
+            rec,
+            target.view(),
+            camera,
+            Some(maligog::ClearColorValue {
+                float32: [1.0, 1.0, 1.0, 1.0],
+            }),
+            skymap,
+        );
+    });
+    device.graphics_queue().submit_blocking(&[cmd_buf]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pass {
+    Wireframe,
+    RayTracing,
+}
+
+/// The interactive passes and Po on a device without a surface.
+pub struct Offscreen {
+    device: Device,
+    skymap: maligog::Image,
+    skymap_view: maligog::ImageView,
+    pub wireframe: scene_pass::Wireframe,
+    pub ray_tracing: scene_pass::RayTracing,
+    pub po: Po,
+}
+
+impl Offscreen {
+    /// `width` and `height` size the ray tracing pass's own images, which
+    /// are scaled to the target when blitting. Fails like [`super::Engine::new`].
+    pub fn new(
+        width: u32,
+        height: u32,
+        adapter: &super::adapter::Preference,
+    ) -> Result<Self, String> {
+        let entry = maligog::Entry::new().unwrap();
+        let instance = entry.create_instance(&[], &[]);
+        let device =
+            super::adapter::select(&instance, super::adapter::RAY_TRACING_EXTENSIONS, adapter)?
+                .create_device();
+        let pipeline_cache = device.create_pipeline_cache(&[]);
+        let skymap = super::load_skymap(&device, None).unwrap();
+        let skymap_view = skymap.create_view();
+        let wireframe = scene_pass::Wireframe::new(&device, &pipeline_cache)?;
+        let ray_tracing = scene_pass::RayTracing::new(&device, &pipeline_cache, width, height, 1)?;
+        let po = Po::new(&device, &pipeline_cache)?;
+        Ok(Self {
+            device,
+            skymap,
+            skymap_view,
+            wireframe,
+            ray_tracing,
+            po,
+        })
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn load_scene(&self, path: &Path) -> Result<maligog_gltf::Scene, LoadError> {
+        super::load_scene(&self.device, path)
+    }
+
+    pub fn set_skymap(&mut self, path: &Path) -> Result<(), LoadError> {
+        self.skymap = super::load_skymap(&self.device, Some(path))?;
+        self.skymap_view = self.skymap.create_view();
+        Ok(())
+    }
+
+    pub fn create_target(&self, width: u32, height: u32) -> RenderTarget {
+        RenderTarget::new(&self.device, width, height)
+    }
+
+    pub fn render(
+        &mut self,
+        pass: Pass,
+        scene: &maligog_gltf::Scene,
+        camera: &Camera,
+        target: &RenderTarget,
+    ) {
+        let pass: &mut dyn ScenePass = match pass {
+            Pass::Wireframe => &mut self.wireframe,
+            Pass::RayTracing => &mut self.ray_tracing,
+        };
+        render_pass_to(&self.device, pass, scene, camera, &self.skymap_view, target);
+    }
+
+    /// Renders `scene` through Po from the camera of `settings` under the
+    /// skymap, see [`Po::render`].
+    pub fn render_po(
+        &mut self,
+        settings: &RenderSettings,
+        scene: &maligog_gltf::Scene,
+    ) -> Vec<RenderResult> {
+        self.po
+            .render(settings, scene, &self.skymap_view, &settings.camera)
+    }
+}
@@ -0,0 +1,168 @@
+//! Cameras authored in the glTF file. `maligog_gltf` only uploads geometry,
+//! so they are read from the document like the scene bounds.
+
+use std::path::Path;
+
+use glam::{Mat4, Vec3};
+
+use super::bounds::visit_nodes;
+use super::{Camera, LoadError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective {
+        yfov: f32,
+        /// The viewport's when unset.
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        /// Infinite when unset.
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneCamera {
+    pub name: String,
+    pub projection: Projection,
+    /// World transform of the node, the camera looks down its -Z axis.
+    pub transform: Mat4,
+}
+
+impl SceneCamera {
+    pub fn from_file(path: &Path) -> Result<Vec<Self>, LoadError> {
+        let gltf = gltf::Gltf::open(path).map_err(|e| LoadError::from_gltf(path, e))?;
+        Ok(Self::from_document(&gltf.document))
+    }
+
+    /// Every node with a camera in the default scene, named after the node
+    /// or else the camera.
+    pub fn from_document(document: &gltf::Document) -> Vec<Self> {
+        let mut cameras = Vec::new();
+        visit_nodes(document, &mut |node, transform| {
+            if let Some(camera) = node.camera() {
+                let projection = match camera.projection() {
+                    gltf::camera::Projection::Perspective(p) => {
+                        Projection::Perspective {
+                            yfov: p.yfov(),
+                            aspect_ratio: p.aspect_ratio(),
+                            znear: p.znear(),
+                            zfar: p.zfar(),
+                        }
+                    }
+                    gltf::camera::Projection::Orthographic(o) => {
+                        Projection::Orthographic {
+                            xmag: o.xmag(),
+                            ymag: o.ymag(),
+                            znear: o.znear(),
+                            zfar: o.zfar(),
+                        }
+                    }
+                };
+                cameras.push(SceneCamera {
+                    name: node
+                        .name()
+                        .or_else(|| camera.name())
+                        .map(|name| name.to_owned())
+                        .unwrap_or_else(|| format!("camera {}", camera.index())),
+                    projection,
+                    transform,
+                });
+            }
+        });
+        cameras
+    }
+
+    /// The first camera called `name`.
+    pub fn find<'a>(cameras: &'a [SceneCamera], name: &str) -> Option<&'a SceneCamera> {
+        cameras.iter().find(|camera| camera.name == name)
+    }
+
+    pub fn location(&self) -> Vec3 {
+        self.transform.transform_point3(Vec3::ZERO)
+    }
+
+    pub fn front(&self) -> Vec3 {
+        self.transform.transform_vector3(-Vec3::Z).normalize()
+    }
+
+    /// A viewer camera at this one. `Camera` has no roll, a rolled camera
+    /// comes out level. An orthographic camera keeps its height, `xmag`
+    /// gives way to the viewport's aspect ratio.
+    pub fn camera(&self, aspect_ratio: f32) -> Result<Camera, String> {
+        let front = self.front();
+        let mut camera = Camera::from_angles(
+            self.location(),
+            front.z.atan2(front.x),
+            front.y.clamp(-1.0, 1.0).asin(),
+            aspect_ratio,
+            std::f32::consts::FRAC_PI_3,
+        );
+        match self.projection {
+            Projection::Perspective {
+                yfov, znear, zfar, ..
+            } => {
+                camera.fov = yfov;
+                camera.near = znear;
+                // the depth range ratio of the default planes
+                camera.far = zfar.unwrap_or(znear * 1e7);
+            }
+            Projection::Orthographic {
+                ymag, znear, zfar, ..
+            } => {
+                if ymag <= 0.0 || zfar <= znear {
+                    return Err(format!("{} has an empty orthographic view", self.name));
+                }
+                camera.projection = super::camera::Projection::Orthographic { height: 2.0 * ymag };
+                camera.near = znear;
+                camera.far = zfar;
+            }
+        }
+        Ok(camera)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scenes": [{ "nodes": [0, 1] }],
+        "nodes": [
+            { "name": "Shot_010", "camera": 0, "translation": [0, 1, 5], "rotation": [0, 0.7071068, 0, 0.7071068] },
+            { "camera": 1 }
+        ],
+        "cameras": [
+            { "type": "perspective", "perspective": { "yfov": 0.8, "znear": 0.1, "zfar": 100 } },
+            { "name": "top", "type": "orthographic", "orthographic": { "xmag": 1, "ymag": 1, "znear": 0, "zfar": 10 } }
+        ]
+    }"#;
+
+    #[test]
+    fn reads_node_cameras() {
+        let gltf = gltf::Gltf::from_slice(DOCUMENT.as_bytes()).unwrap();
+        let cameras = SceneCamera::from_document(&gltf.document);
+        assert_eq!(cameras.len(), 2);
+        assert_eq!(cameras[1].name, "top");
+
+        let shot = SceneCamera::find(&cameras, "Shot_010").unwrap();
+        let camera = shot.camera(1.5).unwrap();
+        assert!((camera.location - Vec3::new(0.0, 1.0, 5.0)).length() < 1e-5);
+        // a quarter turn about +Y takes -Z to -X
+        assert!((camera.front - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-5);
+        assert_eq!((camera.fov, camera.near, camera.far), (0.8, 0.1, 100.0));
+
+        let top = cameras[1].camera(1.5).unwrap();
+        assert_eq!(
+            top.projection,
+            super::super::camera::Projection::Orthographic { height: 2.0 }
+        );
+        assert_eq!((top.near, top.far), (0.0, 10.0));
+    }
+}
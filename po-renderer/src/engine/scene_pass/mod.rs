@@ -16,5 +16,14 @@ pub trait ScenePass {
 
     fn update(&mut self);
 
+    /// Selects the resources of frame slot `frame` for the following
+    /// `execute`. The GPU has finished the slot's previous submission.
+    fn begin_frame(&mut self, _frame: usize) {}
+
+    /// Called when the output size changes, before the next `execute`.
+    fn resize(&mut self, _width: u32, _height: u32) {}
+
+    fn shader(&self) -> &super::util::ShaderWatcher;
+
     fn prepare_scene(&mut self, scene: &maligog_gltf::Scene);
 }
@@ -1,24 +1,21 @@
-use std::time::Duration;
+use std::collections::HashMap;
 
 use bytemuck::{Pod, Zeroable};
 use maligog::vk;
 use maligog::Device;
-use maligog::ShaderBindingTables;
 use maplit::btreemap;
 
-use crate::Vec3;
+use crate::engine::integrator::{EntryPoints, IntegratorOptions, ShadingVariant};
+use crate::engine::po::{CameraInfo, RenderInfo};
+use crate::engine::{reflect, util};
 
-use crate::engine::util;
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, Zeroable, Pod)]
-pub struct CameraInfo {
-    view_inv: glam::Mat4,
-    proj_inv: glam::Mat4,
-}
+/// Rays per pixel through a lens with an open aperture, each frame.
+const LENS_SAMPLES: u32 = 8;
 
+// The raygen and hit shaders read these, never the host.
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Zeroable, Pod)]
+#[allow(dead_code)]
 pub struct MaterialInfo {
     base_color_factor: glam::Vec4,
     has_base_color_texture: u32,
@@ -32,6 +29,7 @@ pub struct MaterialInfo {
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
+#[allow(dead_code)]
 pub struct GeometryInfo {
     pub index_offset: u64,
     pub vertex_offset: u64,
@@ -44,20 +42,41 @@ pub struct GeometryInfo {
     pub has_tex_coord: u32,
 }
 
+/// The resources `execute` writes, one set per frame in flight so recording
+/// a frame never touches images or descriptor sets the GPU may still read.
+struct FrameData {
+    color_image: maligog::Image,
+    /// Written by the shaders and read through the image descriptor set.
+    #[allow(dead_code)]
+    ao_image: maligog::Image,
+    render_info_buffer: maligog::Buffer,
+    image_descriptor_set: maligog::DescriptorSet,
+    skymap_descriptor_set: maligog::DescriptorSet,
+}
+
 pub struct RayTracing {
-    pipeline: maligog::RayTracingPipeline,
-    rx: crossbeam::channel::Receiver<Vec<u8>>,
+    pipelines: HashMap<EntryPoints, maligog::RayTracingPipeline>,
+    module: maligog::ShaderModule,
+    spirv: Vec<u8>,
+    shader: util::ShaderWatcher,
+    entry_points: EntryPoints,
+    options: IntegratorOptions,
+    host_layout: Vec<reflect::DescriptorBinding>,
     device: Device,
     pipeline_layout: maligog::PipelineLayout,
-    color_image: maligog::Image,
-    ao_image: maligog::Image,
+    pipeline_cache: maligog::PipelineCache,
+    frames: Vec<FrameData>,
+    frame: usize,
+    /// Counts the frames, so each one samples the lens and paths anew.
+    seed: u32,
+    /// The sets are allocated from it, kept as long as they are.
+    #[allow(dead_code)]
     descriptor_pool: maligog::DescriptorPool,
+    #[allow(dead_code)]
     as_descriptor_set_layout: maligog::DescriptorSetLayout,
-    image_descriptor_set: maligog::DescriptorSet,
     as_descriptor_set: maligog::DescriptorSet,
+    #[allow(dead_code)]
     skymap_descriptor_set_layout: maligog::DescriptorSetLayout,
-    skymap_descriptor_set: maligog::DescriptorSet,
-    descriptor_helper: crate::engine::DescriptorHelper,
     scene: Option<maligog_gltf::Scene>,
     geometry_infos: Vec<GeometryInfo>,
     geometry_info_offsets: Vec<u32>,
@@ -67,8 +86,13 @@ pub struct RayTracing {
 }
 
 impl RayTracing {
-    pub fn new(device: &Device, width: u32, height: u32) -> Self {
-        let descriptor_helper = crate::engine::DescriptorHelper::new(device);
+    pub fn new(
+        device: &Device,
+        pipeline_cache: &maligog::PipelineCache,
+        width: u32,
+        height: u32,
+        frames_in_flight: usize,
+    ) -> Result<Self, String> {
         let sky_sampler = device.create_sampler(
             Some("sky"),
             maligog::Filter::LINEAR,
@@ -79,128 +103,138 @@ impl RayTracing {
         log::debug!("creating image descriptor set layout");
 
         // descriptor set 1
-        let image_descriptor_set_layout = device.create_descriptor_set_layout(
-            Some("ray tracing image"),
-            &[
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 0,
-                    descriptor_type: maligog::DescriptorType::StorageImage,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 1,
-                    descriptor_type: maligog::DescriptorType::StorageImage,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 2,
-                    descriptor_type: maligog::DescriptorType::Sampler(Some(sky_sampler)),
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-            ],
-        );
+        let image_bindings = [
+            maligog::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: maligog::DescriptorType::StorageImage,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: maligog::DescriptorType::StorageImage,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 2,
+                descriptor_type: maligog::DescriptorType::Sampler(Some(sky_sampler)),
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 4,
+                descriptor_type: maligog::DescriptorType::UniformBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+        ];
+        let image_descriptor_set_layout =
+            device.create_descriptor_set_layout(Some("ray tracing image"), &image_bindings);
         log::debug!("creating as descriptor set layout");
 
         // descriptor set 0
-        let as_descriptor_set_layout = device.create_descriptor_set_layout(
-            Some("ray tracing as"),
-            &[
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 0,
-                    descriptor_type: maligog::DescriptorType::AccelerationStructure,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 1,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 2,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 3,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 4,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 5,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 6,
-                    descriptor_type: maligog::DescriptorType::Sampler(None),
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 500,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 7,
-                    descriptor_type: maligog::DescriptorType::SampledImage,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 500,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 8,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 9,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-                maligog::DescriptorSetLayoutBinding {
-                    binding: 10,
-                    descriptor_type: maligog::DescriptorType::StorageBuffer,
-                    stage_flags: maligog::ShaderStageFlags::ALL,
-                    descriptor_count: 1,
-                    variable_count: false,
-                },
-            ],
-        );
-        log::debug!("creating skymap descriptor set layout");
-        let skymap_descriptor_set_layout = device.create_descriptor_set_layout(
-            Some("ray tracing skymap"),
-            &[maligog::DescriptorSetLayoutBinding {
+        let as_bindings = [
+            maligog::DescriptorSetLayoutBinding {
                 binding: 0,
+                descriptor_type: maligog::DescriptorType::AccelerationStructure,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 2,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 3,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 4,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 5,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 6,
+                descriptor_type: maligog::DescriptorType::Sampler(None),
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 500,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 7,
                 descriptor_type: maligog::DescriptorType::SampledImage,
-                stage_flags: maligog::ShaderStageFlags::MISS_KHR,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 500,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 8,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
                 descriptor_count: 1,
                 variable_count: false,
-            }],
-        );
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 9,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+            maligog::DescriptorSetLayoutBinding {
+                binding: 10,
+                descriptor_type: maligog::DescriptorType::StorageBuffer,
+                stage_flags: maligog::ShaderStageFlags::ALL,
+                descriptor_count: 1,
+                variable_count: false,
+            },
+        ];
+        let as_descriptor_set_layout =
+            device.create_descriptor_set_layout(Some("ray tracing as"), &as_bindings);
+        log::debug!("creating skymap descriptor set layout");
+        let skymap_bindings = [maligog::DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: maligog::DescriptorType::SampledImage,
+            stage_flags: maligog::ShaderStageFlags::MISS_KHR,
+            descriptor_count: 1,
+            variable_count: false,
+        }];
+        let skymap_descriptor_set_layout =
+            device.create_descriptor_set_layout(Some("ray tracing skymap"), &skymap_bindings);
+        let host_layout = [
+            reflect::host_bindings(0, &as_bindings),
+            reflect::host_bindings(1, &image_bindings),
+            reflect::host_bindings(2, &skymap_bindings),
+        ]
+        .concat();
         let pipeline_layout = device.create_pipeline_layout(
             Some("ray tracing"),
             &[
@@ -211,75 +245,41 @@ impl RayTracing {
             &[maligog::PushConstantRange::builder()
                 .offset(0)
                 .size(std::mem::size_of::<CameraInfo>() as u32)
-                .stage_flags(
-                    maligog::ShaderStageFlags::RAYGEN_KHR
-                        | maligog::ShaderStageFlags::CLOSEST_HIT_KHR,
-                )
+                .stage_flags(maligog::ShaderStageFlags::RAYGEN_KHR)
                 .build()],
         );
-        let (tx, rx) = crossbeam::channel::bounded(1);
-        let builder = util::spirv_builder("./shaders/ray-tracing");
-        let tx1 = tx.clone();
-        let result = builder
-            .watch(move |result| {
-                crate::engine::util::handle_shader_compile(result, &tx1);
-            })
-            .unwrap();
-        crate::engine::util::handle_shader_compile(result, &tx);
-
-        let spirv = rx.recv().unwrap();
+        let (shader, spirv) =
+            util::ShaderWatcher::new(util::ShaderSource::Bundled("ray-tracing".to_owned()))
+                .and_then(|(shader, spirv)| {
+                    reflect::check_module(&spirv, &host_layout)?;
+                    Ok((shader, spirv))
+                })
+                .map_err(|e| format!("ray tracing shader: {}", e))?;
 
         log::debug!("creating shader module");
-        let module = device.create_shader_module(spirv);
+        let module = device.create_shader_module(spirv.clone());
 
         log::debug!("creating pipeline");
-        let pipeline = Self::build_pipeline(
+        let entry_points = EntryPoints::new("main", "miss", "closest_hit");
+        let pipeline = Self::create_pipeline(
             device,
             &pipeline_layout,
-            &maligog::ShaderStage::new(&module, maligog::ShaderStageFlags::RAYGEN_KHR, "main"),
-            &[&maligog::ShaderStage::new(
-                &module,
-                maligog::ShaderStageFlags::MISS_KHR,
-                "miss",
-            )],
-            &[&maligog::TrianglesHitGroup::new(
-                &maligog::ShaderStage::new(
-                    &module,
-                    maligog::ShaderStageFlags::CLOSEST_HIT_KHR,
-                    "closest_hit",
-                ),
-                None,
-            )],
-        );
-
-        let color_image = device.create_image(
-            Some("color image"),
-            maligog::Format::R32G32B32A32_SFLOAT,
-            width,
-            height,
-            maligog::ImageUsageFlags::STORAGE
-                | maligog::ImageUsageFlags::TRANSFER_DST
-                | maligog::ImageUsageFlags::TRANSFER_SRC,
-            maligog::MemoryLocation::GpuOnly,
-        );
-        let ao_image = device.create_image(
-            Some("ao image"),
-            maligog::Format::R32_SFLOAT,
-            width,
-            height,
-            maligog::ImageUsageFlags::STORAGE,
-            maligog::MemoryLocation::GpuOnly,
+            pipeline_cache,
+            &module,
+            &entry_points,
         );
+        let mut pipelines = HashMap::new();
+        pipelines.insert(entry_points.clone(), pipeline);
 
         let descriptor_pool = device.create_descriptor_pool(
             &[
                 maligog::DescriptorPoolSize::builder()
                     .ty(vk::DescriptorType::STORAGE_IMAGE)
-                    .descriptor_count(2)
+                    .descriptor_count(2 * frames_in_flight as u32)
                     .build(),
                 maligog::DescriptorPoolSize::builder()
                     .ty(vk::DescriptorType::SAMPLED_IMAGE)
-                    .descriptor_count(2)
+                    .descriptor_count(1 + frames_in_flight as u32)
                     .build(),
                 maligog::DescriptorPoolSize::builder()
                     .ty(vk::DescriptorType::SAMPLER)
@@ -289,20 +289,49 @@ impl RayTracing {
                     .ty(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
                     .descriptor_count(1)
                     .build(),
+                maligog::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                    .descriptor_count(frames_in_flight as u32)
+                    .build(),
             ],
-            10,
+            8 + 2 * frames_in_flight as u32,
         );
 
-        log::debug!("creating image descriptor set");
-        let image_descriptor_set = device.create_descriptor_set(
-            Some("image descriptor set"),
-            &descriptor_pool,
-            &image_descriptor_set_layout,
-            btreemap! {
-                0 => maligog::DescriptorUpdate::Image(vec![color_image.create_view()]),
-                1 => maligog::DescriptorUpdate::Image(vec![ao_image.create_view()]),
-            },
-        );
+        log::debug!("creating {} sets of frame resources", frames_in_flight);
+        let frames = (0..frames_in_flight)
+            .map(|_| {
+                let (color_image, ao_image) = Self::create_images(device, width, height);
+                // written every frame by the host
+                let render_info_buffer = device.create_buffer_init(
+                    Some("render info"),
+                    bytemuck::bytes_of(&RenderInfo::zeroed()),
+                    maligog::BufferUsageFlags::UNIFORM_BUFFER,
+                    maligog::MemoryLocation::CpuToGpu,
+                );
+                let image_descriptor_set = device.create_descriptor_set(
+                    Some("image descriptor set"),
+                    &descriptor_pool,
+                    &image_descriptor_set_layout,
+                    btreemap! {
+                        0 => maligog::DescriptorUpdate::Image(vec![color_image.create_view()]),
+                        1 => maligog::DescriptorUpdate::Image(vec![ao_image.create_view()]),
+                        4 => maligog::DescriptorUpdate::Buffer(vec![maligog::BufferView { buffer: render_info_buffer.clone(), offset: 0 }]),
+                    },
+                );
+                let skymap_descriptor_set = device.allocate_descriptor_set(
+                    Some("skymap descriptor set"),
+                    &descriptor_pool,
+                    &skymap_descriptor_set_layout,
+                );
+                FrameData {
+                    color_image,
+                    ao_image,
+                    render_info_buffer,
+                    image_descriptor_set,
+                    skymap_descriptor_set,
+                }
+            })
+            .collect();
 
         log::debug!("allocating as descriptor set");
         let as_descriptor_set = device.allocate_descriptor_set(
@@ -310,12 +339,6 @@ impl RayTracing {
             &descriptor_pool,
             &as_descriptor_set_layout,
         );
-        log::debug!("allocating skymap descriptor set");
-        let skymap_descriptor_set = device.allocate_descriptor_set(
-            Some("skymap descriptor set"),
-            &descriptor_pool,
-            &skymap_descriptor_set_layout,
-        );
 
         let default_sampler = device.create_sampler(
             Some("rt default sampler"),
@@ -325,20 +348,24 @@ impl RayTracing {
             maligog::SamplerAddressMode::CLAMP_TO_EDGE,
         );
 
-        Self {
-            pipeline,
-            rx,
+        Ok(Self {
+            pipelines,
+            module,
+            spirv,
+            shader,
+            entry_points,
+            options: IntegratorOptions::default(),
+            host_layout,
             device: device.clone(),
             pipeline_layout,
-            color_image,
-            ao_image,
+            pipeline_cache: pipeline_cache.clone(),
+            frames,
+            frame: 0,
+            seed: 0,
             descriptor_pool,
             as_descriptor_set_layout,
-            image_descriptor_set,
             as_descriptor_set,
             skymap_descriptor_set_layout,
-            skymap_descriptor_set,
-            descriptor_helper,
             scene: None,
             geometry_infos: Vec::new(),
             geometry_info_offsets: Vec::new(),
@@ -355,12 +382,128 @@ impl RayTracing {
                 maligog::MemoryLocation::GpuOnly,
             ),
             default_sampler,
+        })
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        pipeline_layout: &maligog::PipelineLayout,
+        pipeline_cache: &maligog::PipelineCache,
+        module: &maligog::ShaderModule,
+        entry_points: &EntryPoints,
+    ) -> maligog::RayTracingPipeline {
+        Self::build_pipeline(
+            device,
+            pipeline_layout,
+            pipeline_cache,
+            &maligog::ShaderStage::new(
+                module,
+                maligog::ShaderStageFlags::RAYGEN_KHR,
+                &entry_points.raygen,
+            ),
+            &[&maligog::ShaderStage::new(
+                module,
+                maligog::ShaderStageFlags::MISS_KHR,
+                &entry_points.miss,
+            )],
+            &[&maligog::TrianglesHitGroup::new(
+                &maligog::ShaderStage::new(
+                    module,
+                    maligog::ShaderStageFlags::CLOSEST_HIT_KHR,
+                    &entry_points.closest_hit,
+                ),
+                None,
+            )],
+        )
+    }
+
+    /// Switches to another shader module bound to the standard ray tracing
+    /// layout. The current pipeline stays in place if the module is unusable.
+    pub fn set_integrator(
+        &mut self,
+        source: util::ShaderSource,
+        entry_points: EntryPoints,
+    ) -> Result<(), String> {
+        let (shader, spirv) = util::ShaderWatcher::new(source)?;
+        reflect::check_module(&spirv, &self.host_layout)?;
+        entry_points.check(&spirv)?;
+        log::info!("using integrator {}", shader.source());
+        self.shader = shader;
+        self.entry_points = entry_points;
+        self.replace_module(spirv);
+        Ok(())
+    }
+
+    fn create_images(device: &Device, width: u32, height: u32) -> (maligog::Image, maligog::Image) {
+        let color_image = device.create_image(
+            Some("color image"),
+            maligog::Format::R32G32B32A32_SFLOAT,
+            width,
+            height,
+            maligog::ImageUsageFlags::STORAGE
+                | maligog::ImageUsageFlags::TRANSFER_DST
+                | maligog::ImageUsageFlags::TRANSFER_SRC,
+            maligog::MemoryLocation::GpuOnly,
+        );
+        let ao_image = device.create_image(
+            Some("ao image"),
+            maligog::Format::R32_SFLOAT,
+            width,
+            height,
+            maligog::ImageUsageFlags::STORAGE,
+            maligog::MemoryLocation::GpuOnly,
+        );
+        (color_image, ao_image)
+    }
+
+    /// Drops every cached pipeline of the old module and builds the current
+    /// entry points from `spirv`.
+    fn replace_module(&mut self, spirv: Vec<u8>) {
+        self.module = self.device.create_shader_module(spirv.clone());
+        self.spirv = spirv;
+        self.pipelines.clear();
+        let pipeline = Self::create_pipeline(
+            &self.device,
+            &self.pipeline_layout,
+            &self.pipeline_cache,
+            &self.module,
+            &self.entry_points,
+        );
+        self.pipelines.insert(self.entry_points.clone(), pipeline);
+    }
+
+    /// Makes `entry_points` current, building its pipeline on first use.
+    fn select_pipeline(&mut self, entry_points: EntryPoints) -> Result<(), String> {
+        if !self.pipelines.contains_key(&entry_points) {
+            entry_points.check(&self.spirv)?;
+            log::info!("building pipeline for {:?}", entry_points);
+            let pipeline = Self::create_pipeline(
+                &self.device,
+                &self.pipeline_layout,
+                &self.pipeline_cache,
+                &self.module,
+                &entry_points,
+            );
+            self.pipelines.insert(entry_points.clone(), pipeline);
         }
+        self.entry_points = entry_points;
+        Ok(())
+    }
+
+    pub fn set_shading(&mut self, variant: ShadingVariant) -> Result<(), String> {
+        let mut entry_points = self.entry_points.clone();
+        entry_points.closest_hit = variant.closest_hit().to_owned();
+        self.select_pipeline(entry_points)
+    }
+
+    pub fn set_options(&mut self, options: IntegratorOptions) {
+        self.options = options;
     }
 
     fn build_pipeline(
         device: &Device,
         pipeline_layout: &maligog::PipelineLayout,
+        pipeline_cache: &maligog::PipelineCache,
         ray_gen_shader: &maligog::ShaderStage,
         miss_shaders: &[&maligog::ShaderStage],
         hit_groups: &[&dyn maligog::HitGroup],
@@ -372,6 +515,7 @@ impl RayTracing {
             miss_shaders,
             hit_groups,
             31,
+            Some(pipeline_cache),
         );
         pipeline
     }
@@ -383,56 +527,49 @@ impl super::ScenePass for RayTracing {
         recorder: &mut maligog::CommandRecorder,
         image_view: &maligog::ImageView,
         camera: &super::super::Camera,
-        clear_color: Option<maligog::ClearColorValue>,
+        _clear_color: Option<maligog::ClearColorValue>,
         skymap: &maligog::ImageView,
     ) {
-        let scene = self.scene.as_ref().unwrap();
-        self.as_descriptor_set.update(btreemap! {
-            0 => maligog::DescriptorUpdate::AccelerationStructure(vec![scene.tlas().clone()]),
-            1 => maligog::DescriptorUpdate::Buffer(vec![scene.index_buffer().clone()]),
-            2 => maligog::DescriptorUpdate::Buffer(vec![scene.vertex_buffer().clone()]),
-            3 => maligog::DescriptorUpdate::Buffer(vec![maligog::BufferView { buffer: self.geometry_infos_buffer.clone(), offset: 0}]),
-            4 => maligog::DescriptorUpdate::Buffer(vec![maligog::BufferView { buffer: self.geometry_info_offsets_buffer.clone(), offset: 0}]),
-            5 => maligog::DescriptorUpdate::Buffer(vec![scene.transform_buffer().clone()]),
-        });
-        self.skymap_descriptor_set.update(btreemap! {
+        let frame = &self.frames[self.frame];
+        frame.skymap_descriptor_set.update(btreemap! {
             0 => maligog::DescriptorUpdate::Image(vec![skymap.clone()]),
         });
 
         let mut hit_groups: Vec<u32> = Vec::new();
-        for i in 0..12345 {
+        for _ in 0..12345 {
             hit_groups.push(0);
         }
-        let shader_binding_tables = self.pipeline.create_shader_binding_tables(&hit_groups);
-
-        let mut camera_info = CameraInfo {
-            view_inv: glam::Mat4::look_at_lh(
-                camera.location,
-                camera.location + camera.front,
-                camera.up,
-            )
-            .inverse(),
-            proj_inv: glam::Mat4::perspective_lh(camera.fov, camera.aspect_ratio, 0.001, 10000.0)
-                .inverse(),
-        };
+        let pipeline = &self.pipelines[&self.entry_points];
+        let shader_binding_tables = pipeline.create_shader_binding_tables(&hit_groups);
+
+        let camera_info = CameraInfo::new(camera);
+        let render_info = RenderInfo::new(camera, self.options, LENS_SAMPLES, self.seed);
+        // the GPU is done with this frame's buffer, see `FrameData`
+        frame
+            .render_info_buffer
+            .lock_memory()
+            .unwrap()
+            .mapped_slice_mut()
+            .unwrap()[..std::mem::size_of::<RenderInfo>()]
+            .copy_from_slice(bytemuck::bytes_of(&render_info));
 
         recorder.clear_color_image(
-            &self.color_image,
+            &frame.color_image,
             &vk::ClearColorValue {
                 float32: [1.0, 1.0, 1.0, 1.0],
             },
         );
-        recorder.bind_ray_tracing_pipeline(&self.pipeline, |rec| {
+        recorder.bind_ray_tracing_pipeline(pipeline, |rec| {
             rec.bind_descriptor_sets(
                 vec![
                     &self.as_descriptor_set,
-                    &self.image_descriptor_set,
-                    &self.skymap_descriptor_set,
+                    &frame.image_descriptor_set,
+                    &frame.skymap_descriptor_set,
                 ],
                 0,
             );
             rec.push_constants(
-                maligog::ShaderStageFlags::RAYGEN_KHR | maligog::ShaderStageFlags::CLOSEST_HIT_KHR,
+                maligog::ShaderStageFlags::RAYGEN_KHR,
                 &bytemuck::cast_slice(&[camera_info]),
             );
             rec.trace_ray(
@@ -440,40 +577,55 @@ impl super::ScenePass for RayTracing {
                 &shader_binding_tables.miss_table(),
                 &shader_binding_tables.hit_table(),
                 &shader_binding_tables.callable_table(),
-                self.color_image.width(),
-                self.color_image.height(),
+                frame.color_image.width(),
+                frame.color_image.height(),
                 31,
             );
         });
-        util::cmd_blit_image(recorder, &self.color_image, &image_view.image());
+        util::cmd_blit_image(recorder, &frame.color_image, &image_view.image());
+    }
+
+    fn begin_frame(&mut self, frame: usize) {
+        self.frame = frame % self.frames.len();
+        self.seed = self.seed.wrapping_add(1);
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        let current = &self.frames[0].color_image;
+        if (width, height) == (current.width(), current.height()) {
+            return;
+        }
+        log::debug!("resizing ray tracing images to {}x{}", width, height);
+        for frame in &mut self.frames {
+            let (color_image, ao_image) = Self::create_images(&self.device, width, height);
+            frame.image_descriptor_set.update(btreemap! {
+                0 => maligog::DescriptorUpdate::Image(vec![color_image.create_view()]),
+                1 => maligog::DescriptorUpdate::Image(vec![ao_image.create_view()]),
+            });
+            frame.color_image = color_image;
+            frame.ao_image = ao_image;
+        }
     }
 
     fn update(&mut self) {
-        if let Ok(spirv) = self.rx.try_recv() {
+        if let Some(spirv) = self.shader.try_recv() {
+            if let Err(e) = reflect::check_module(&spirv, &self.host_layout) {
+                self.shader.reject(e);
+                return;
+            }
+            if let Err(e) = self.entry_points.check(&spirv) {
+                self.shader.reject(e);
+                return;
+            }
             log::info!("updating shader");
-            let module = self.device.create_shader_module(spirv);
-
-            self.pipeline = Self::build_pipeline(
-                &self.device,
-                &self.pipeline_layout,
-                &maligog::ShaderStage::new(&module, maligog::ShaderStageFlags::RAYGEN_KHR, "main"),
-                &[&maligog::ShaderStage::new(
-                    &module,
-                    maligog::ShaderStageFlags::MISS_KHR,
-                    "miss",
-                )],
-                &[&maligog::TrianglesHitGroup::new(
-                    &maligog::ShaderStage::new(
-                        &module,
-                        maligog::ShaderStageFlags::CLOSEST_HIT_KHR,
-                        "closest_hit",
-                    ),
-                    None,
-                )],
-            );
+            self.replace_module(spirv);
         }
     }
 
+    fn shader(&self) -> &util::ShaderWatcher {
+        &self.shader
+    }
+
     fn prepare_scene(&mut self, scene: &maligog_gltf::Scene) {
         let need_reload = self.scene.is_none() || self.scene.as_ref().unwrap() != scene;
         if need_reload {
@@ -569,6 +721,14 @@ impl super::ScenePass for RayTracing {
                 buffer: material_info_buffer,
                 offset: 0,
             };
+            self.as_descriptor_set.update(btreemap! {
+                0 => maligog::DescriptorUpdate::AccelerationStructure(vec![scene.tlas().clone()]),
+                1 => maligog::DescriptorUpdate::Buffer(vec![scene.index_buffer().clone()]),
+                2 => maligog::DescriptorUpdate::Buffer(vec![scene.vertex_buffer().clone()]),
+                3 => maligog::DescriptorUpdate::Buffer(vec![maligog::BufferView { buffer: self.geometry_infos_buffer.clone(), offset: 0}]),
+                4 => maligog::DescriptorUpdate::Buffer(vec![maligog::BufferView { buffer: self.geometry_info_offsets_buffer.clone(), offset: 0}]),
+                5 => maligog::DescriptorUpdate::Buffer(vec![scene.transform_buffer().clone()]),
+            });
             self.as_descriptor_set.update(btreemap! {
                 6 => maligog::DescriptorUpdate::Sampler(scene.samplers().to_vec()),
             });
@@ -2,10 +2,11 @@ use bytemuck::{Pod, Zeroable};
 use maligog::vk;
 use maligog::Device;
 
-use crate::engine::util;
+use crate::engine::{reflect, util};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Zeroable, Pod)]
+#[allow(dead_code)]
 struct Transform {
     model: glam::Mat4,
     view: glam::Mat4,
@@ -14,15 +15,16 @@ struct Transform {
 
 pub struct Wireframe {
     pipeline: maligog::GraphicsPipeline,
-    rx: crossbeam::channel::Receiver<Vec<u8>>,
+    shader: util::ShaderWatcher,
     device: Device,
     pipeline_layout: maligog::PipelineLayout,
+    pipeline_cache: maligog::PipelineCache,
     render_pass: maligog::RenderPass,
     scene: Option<maligog_gltf::Scene>,
 }
 
 impl Wireframe {
-    pub fn new(device: &Device) -> Self {
+    pub fn new(device: &Device, pipeline_cache: &maligog::PipelineCache) -> Result<Self, String> {
         let descriptor_set_layout = device.create_descriptor_set_layout(Some("wireframe"), &[]);
         let pipeline_layout = device.create_pipeline_layout(
             Some("wireframe"),
@@ -33,17 +35,13 @@ impl Wireframe {
                 .stage_flags(maligog::ShaderStageFlags::VERTEX)
                 .build()],
         );
-        let (tx, rx) = crossbeam::channel::bounded(1);
-        let builder = util::spirv_builder("./shaders/wireframe");
-        let tx1 = tx.clone();
-        let result = builder
-            .watch(move |result| {
-                crate::engine::util::handle_shader_compile(result, &tx1);
-            })
-            .unwrap();
-        crate::engine::util::handle_shader_compile(result, &tx);
-
-        let spirv = rx.recv().unwrap();
+        let (shader, spirv) =
+            util::ShaderWatcher::new(util::ShaderSource::Bundled("wireframe".to_owned()))
+                .and_then(|(shader, spirv)| {
+                    reflect::check_module(&spirv, &[])?;
+                    Ok((shader, spirv))
+                })
+                .map_err(|e| format!("wireframe shader: {}", e))?;
 
         let module = device.create_shader_module(spirv);
 
@@ -70,6 +68,7 @@ impl Wireframe {
         let pipeline = Self::build_pipeline(
             device,
             &pipeline_layout,
+            pipeline_cache,
             &render_pass,
             vec![
                 maligog::ShaderStage::new(&module, maligog::ShaderStageFlags::VERTEX, "main_vs"),
@@ -77,19 +76,21 @@ impl Wireframe {
             ],
         );
 
-        Self {
+        Ok(Self {
             pipeline,
-            rx,
+            shader,
             device: device.clone(),
             pipeline_layout,
+            pipeline_cache: pipeline_cache.clone(),
             render_pass,
             scene: None,
-        }
+        })
     }
 
     fn build_pipeline(
         device: &Device,
         pipeline_layout: &maligog::PipelineLayout,
+        pipeline_cache: &maligog::PipelineCache,
         render_pass: &maligog::RenderPass,
         shader_stages: Vec<maligog::ShaderStage>,
     ) -> maligog::GraphicsPipeline {
@@ -136,6 +137,7 @@ impl Wireframe {
             &vk::PipelineDynamicStateCreateInfo::builder()
                 .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
                 .build(),
+            Some(pipeline_cache),
         );
         pipeline
     }
@@ -148,17 +150,13 @@ impl super::ScenePass for Wireframe {
         image_view: &maligog::ImageView,
         camera: &super::super::Camera,
         clear_color: Option<maligog::ClearColorValue>,
-        skymap: &maligog::ImageView,
+        _skymap: &maligog::ImageView,
     ) {
         let scene = self.scene.as_ref().unwrap();
         let mut transform = Transform {
             model: glam::Mat4::IDENTITY,
-            view: glam::Mat4::look_at_lh(
-                camera.location,
-                camera.location + camera.front,
-                camera.up,
-            ),
-            projection: glam::Mat4::perspective_lh(camera.fov, camera.aspect_ratio, 0.001, 10000.0),
+            view: camera.view(),
+            projection: camera.projection_matrix(),
         };
         let framebuffer = self.device.create_framebuffer(
             self.render_pass.clone(),
@@ -234,13 +232,18 @@ impl super::ScenePass for Wireframe {
     }
 
     fn update(&mut self) {
-        if let Ok(spirv) = self.rx.try_recv() {
+        if let Some(spirv) = self.shader.try_recv() {
+            if let Err(e) = reflect::check_module(&spirv, &[]) {
+                self.shader.reject(e);
+                return;
+            }
             log::info!("updating shader");
             let module = self.device.create_shader_module(spirv);
 
             self.pipeline = Self::build_pipeline(
                 &self.device,
                 &self.pipeline_layout,
+                &self.pipeline_cache,
                 &self.render_pass,
                 vec![
                     maligog::ShaderStage::new(
@@ -258,6 +261,10 @@ impl super::ScenePass for Wireframe {
         }
     }
 
+    fn shader(&self) -> &util::ShaderWatcher {
+        &self.shader
+    }
+
     fn prepare_scene(&mut self, scene: &maligog_gltf::Scene) {
         let need_reload = self.scene.is_none() || self.scene.as_ref().unwrap() != scene;
         if need_reload {
@@ -0,0 +1,262 @@
+//! Viewer sessions: what is loaded, where the camera is and how the UI is
+//! laid out. Written as JSON on exit and restored on the next start.
+
+use std::path::{Path, PathBuf};
+
+use egui_maligog::egui;
+
+use super::integrator::{IntegratorOptions, ShadingVariant};
+use super::render_target::Pass;
+
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CameraPose {
+    pub location: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+    /// The lens and projection are missing from poses saved before the
+    /// camera had them.
+    #[serde(default)]
+    pub lens: super::camera::Lens,
+    #[serde(default)]
+    pub projection: super::camera::Projection,
+}
+
+impl CameraPose {
+    pub fn new(camera: &super::Camera) -> Self {
+        Self {
+            location: camera.location.into(),
+            yaw: camera.yaw(),
+            pitch: camera.pitch(),
+            fov: camera.fov,
+            lens: camera.lens,
+            projection: camera.projection,
+        }
+    }
+
+    /// The camera at this pose, with the aspect ratio of the current window.
+    pub fn camera(&self, aspect_ratio: f32) -> super::Camera {
+        let mut camera = super::Camera::from_angles(
+            self.location.into(),
+            self.yaw,
+            self.pitch,
+            aspect_ratio,
+            self.fov,
+        );
+        camera.lens = self.lens;
+        camera.projection = self.projection;
+        camera
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub scene: Option<PathBuf>,
+    pub skymap: Option<PathBuf>,
+    pub camera: CameraPose,
+    pub pass: Pass,
+    pub shading: ShadingVariant,
+    pub render_width: u32,
+    pub render_height: u32,
+    pub integrator: IntegratorOptions,
+    /// Window positions, sizes and collapsed states.
+    pub ui_memory: Option<egui::Memory>,
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("po-renderer").join("session.json"))
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let session: Self =
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if session.version != VERSION {
+            return Err(format!(
+                "{}: session version {}, expected {}",
+                path.display(),
+                session.version,
+                VERSION
+            ));
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        write_json(path, self)
+    }
+}
+
+/// Writes to a temporary file first so an interrupted save leaves the
+/// previous file intact.
+pub(super) fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    let text = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, text).map_err(|e| format!("{}: {}", tmp.display(), e))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+impl super::Engine {
+    pub fn session(&self) -> Session {
+        Session {
+            version: VERSION,
+            scene: self.scene_path.clone(),
+            skymap: self.skymap_path.clone(),
+            camera: CameraPose::new(&self.camera),
+            pass: self.pass,
+            shading: self.shading,
+            render_width: self.render_settings.width,
+            render_height: self.render_settings.height,
+            integrator: self.render_settings.integrator,
+            ui_memory: Some(self.ui_instance.context().memory().clone()),
+        }
+    }
+
+    pub fn save_session(&self, path: &Path) {
+        match self.session().save(path) {
+            Ok(()) => log::info!("saved session to {}", path.display()),
+            Err(e) => log::error!("cannot save session: {}", e),
+        }
+    }
+
+    /// Loads the scene and skymap of `session` and then applies its view.
+    pub fn open_session(&mut self, path: &Path) {
+        let session = match Session::load(path) {
+            Ok(session) => session,
+            Err(e) => {
+                log::error!("cannot open session: {}", e);
+                return;
+            }
+        };
+        match &session.scene {
+            Some(scene) => self.open_scene(scene),
+            None => {
+                self.frames.wait_idle();
+                self.scene = None;
+                self.scene_path = None;
+                self.scene_bounds = Default::default();
+                self.scene_cameras.clear();
+                self.reload_bookmarks();
+                self.selection = None;
+            }
+        }
+        match &session.skymap {
+            Some(skymap) => self.open_skymap(skymap),
+            None => {
+                self.skymap = super::load_skymap(&self.device, None).unwrap();
+                self.skymap_view = self.skymap.create_view();
+                self.skymap_path = None;
+            }
+        }
+        self.apply_session_view(&session);
+    }
+
+    /// Moves the camera to `pose`, keeping the window's aspect ratio and the
+    /// clip planes, which follow the scene.
+    pub(super) fn look_from_pose(&mut self, pose: &CameraPose) {
+        let mut camera = pose.camera(self.width as f32 / self.height.max(1) as f32);
+        camera.near = self.camera.near;
+        camera.far = self.camera.far;
+        self.look_from(camera);
+    }
+
+    /// Everything of `session` except the scene and skymap. The ray interval
+    /// is fitted to the current scene again rather than taken from the
+    /// session.
+    pub(super) fn apply_session_view(&mut self, session: &Session) {
+        self.look_from_pose(&session.camera);
+        self.set_pass(session.pass);
+        match self.ray_tracing.borrow_mut().set_shading(session.shading) {
+            Ok(()) => self.shading = session.shading,
+            Err(e) => log::error!("cannot switch shading: {}", e),
+        }
+        self.render_settings.width = session.render_width;
+        self.render_settings.height = session.render_height;
+        self.render_settings.integrator = session.integrator;
+        self.fit_to_scene();
+        if let Some(memory) = &session.ui_memory {
+            *self.ui_instance.context().memory() = memory.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session {
+            version: VERSION,
+            scene: Some(PathBuf::from("assets/chair.glb")),
+            skymap: None,
+            camera: CameraPose {
+                location: [1.0, 2.0, 3.0],
+                yaw: 0.5,
+                pitch: -0.25,
+                fov: 1.0,
+                lens: Default::default(),
+                projection: Default::default(),
+            },
+            pass: Pass::RayTracing,
+            shading: ShadingVariant::Normal,
+            render_width: 640,
+            render_height: 480,
+            integrator: IntegratorOptions::default(),
+            ui_memory: None,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("po-session-{}", std::process::id()))
+            .join(name)
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let path = temp_path("round-trip.json");
+        session().save(&path).unwrap();
+        let loaded = Session::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let expected = session();
+        assert_eq!(loaded.version, expected.version);
+        assert_eq!(loaded.scene, expected.scene);
+        assert_eq!(loaded.skymap, expected.skymap);
+        assert_eq!(loaded.camera, expected.camera);
+        assert_eq!(loaded.pass, expected.pass);
+        assert_eq!(loaded.shading, expected.shading);
+        assert_eq!(
+            (loaded.render_width, loaded.render_height),
+            (expected.render_width, expected.render_height)
+        );
+        assert_eq!(loaded.integrator, expected.integrator);
+        assert!(loaded.ui_memory.is_none());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let path = temp_path("version.json");
+        let mut newer = session();
+        newer.version = VERSION + 1;
+        newer.save(&path).unwrap();
+        let error = Session::load(&path).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+        assert!(
+            error.ends_with(&format!(
+                "session version {}, expected {}",
+                VERSION + 1,
+                VERSION
+            )),
+            "{}",
+            error
+        );
+    }
+}
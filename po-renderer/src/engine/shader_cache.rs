@@ -0,0 +1,108 @@
+//! Precompiled SPIR-V written by `po-renderer build-shaders`, so the viewer
+//! runs on machines without the rust-gpu toolchain.
+//!
+//! The cache is a directory holding one `<crate>.spv` per shader crate and a
+//! manifest with the content hash of each module. It is looked up in
+//! `PO_SHADER_DIR`, or a `shaders` directory next to the executable.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SHADER_CRATES: &[&str] = &["wireframe", "ray-tracing", "po"];
+pub const MANIFEST: &str = "shaders.manifest";
+
+pub fn shader_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("PO_SHADER_DIR") {
+        return PathBuf::from(dir);
+    }
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|p| p.join("shaders")))
+        .unwrap_or_else(|| PathBuf::from("shaders"))
+}
+
+pub fn module_path(name: &str) -> PathBuf {
+    shader_dir().join(format!("{}.spv", name))
+}
+
+/// 64 bit FNV-1a, stable across toolchains unlike `DefaultHasher`.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn read_manifest(dir: &Path) -> io::Result<HashMap<String, u64>> {
+    let manifest = std::fs::read_to_string(dir.join(MANIFEST))?;
+    let mut hashes = HashMap::new();
+    for line in manifest.lines().filter(|l| !l.trim().is_empty()) {
+        let mut parts = line.split_whitespace();
+        match (
+            parts.next(),
+            parts.next().map(|h| u64::from_str_radix(h, 16)),
+        ) {
+            (Some(name), Some(Ok(hash))) => {
+                hashes.insert(name.to_owned(), hash);
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed manifest line {:?}", line),
+                ));
+            }
+        }
+    }
+    Ok(hashes)
+}
+
+pub fn load(name: &str) -> io::Result<Vec<u8>> {
+    let dir = shader_dir();
+    let expected = read_manifest(&dir)?.get(name).copied().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not listed in {}", name, dir.join(MANIFEST).display()),
+        )
+    })?;
+    let module = std::fs::read(module_path(name))?;
+    let hash = content_hash(&module);
+    if hash != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}.spv has hash {:016x} but the manifest expects {:016x}, rerun build-shaders",
+                name, hash, expected
+            ),
+        ));
+    }
+    log::info!("loaded precompiled {} shader ({:016x})", name, hash);
+    Ok(module)
+}
+
+/// Compiles every shader crate and writes the modules and manifest to `out_dir`.
+#[cfg(feature = "hot-reload")]
+pub fn build_all(out_dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(out_dir)?;
+    let mut manifest = String::new();
+    for name in SHADER_CRATES {
+        let source = super::util::ShaderSource::Bundled(name.to_string());
+        let module = match super::util::compile_shader(&source) {
+            super::util::ShaderEvent::Compiled(module) => module,
+            super::util::ShaderEvent::Failed(diagnostics) => {
+                let message = diagnostics
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                return Err(io::Error::new(io::ErrorKind::Other, message));
+            }
+        };
+        std::fs::write(out_dir.join(format!("{}.spv", name)), &module)?;
+        manifest.push_str(&format!("{} {:016x}\n", name, content_hash(&module)));
+        log::info!("wrote {}.spv", name);
+    }
+    std::fs::write(out_dir.join(MANIFEST), manifest)
+}
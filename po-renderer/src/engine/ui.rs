@@ -1,8 +1,19 @@
 use egui_maligog::egui;
-use image::GenericImageView;
+
+use super::calibration::Distortion;
+use super::camera::{CameraMode, FisheyeMapping, Projection};
+use super::camera_path::Interpolation;
+use super::frame::PresentMode;
+use super::integrator::ShadingVariant;
+use super::render_target::Pass;
 
 pub enum UiMessage {
     Render,
+    SetShading(ShadingVariant),
+    SetPresentMode(PresentMode),
+    Screenshot(std::path::PathBuf),
+    /// Renders the camera path into the directory.
+    RenderPath(std::path::PathBuf),
 }
 
 impl super::Engine {
@@ -16,55 +27,96 @@ impl super::Engine {
                         if ui.button("Open Scene").clicked() {
                             match nfd2::open_file_dialog(Some("gltf,glb"), None).unwrap() {
                                 nfd2::Response::Okay(p) => {
-                                    log::info!("open {:?}", p);
-                                    self.scene = Some(maligog_gltf::Scene::from_file(
-                                        p.file_stem().map(|s| s.to_str().unwrap()),
-                                        &self.device,
-                                        &p,
-                                    ));
+                                    self.open_scene(&p);
                                 }
-                                nfd2::Response::OkayMultiple(p) => todo!(),
-                                nfd2::Response::Cancel => {}
+                                nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
                             }
                         }
                         if ui.button("Import Skymap").clicked() {
                             match nfd2::open_file_dialog(Some("jpg,jpeg"), None).unwrap() {
                                 nfd2::Response::Okay(p) => {
-                                    let img = image::open(&p).unwrap();
-                                    let img = img.into_rgba8();
-                                    self.skymap = self.device.create_image_init(
-                                        Some("skymap"),
-                                        maligog::Format::R8G8B8A8_UNORM,
-                                        img.width(),
-                                        img.height(),
-                                        maligog::ImageUsageFlags::SAMPLED,
-                                        maligog::MemoryLocation::GpuOnly,
-                                        &img.as_raw(),
-                                    );
-                                    self.skymap_view = self.skymap.create_view();
+                                    self.open_skymap(&p);
+                                }
+                                nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
+                            }
+                        }
+                        if ui.button("Load Integrator").clicked() {
+                            match nfd2::open_file_dialog(Some("toml"), None).unwrap() {
+                                nfd2::Response::Okay(p) => {
+                                    if let Err(e) = self.load_integrator(&p) {
+                                        log::error!("cannot load integrator: {}", e);
+                                    }
                                 }
-                                nfd2::Response::OkayMultiple(p) => todo!(),
-                                nfd2::Response::Cancel => {}
+                                nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
+                            }
+                        }
+                        if ui.button("Default Integrator").clicked() {
+                            self.reset_integrator();
+                        }
+                        if ui.button("Open Session").clicked() {
+                            match nfd2::open_file_dialog(Some("json"), None).unwrap() {
+                                nfd2::Response::Okay(p) => {
+                                    self.open_session(&p);
+                                }
+                                nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
+                            }
+                        }
+                        if ui.button("Save Session").clicked() {
+                            match nfd2::open_save_dialog(Some("json"), None).unwrap() {
+                                nfd2::Response::Okay(p) => {
+                                    self.save_session(&p);
+                                }
+                                nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
+                            }
+                        }
+                        if ui.button("Save Screenshot").clicked() {
+                            match nfd2::open_save_dialog(Some("png,jpg"), None).unwrap() {
+                                nfd2::Response::Okay(p) => {
+                                    msg = Some(UiMessage::Screenshot(p));
+                                }
+                                nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
                             }
                         }
                         if ui.button("Organize Windows").clicked() {
                             ui.ctx().memory().reset_areas();
                         }
                     });
+                    egui::menu::menu(ui, "View", |ui| {
+                        if ui.button("Frame All").clicked() {
+                            self.frame_all();
+                        }
+                        if ui.button("Focus Selection").clicked() {
+                            self.focus_selection();
+                        }
+                    });
                 });
             },
         );
         egui::SidePanel::left("side", 100.0).show(&self.ui_instance.context(), |ui| {
             if ui.button("Wireframe").clicked() {
-                self.scene_pass = self.wireframe.clone();
+                self.set_pass(Pass::Wireframe);
             }
             if ui.button("Ray Tracing").clicked() {
-                self.scene_pass = self.ray_tracing.clone();
+                self.set_pass(Pass::RayTracing);
             }
         });
         egui::CentralPanel::default()
             .frame(egui::Frame::default().fill(egui::Color32::from_rgb(0, 0, 0)))
             .show(&self.ui_instance.context(), |ui| {
+                let viewport = ui.interact(
+                    ui.max_rect(),
+                    egui::Id::new("viewport"),
+                    egui::Sense::click(),
+                );
+                if viewport.clicked() {
+                    if let Some(pos) = ui.input().pointer.interact_pos() {
+                        let screen = ui.ctx().input().screen_rect();
+                        self.focus_at(glam::Vec2::new(
+                            2.0 * pos.x / screen.width() - 1.0,
+                            1.0 - 2.0 * pos.y / screen.height(),
+                        ));
+                    }
+                }
                 ui.with_layout(egui::Layout::bottom_up(egui::Align::Min), |ui| {
                     let response = ui.add(
                         egui::TextEdit::multiline(&mut self.input.command)
@@ -76,7 +128,10 @@ impl super::Engine {
                         if let Some(last) = self.input.command.chars().last() {
                             if last == '\n' {
                                 response.request_focus();
-                                self.input.command.clear();
+                                let line = std::mem::take(&mut self.input.command);
+                                if let Err(e) = self.run_command(&line) {
+                                    log::error!("{}", e);
+                                }
                             }
                         }
                     }
@@ -87,19 +142,424 @@ impl super::Engine {
                         ui.label(format!("Location: {}", self.camera.location));
                         ui.label(format!("Front: {}", self.camera.front));
                         ui.label(format!("Right: {}", self.camera.right));
+                        ui.label(format!(
+                            "Yaw: {:.1}°  Pitch: {:.1}°  FOV: {:.1}°",
+                            self.camera.yaw().to_degrees(),
+                            self.camera.pitch().to_degrees(),
+                            self.camera.fov.to_degrees()
+                        ));
+                        ui.horizontal(|ui| {
+                            for mode in CameraMode::ALL.iter() {
+                                if ui.radio(self.camera_mode == *mode, mode.label()).clicked() {
+                                    self.set_camera_mode(*mode);
+                                }
+                            }
+                        });
+                        if self.camera_mode == CameraMode::Orbit {
+                            ui.label(format!("Pivot: {}", self.orbit.pivot));
+                            ui.label(format!("Distance: {:.3}", self.orbit.distance));
+                        }
+                        ui.horizontal(|ui| {
+                            if ui.button("Frame All").on_hover_text("Home").clicked() {
+                                self.frame_all();
+                            }
+                            if ui.button("Focus Selection").on_hover_text("F").clicked() {
+                                self.focus_selection();
+                            }
+                        });
+                        ui.collapsing("Projection", |ui| {
+                            if ui.button("Load Calibration").clicked() {
+                                match nfd2::open_file_dialog(Some("yml,yaml,json"), None).unwrap() {
+                                    nfd2::Response::Okay(p) => {
+                                        if let Err(e) = self.load_calibration(&p) {
+                                            log::error!("cannot load calibration: {}", e);
+                                        }
+                                    }
+                                    nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
+                                }
+                            }
+                            let camera = &mut self.camera;
+                            let choices = [
+                                Projection::Perspective,
+                                // as much as is in view at the focal plane
+                                Projection::Orthographic {
+                                    height: camera.view_height(camera.lens.focus_distance),
+                                },
+                                Projection::Equirectangular,
+                                Projection::Fisheye {
+                                    mapping: FisheyeMapping::Equidistant,
+                                    fov: std::f32::consts::PI,
+                                },
+                            ];
+                            ui.horizontal(|ui| {
+                                for choice in choices.iter() {
+                                    let selected = std::mem::discriminant(&camera.projection)
+                                        == std::mem::discriminant(choice);
+                                    if ui.radio(selected, choice.label()).clicked() && !selected {
+                                        camera.projection = *choice;
+                                    }
+                                }
+                            });
+                            match &mut camera.projection {
+                                Projection::Perspective => {
+                                    ui.horizontal(|ui| {
+                                        ui.drag_angle(&mut camera.fov);
+                                        ui.label("field of view");
+                                    });
+                                }
+                                Projection::Orthographic { height } => {
+                                    let speed = *height * 0.01;
+                                    ui.add(
+                                        egui::DragValue::new(height)
+                                            .speed(speed)
+                                            .prefix("height: "),
+                                    );
+                                    *height = height.max(f32::MIN_POSITIVE);
+                                }
+                                Projection::Equirectangular => {}
+                                Projection::Fisheye { mapping, fov } => {
+                                    ui.horizontal(|ui| {
+                                        for choice in FisheyeMapping::ALL.iter() {
+                                            if ui
+                                                .radio(*mapping == *choice, choice.label())
+                                                .clicked()
+                                            {
+                                                *mapping = *choice;
+                                            }
+                                        }
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.drag_angle(fov);
+                                        ui.label("field of view");
+                                    });
+                                    *fov = fov.clamp(0.01, 2.0 * std::f32::consts::PI);
+                                }
+                                Projection::Calibrated(intrinsics) => {
+                                    ui.label(format!(
+                                        "{}x{}, {}",
+                                        intrinsics.width,
+                                        intrinsics.height,
+                                        match intrinsics.distortion {
+                                            Distortion::BrownConrady { .. } => "Brown-Conrady",
+                                            Distortion::Fisheye { .. } => "fisheye",
+                                        }
+                                    ));
+                                    ui.label(format!(
+                                        "fx {:.1}, fy {:.1}, cx {:.1}, cy {:.1}",
+                                        intrinsics.fx, intrinsics.fy, intrinsics.cx, intrinsics.cy
+                                    ));
+                                }
+                            }
+                            if !camera.projection.is_rasterisable() {
+                                ui.label("Wireframe previews it in perspective");
+                            }
+                        });
+                        ui.collapsing("Lens", |ui| {
+                            let camera = &mut self.camera;
+                            ui.label("Click the viewport to focus");
+                            ui.add(
+                                egui::DragValue::new(&mut camera.lens.focus_distance)
+                                    .speed(camera.lens.focus_distance * 0.01)
+                                    .prefix("focus distance: "),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut camera.lens.aperture_radius)
+                                    .speed(0.0001)
+                                    .prefix("aperture radius: "),
+                            );
+                            camera.lens.aperture_radius = camera.lens.aperture_radius.max(0.0);
+                            if camera.lens.aperture_radius > 0.0 {
+                                let mut f_stop = camera.f_stop();
+                                if ui
+                                    .add(egui::DragValue::new(&mut f_stop).speed(0.1).prefix("f/"))
+                                    .changed()
+                                {
+                                    camera.set_f_stop(f_stop.max(0.5));
+                                }
+                                if ui.button("Pinhole").clicked() {
+                                    camera.lens.aperture_radius = 0.0;
+                                }
+                            } else if ui.button("Open to f/2.8").clicked() {
+                                camera.set_f_stop(2.8);
+                            }
+                            ui.add(
+                                egui::Slider::new(&mut camera.lens.blades, 0..=12).text("blades"),
+                            );
+                            ui.horizontal(|ui| {
+                                ui.drag_angle(&mut camera.lens.blade_rotation);
+                                ui.label("blade rotation");
+                            });
+                            ui.add(
+                                egui::Slider::new(&mut camera.lens.anamorphic_ratio, 0.25..=2.0)
+                                    .text("anamorphic ratio"),
+                            );
+                        });
+                        ui.collapsing("Bookmarks", |ui| {
+                            let mut recalled = None;
+                            let mut removed = None;
+                            for (i, bookmark) in self.bookmarks.bookmarks.iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    if i < 9 {
+                                        ui.label((i + 1).to_string());
+                                    }
+                                    if ui.button(&bookmark.name).clicked() {
+                                        recalled = Some(bookmark.name.clone());
+                                    }
+                                    if ui.small_button("Delete").clicked() {
+                                        removed = Some(bookmark.name.clone());
+                                    }
+                                });
+                            }
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut self.input.bookmark_name);
+                                if ui.button("Add").clicked()
+                                    && !self.input.bookmark_name.is_empty()
+                                {
+                                    let name = std::mem::take(&mut self.input.bookmark_name);
+                                    if let Err(e) = self.add_bookmark(&name) {
+                                        log::error!("cannot add bookmark: {}", e);
+                                    }
+                                }
+                            });
+                            let result = match (recalled, removed) {
+                                (Some(name), _) => self.recall_bookmark(&name),
+                                (_, Some(name)) => self.remove_bookmark(&name),
+                                _ => Ok(()),
+                            };
+                            if let Err(e) = result {
+                                log::error!("{}", e);
+                            }
+                        });
+                        ui.collapsing("Nodes", |ui| {
+                            egui::ScrollArea::from_max_height(200.0).show(ui, |ui| {
+                                for (i, node) in self.scene_bounds.nodes.iter().enumerate() {
+                                    if ui
+                                        .selectable_label(self.selection == Some(i), &node.name)
+                                        .clicked()
+                                    {
+                                        self.selection = Some(i);
+                                    }
+                                }
+                            });
+                        });
                     },
                 );
+                if !self.scene_cameras.is_empty() {
+                    let mut chosen = None;
+                    egui::Window::new("Cameras").show(&self.ui_instance.context(), |ui| {
+                        for scene_camera in &self.scene_cameras {
+                            if ui.button(&scene_camera.name).clicked() {
+                                chosen = Some(scene_camera.name.clone());
+                            }
+                        }
+                    });
+                    if let Some(name) = chosen {
+                        self.view_through(&name);
+                    }
+                }
+                egui::Window::new("Camera Path").show(&self.ui_instance.context(), |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Open").clicked() {
+                            match nfd2::open_file_dialog(Some("json"), None).unwrap() {
+                                nfd2::Response::Okay(p) => {
+                                    if let Err(e) = self.open_camera_path(&p) {
+                                        log::error!("cannot open camera path: {}", e);
+                                    }
+                                }
+                                nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
+                            }
+                        }
+                        if ui.button("Save").clicked() {
+                            match nfd2::open_save_dialog(Some("json"), None).unwrap() {
+                                nfd2::Response::Okay(p) => {
+                                    if let Err(e) = self.save_camera_path(&p) {
+                                        log::error!("cannot save camera path: {}", e);
+                                    }
+                                }
+                                nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
+                            }
+                        }
+                    });
+                    let path = &mut self.camera_path;
+                    ui.horizontal(|ui| {
+                        for choice in Interpolation::ALL.iter() {
+                            if ui
+                                .radio(path.interpolation == *choice, choice.label())
+                                .clicked()
+                            {
+                                path.interpolation = *choice;
+                            }
+                        }
+                    });
+                    ui.checkbox(&mut path.ease, "ease in and out");
+                    let mut recalled = None;
+                    let mut removed = None;
+                    let mut retimed = false;
+                    for (i, key) in path.keys.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            retimed |= ui
+                                .add(
+                                    egui::DragValue::new(&mut key.time)
+                                        .speed(0.05)
+                                        .prefix("t: "),
+                                )
+                                .changed();
+                            key.time = key.time.max(0.0);
+                            if ui.small_button("View").clicked() {
+                                recalled = Some(key.pose);
+                            }
+                            if ui.small_button("Delete").clicked() {
+                                removed = Some(i);
+                            }
+                        });
+                    }
+                    if retimed {
+                        path.sort();
+                    }
+                    if let Some(i) = removed {
+                        path.keys.remove(i);
+                    }
+                    let mut duration = path.duration();
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut duration)
+                                .speed(0.1)
+                                .prefix("duration: "),
+                        );
+                        if ui.button("Space Evenly").clicked() {
+                            path.retime(duration.max(0.0));
+                        }
+                    });
+                    ui.add(
+                        egui::DragValue::new(&mut path.fps)
+                            .speed(1.0)
+                            .prefix("fps: "),
+                    );
+                    path.fps = path.fps.max(1.0);
+                    ui.label(format!("{} frames", path.frame_count()));
+                    if let Some(pose) = recalled {
+                        self.look_from_pose(&pose);
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Add Key").clicked() {
+                            self.add_path_key();
+                        }
+                        match self.path_playhead {
+                            Some(time) => {
+                                if ui.button("Stop").clicked() {
+                                    self.stop_path();
+                                }
+                                ui.label(format!("{:.2} s", time));
+                            }
+                            None => {
+                                if ui.button("Play").clicked() {
+                                    self.play_path();
+                                }
+                            }
+                        }
+                    });
+                    if ui.button("Render Sequence").clicked() {
+                        match nfd2::open_pick_folder(None).unwrap() {
+                            nfd2::Response::Okay(p) => msg = Some(UiMessage::RenderPath(p)),
+                            nfd2::Response::OkayMultiple(_) | nfd2::Response::Cancel => {}
+                        }
+                    }
+                });
                 egui::Window::new("Stats").min_width(1600.0).show(
                     &self.ui_instance.context(),
                     |ui| {
                         ui.label(format!("Frame time: {:.2}", self.frame_time * 1000.0));
+                        ui.label(format!("Frames in flight: {}", self.frames.len()));
+                        ui.horizontal(|ui| {
+                            for mode in PresentMode::ALL.iter() {
+                                if ui.radio(self.present_mode == *mode, mode.label()).clicked() {
+                                    msg = Some(UiMessage::SetPresentMode(*mode));
+                                }
+                            }
+                        });
                     },
                 );
+                egui::Window::new("Config").show(&self.ui_instance.context(), |ui| {
+                    egui::Grid::new("config entries").show(ui, |ui| {
+                        for (key, value, origin) in self.config.entries() {
+                            ui.label(key);
+                            ui.label(value);
+                            ui.label(origin);
+                            ui.end_row();
+                        }
+                    });
+                });
                 egui::Window::new("Render").show(&self.ui_instance.context(), |ui| {
+                    let options = &mut self.render_settings.integrator;
+                    ui.add(egui::Slider::new(&mut options.max_depth, 1..=64).text("max depth"));
+                    ui.add(
+                        egui::Slider::new(&mut options.sky_intensity, 0.0..=10.0)
+                            .text("sky intensity"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut options.tmin)
+                            .speed(0.0001)
+                            .prefix("tmin: "),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut options.tmax)
+                            .speed(10.0)
+                            .prefix("tmax: "),
+                    );
+                    ui.horizontal(|ui| {
+                        for variant in ShadingVariant::ALL.iter() {
+                            if ui
+                                .radio(self.shading == *variant, variant.label())
+                                .clicked()
+                            {
+                                msg = Some(UiMessage::SetShading(*variant));
+                            }
+                        }
+                    });
                     if ui.button("Render").clicked() {
                         msg = Some(UiMessage::Render);
                     }
-                })
+                });
+                if !self.load_errors.is_empty() {
+                    let mut dismissed = None;
+                    let mut dismiss_all = false;
+                    egui::Window::new("Load Errors").show(&self.ui_instance.context(), |ui| {
+                        for (i, error) in self.load_errors.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.button("Dismiss").clicked() {
+                                    dismissed = Some(i);
+                                }
+                                ui.colored_label(egui::Color32::RED, error.to_string());
+                            });
+                        }
+                        if self.load_errors.len() > 1 && ui.button("Dismiss All").clicked() {
+                            dismiss_all = true;
+                        }
+                    });
+                    if dismiss_all {
+                        self.load_errors.clear();
+                    } else if let Some(i) = dismissed {
+                        self.load_errors.remove(i);
+                    }
+                }
+                let wireframe = self.wireframe.borrow();
+                let ray_tracing = self.ray_tracing.borrow();
+                let shaders = [wireframe.shader(), ray_tracing.shader(), self.po.shader()];
+                if shaders.iter().any(|s| !s.errors().is_empty()) {
+                    egui::Window::new("Shader Errors").show(&self.ui_instance.context(), |ui| {
+                        for shader in shaders.iter().filter(|s| !s.errors().is_empty()) {
+                            ui.horizontal(|ui| {
+                                ui.heading(shader.source().to_string());
+                                if ui.button("Retry").clicked() {
+                                    shader.retry();
+                                }
+                            });
+                            for error in shader.errors() {
+                                ui.colored_label(egui::Color32::RED, error.to_string());
+                            }
+                        }
+                    });
+                }
             });
         // egui::SidePanel::left("left panel", 500.0).show(&self.ui_instance.context(), |ui| {});
 
@@ -1,15 +1,351 @@
+use std::path::{Path, PathBuf};
+#[cfg(feature = "hot-reload")]
+use std::time::Duration;
+
 use maligog::vk;
+#[cfg(feature = "hot-reload")]
 use spirv_builder::CompileResult;
 
-pub fn handle_shader_compile(
-    compile_result: CompileResult,
-    tx: &crossbeam::channel::Sender<Vec<u8>>,
-) {
-    log::info!("shader incoming");
-    let module = std::fs::read(compile_result.module.unwrap_single()).unwrap();
-    tx.send(module).unwrap();
+use super::shader_cache;
+
+#[derive(Debug, Clone)]
+pub struct ShaderDiagnostic {
+    pub file: String,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+pub enum ShaderEvent {
+    Compiled(Vec<u8>),
+    Failed(Vec<ShaderDiagnostic>),
+}
+
+/// Where a shader module comes from.
+#[derive(Debug, Clone)]
+pub enum ShaderSource {
+    /// One of the crates under `shaders/`, compiled or loaded from the
+    /// precompiled shader cache depending on the `hot-reload` feature.
+    Bundled(String),
+    /// An external rust-gpu crate, needs the `hot-reload` feature.
+    Crate(PathBuf),
+    /// A SPIR-V module produced by any compiler.
+    Spirv(PathBuf),
+}
+
+impl std::fmt::Display for ShaderSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderSource::Bundled(name) => write!(f, "{}", name),
+            ShaderSource::Crate(path) | ShaderSource::Spirv(path) => {
+                write!(f, "{}", path.display())
+            }
+        }
+    }
+}
+
+/// Shader crates are located relative to this crate so the viewer can be
+/// started from any directory.
+pub fn shader_crate_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../shaders")
+        .join(name)
+}
+
+pub fn compile_shader(source: &ShaderSource) -> ShaderEvent {
+    match source {
+        ShaderSource::Spirv(path) => read_spirv(path),
+        #[cfg(feature = "hot-reload")]
+        ShaderSource::Bundled(name) => compile_crate(&shader_crate_path(name)),
+        #[cfg(feature = "hot-reload")]
+        ShaderSource::Crate(path) => compile_crate(path),
+        #[cfg(not(feature = "hot-reload"))]
+        ShaderSource::Bundled(name) => {
+            match shader_cache::load(name) {
+                Ok(module) => ShaderEvent::Compiled(module),
+                Err(e) => {
+                    ShaderEvent::Failed(vec![ShaderDiagnostic {
+                        file: shader_cache::module_path(name).display().to_string(),
+                        line: None,
+                        message: e.to_string(),
+                    }])
+                }
+            }
+        }
+        #[cfg(not(feature = "hot-reload"))]
+        ShaderSource::Crate(path) => {
+            ShaderEvent::Failed(vec![ShaderDiagnostic {
+                file: path.display().to_string(),
+                line: None,
+                message: "compiling shader crates needs the hot-reload feature".to_owned(),
+            }])
+        }
+    }
 }
 
+fn read_spirv(path: &Path) -> ShaderEvent {
+    let failed = |message: String| {
+        ShaderEvent::Failed(vec![ShaderDiagnostic {
+            file: path.display().to_string(),
+            line: None,
+            message,
+        }])
+    };
+    match std::fs::read(path) {
+        Ok(module) if module.len() % 4 != 0 || !module.starts_with(&[0x03, 0x02, 0x23, 0x07]) => {
+            failed("not a little endian SPIR-V module".to_owned())
+        }
+        Ok(module) => ShaderEvent::Compiled(module),
+        Err(e) => failed(e.to_string()),
+    }
+}
+
+/// rust-gpu renders the compiler errors straight to stderr, so stderr is
+/// captured during the build, echoed and parsed for them. Builds running while
+/// another one holds stderr only report that they failed.
+#[cfg(feature = "hot-reload")]
+fn compile_crate(path_to_crate: &Path) -> ShaderEvent {
+    let path_to_crate = path_to_crate.display().to_string();
+    log::info!("compiling {}", path_to_crate);
+    let capture = gag::BufferRedirect::stderr().ok();
+    let result = spirv_builder(&path_to_crate).build();
+    let mut output = String::new();
+    if let Some(mut capture) = capture {
+        std::io::Read::read_to_string(&mut capture, &mut output).ok();
+        drop(capture);
+        eprint!("{}", output);
+    }
+    match result {
+        Ok(result) => handle_shader_compile(&path_to_crate, result),
+        Err(e) => {
+            let mut diagnostics = parse_diagnostics(&path_to_crate, &output);
+            if diagnostics.is_empty() {
+                diagnostics.push(ShaderDiagnostic {
+                    file: path_to_crate.clone(),
+                    line: None,
+                    message: e.to_string(),
+                });
+            }
+            for diagnostic in &diagnostics {
+                log::error!("{}", diagnostic);
+            }
+            ShaderEvent::Failed(diagnostics)
+        }
+    }
+}
+
+#[cfg(feature = "hot-reload")]
+pub fn handle_shader_compile(path_to_crate: &str, compile_result: CompileResult) -> ShaderEvent {
+    let path = match compile_result.module {
+        spirv_builder::ModuleResult::SingleModule(path) => path,
+        spirv_builder::ModuleResult::MultiModule(_) => {
+            return ShaderEvent::Failed(vec![ShaderDiagnostic {
+                file: path_to_crate.to_owned(),
+                line: None,
+                message: "expected a single SPIR-V module".to_owned(),
+            }]);
+        }
+    };
+    match std::fs::read(&path) {
+        Ok(module) => ShaderEvent::Compiled(module),
+        Err(e) => {
+            ShaderEvent::Failed(vec![ShaderDiagnostic {
+                file: path.display().to_string(),
+                line: None,
+                message: e.to_string(),
+            }])
+        }
+    }
+}
+
+/// The errors among the rendered compiler `output` of a build of
+/// `path_to_crate`, without the summaries cargo and rustc end with.
+#[cfg(feature = "hot-reload")]
+fn parse_diagnostics(path_to_crate: &str, output: &str) -> Vec<ShaderDiagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut lines = output.lines().peekable();
+    while let Some(line) = lines.next() {
+        // `error: ...` or `error[E0425]: ...`
+        let message = match line.strip_prefix("error") {
+            Some(rest) if rest.starts_with(": ") || rest.starts_with('[') => {
+                match rest.find(": ") {
+                    Some(i) => &rest[i + 2..],
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        if message.starts_with("aborting due to") || message.starts_with("could not compile") {
+            continue;
+        }
+        // `  --> src/lib.rs:12:5`
+        let location = lines
+            .peek()
+            .and_then(|l| l.trim_start().strip_prefix("--> "))
+            .map(|l| l.rsplitn(3, ':').collect::<Vec<_>>());
+        let (file, line) = match location.as_deref() {
+            Some([_, line, file]) => (format!("{}/{}", path_to_crate, file), line.parse().ok()),
+            _ => (path_to_crate.to_owned(), None),
+        };
+        diagnostics.push(ShaderDiagnostic {
+            file,
+            line,
+            message: message.to_owned(),
+        });
+    }
+    diagnostics
+}
+
+/// Owns the compile/watch loop of one shader crate. Failed builds are kept as
+/// diagnostics while the caller keeps using the last module it received.
+///
+/// Without the `hot-reload` feature the module comes from the precompiled
+/// shader cache and is only reloaded on retry.
+///
+/// Changes and retries are requests to one compile thread, so builds of the
+/// same source never race and pending requests coalesce into one build.
+pub struct ShaderWatcher {
+    source: ShaderSource,
+    requests: crossbeam::channel::Sender<()>,
+    rx: crossbeam::channel::Receiver<ShaderEvent>,
+    errors: Vec<ShaderDiagnostic>,
+}
+
+impl ShaderWatcher {
+    fn create(source: ShaderSource) -> Result<Self, String> {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let (requests, request_rx) = crossbeam::channel::unbounded::<()>();
+        let thread_source = source.clone();
+        std::thread::spawn(move || {
+            while request_rx.recv().is_ok() {
+                request_rx.try_iter().for_each(drop);
+                if tx.send(compile_shader(&thread_source)).is_err() {
+                    break;
+                }
+            }
+        });
+        let watcher = Self {
+            source,
+            requests,
+            rx,
+            errors: Vec::new(),
+        };
+        watcher.watch()?;
+        Ok(watcher)
+    }
+
+    /// Compiles the source once, then recompiles on every change. Fails with
+    /// the diagnostics of the first compile, one per line, since there is no
+    /// module to fall back to yet.
+    pub fn new(source: ShaderSource) -> Result<(Self, Vec<u8>), String> {
+        match compile_shader(&source) {
+            ShaderEvent::Compiled(module) => Ok((Self::create(source)?, module)),
+            ShaderEvent::Failed(diagnostics) => {
+                Err(diagnostics
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+        }
+    }
+
+    #[cfg(not(feature = "hot-reload"))]
+    fn watch(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Requests a build on every change of the source.
+    #[cfg(feature = "hot-reload")]
+    fn watch(&self) -> Result<(), String> {
+        let (fs_tx, fs_rx) = std::sync::mpsc::channel();
+        let mut fs_watcher = notify::watcher(fs_tx, Duration::from_millis(300))
+            .map_err(|e| format!("{}: cannot watch: {}", self.source, e))?;
+        let paths = match &self.source {
+            // the ray tracing shader shares the camera functions of po
+            ShaderSource::Bundled(name) if name == "ray-tracing" => {
+                vec![shader_crate_path(name), shader_crate_path("po")]
+            }
+            ShaderSource::Bundled(name) => vec![shader_crate_path(name)],
+            ShaderSource::Crate(path) | ShaderSource::Spirv(path) => vec![path.clone()],
+        };
+        for path in &paths {
+            if let Err(e) =
+                notify::Watcher::watch(&mut fs_watcher, path, notify::RecursiveMode::Recursive)
+            {
+                log::warn!("not watching {}: {}", path.display(), e);
+                return Ok(());
+            }
+        }
+        let requests = self.requests.clone();
+        std::thread::spawn(move || {
+            let _fs_watcher = fs_watcher;
+            for event in fs_rx {
+                match event {
+                    notify::DebouncedEvent::Write(_)
+                    | notify::DebouncedEvent::Create(_)
+                    | notify::DebouncedEvent::Remove(_)
+                    | notify::DebouncedEvent::Rename(_, _) => {
+                        if requests.send(()).is_err() {
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// The newest module compiled since the last call, if any.
+    pub fn try_recv(&mut self) -> Option<Vec<u8>> {
+        let mut module = None;
+        for event in self.rx.try_iter() {
+            match event {
+                ShaderEvent::Compiled(m) => {
+                    self.errors.clear();
+                    module = Some(m);
+                }
+                ShaderEvent::Failed(diagnostics) => {
+                    self.errors = diagnostics;
+                }
+            }
+        }
+        module
+    }
+
+    /// Marks a module that compiled but could not be used.
+    pub fn reject(&mut self, message: String) {
+        log::error!("{}: {}", self.source, message);
+        self.errors = vec![ShaderDiagnostic {
+            file: self.source.to_string(),
+            line: None,
+            message,
+        }];
+    }
+
+    pub fn retry(&self) {
+        self.requests.send(()).ok();
+    }
+
+    pub fn source(&self) -> &ShaderSource {
+        &self.source
+    }
+
+    pub fn errors(&self) -> &[ShaderDiagnostic] {
+        &self.errors
+    }
+}
+
+#[cfg(feature = "hot-reload")]
 pub fn spirv_builder(path_to_crate: &str) -> spirv_builder::SpirvBuilder {
     spirv_builder::SpirvBuilder::new(path_to_crate, "spirv-unknown-vulkan1.2")
         .capability(spirv_builder::Capability::RayTracingKHR)
@@ -73,3 +409,33 @@ pub fn cmd_blit_image(
         vk::Filter::LINEAR,
     );
 }
+
+#[cfg(all(test, feature = "hot-reload"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rendered_errors() {
+        let output = "   Compiling po v0.1.0
+error[E0425]: cannot find value `x` in this scope
+  --> src/lib.rs:12:5
+   |
+12 |     x
+   |     ^ not found in this scope
+
+error: unsupported type
+error: aborting due to 2 previous errors
+error: could not compile `po`
+";
+        let diagnostics = parse_diagnostics("shaders/po", output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].file, "shaders/po/src/lib.rs");
+        assert_eq!(diagnostics[0].line, Some(12));
+        assert_eq!(
+            diagnostics[0].message,
+            "cannot find value `x` in this scope"
+        );
+        assert_eq!(diagnostics[1].file, "shaders/po");
+        assert_eq!(diagnostics[1].line, None);
+    }
+}
@@ -0,0 +1,59 @@
+//! The po renderer as a library.
+//!
+//! - [`Po`] renders a [`maligog_gltf::Scene`] offline on a ray tracing
+//!   capable GPU, [`cpu::render`] does the same on host threads.
+//! - [`RayTracing`] and [`Wireframe`] are the interactive [`ScenePass`]es the
+//!   viewer draws every frame.
+//! - [`Engine`] is the windowed viewer built from the above, [`cli`] the
+//!   `po-renderer` binary around it.
+//!
+//! Rendering a depth image without a GPU:
+//!
+//! ```no_run
+//! use po_renderer::{cpu, Camera, RenderSettings};
+//!
+//! let scene = cpu::CpuScene::from_file("scene.gltf".as_ref()).unwrap();
+//! let settings = RenderSettings {
+//!     camera: Camera::new(
+//!         po_renderer::vec3(0.0, 1.0, 5.0),
+//!         po_renderer::vec3(0.0, 0.0, 0.0),
+//!         800.0 / 600.0,
+//!         std::f32::consts::FRAC_PI_3,
+//!     ),
+//!     ..RenderSettings::default()
+//! };
+//! for result in cpu::render(&settings, &scene, None, &settings.camera) {
+//!     result
+//!         .write_exr(format!("{}.exr", result.name).as_ref())
+//!         .unwrap();
+//! }
+//! ```
+pub mod cli;
+mod engine;
+pub mod profiler;
+
+pub use glam::{self, vec3, Vec3};
+pub use maligog;
+pub use maligog_gltf;
+
+pub use engine::adapter;
+pub use engine::bookmark;
+pub use engine::bounds;
+pub use engine::calibration;
+pub use engine::camera_path;
+pub use engine::compare;
+pub use engine::config;
+pub use engine::integrator;
+pub use engine::pipeline_cache;
+pub use engine::po::cpu;
+pub use engine::po::{Po, RenderResult, RenderSettings};
+pub use engine::render_target::{Offscreen, Pass, RenderTarget};
+pub use engine::scene_camera;
+pub use engine::scene_pass::{RayTracing, ScenePass, Wireframe};
+pub use engine::session;
+pub use engine::shader_cache;
+pub use engine::util::{ShaderDiagnostic, ShaderSource, ShaderWatcher};
+pub use engine::{
+    load_scene, load_skymap, Camera, CameraMode, Direction, Engine, FisheyeMapping, Lens,
+    LoadError, Orbit, Projection,
+};
@@ -1,3 +1,5 @@
+//! Puffin's profiler UI in a window of its own, next to the viewer.
+
 use egui_maligog::{egui, ScreenDescriptor};
 use egui_winit_platform::PlatformDescriptor;
 
@@ -14,20 +16,20 @@ pub struct Profiler {
 }
 
 impl Profiler {
-    pub fn new(window: &winit::window::Window) -> Self {
+    /// Runs on the same adapter as the viewer, so fails like
+    /// [`crate::Engine::new`] when there is none.
+    pub fn new(
+        window: &winit::window::Window,
+        config: &crate::config::Config,
+    ) -> Result<Self, String> {
         let scale_factor = window.scale_factor();
         let width = window.inner_size().width;
         let height = window.inner_size().height;
 
-        let entry = maligog::Entry::new().unwrap();
+        let entry = maligog::Entry::new().map_err(|e| format!("cannot load Vulkan: {:?}", e))?;
         let required_extensions = maligog::Surface::required_extensions();
         let instance = entry.create_instance(&[], &required_extensions);
-        let device = instance
-            .enumerate_physical_device()
-            .into_iter()
-            .find(|p| p.device_type() == maligog::PhysicalDeviceType::DISCRETE_GPU)
-            .unwrap()
-            .create_device();
+        let device = crate::adapter::select_for_viewer(&instance, config)?.create_device();
         let surface = instance.create_surface(window);
         let swapchain = device.create_swapchain(surface, maligog::PresentModeKHR::FIFO);
         let ui_pass = egui_maligog::UiPass::new(&device);
@@ -41,7 +43,7 @@ impl Profiler {
             style,
         });
         let start_time = std::time::Instant::now();
-        Self {
+        Ok(Self {
             device,
             ui_pass,
             start_time,
@@ -51,7 +53,7 @@ impl Profiler {
             width,
             height,
             swapchain,
-        }
+        })
     }
 
     pub fn update(&mut self, event: &winit::event::Event<()>) {
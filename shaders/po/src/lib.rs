@@ -13,7 +13,7 @@ use spirv_std::image::{Image2d, Image2dArray, Image2dI, Image2dU};
 use spirv_std::num_traits::float::Float;
 use spirv_std::RuntimeArray;
 
-use spirv_std::glam;
+pub use spirv_std::glam;
 use spirv_std::glam::{
     vec2, vec3, vec4, Mat4, UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles,
 };
@@ -23,22 +23,95 @@ use spirv_std::{image, Sampler};
 #[cfg(not(target_arch = "spirv"))]
 use spirv_std::macros::spirv;
 
+#[repr(C)]
+pub struct IntegratorOptions {
+    max_depth: u32,
+    sky_intensity: f32,
+    tmin: f32,
+    tmax: f32,
+}
+
+#[repr(C)]
+pub struct ProjectionInfo {
+    pub kind: u32,
+    pub fov: f32,
+    pub aspect_ratio: f32,
+    pub padding: u32,
+    /// fx, fy, cx, cy over the image size
+    pub intrinsics: Vec4,
+    pub distortion: [Vec4; 2],
+}
+
+pub const PERSPECTIVE: u32 = 0;
+pub const ORTHOGRAPHIC: u32 = 1;
+pub const EQUIRECTANGULAR: u32 = 2;
+pub const FISHEYE_EQUIDISTANT: u32 = 3;
+pub const FISHEYE_EQUISOLID: u32 = 4;
+pub const CALIBRATED_BROWN_CONRADY: u32 = 5;
+pub const CALIBRATED_FISHEYE: u32 = 6;
+
+/// Fixed point iterations undistorting a calibrated sensor's rays, what
+/// `cv::undistortPoints` does by default.
+pub const UNDISTORT_ITERATIONS: u32 = 20;
+
+/// What the closest hit and miss shaders hand back to the raygen.
+pub struct Payload {
+    /// Radiance arriving along the ray.
+    pub color: Vec3,
+    /// Distance to the closest hit, zero when the ray escapes.
+    pub t: f32,
+    /// Hits left before the path is cut off.
+    pub depth: u32,
+    pub rng_state: u32,
+}
+
+/// Thin lens in front of the camera, a zero aperture is a pinhole.
+#[repr(C)]
+pub struct LensInfo {
+    pub aperture_radius: f32,
+    pub focus_distance: f32,
+    pub blades: u32,
+    pub blade_rotation: f32,
+    pub anamorphic_ratio: f32,
+    /// Rays per pixel through an open aperture.
+    pub samples: u32,
+    /// Varies the rng between renders.
+    pub seed: u32,
+    pub padding: u32,
+}
+
+/// Only the matrices, so the push constants stay within the 128 bytes every
+/// device supports.
+#[repr(C)]
 pub struct CameraInfo {
     view_inv: Mat4,
     projection_inv: Mat4,
 }
 
+/// The rest of the camera and the integrator settings, read from a uniform
+/// buffer at set 1, binding 4.
+#[repr(C)]
+pub struct RenderInfo {
+    options: IntegratorOptions,
+    lens: LensInfo,
+    projection: ProjectionInfo,
+}
+
+#[cfg(feature = "entry-points")]
 #[derive(Default)]
 struct Rf32(f32);
 
+#[cfg(feature = "entry-points")]
 unsafe impl spirv_std::vector::Vector<f32, 1> for Rf32 {}
 
+#[cfg(feature = "entry-points")]
 #[spirv(ray_generation)]
 pub fn main(
     #[spirv(push_constant)] camera_info: &CameraInfo,
+    #[spirv(uniform, descriptor_set = 1, binding = 4)] render_info: &RenderInfo,
     #[spirv(launch_id)] pixel: UVec3,
     #[spirv(launch_size)] launch_size: UVec3,
-    #[spirv(ray_payload)] payload: &mut Vec3,
+    #[spirv(ray_payload)] payload: &mut Payload,
     #[spirv(descriptor_set = 0, binding = 0)] tlas: &spirv_std::ray_tracing::AccelerationStructure,
     // #[spirv(descriptor_set = 0, binding = 1)] img: &Image!(2D, type=f32, sampled=false),
     #[spirv(descriptor_set = 1, binding = 0)] color_image: &mut image::Image<
@@ -74,37 +147,255 @@ pub fn main(
     // #[spirv(uniform, descriptor_set = 0, binding = 2)] camera_pos: &mut Vec2,
 ) {
     unsafe {
-        let tmin = 0.001;
-        let tmax = 10000.0;
-        let origin = camera_info.view_inv * Vec3::splat(0.0).extend(1.0);
-
-        let pixel_center = Vec2::new(pixel.x as f32, pixel.y as f32) + Vec2::splat(0.5);
-
-        // map to (0, 1)
-        let uv = pixel_center / Vec2::new(launch_size.x as f32, launch_size.y as f32);
-
-        // map to (-1, 1) square
-        let d = uv * 2.0 - Vec2::splat(1.0);
-
-        let target = camera_info.projection_inv * d.extend(1.0).extend(1.0);
-        let target_norm = (target.xyz() / target.w).normalize();
-        let direction = (camera_info.view_inv * target_norm.extend(0.0)).normalize();
+        let tmin = render_info.options.tmin;
+        let tmax = render_info.options.tmax;
+        let (origin, direction) = primary_ray(
+            camera_info.view_inv,
+            camera_info.projection_inv,
+            &render_info.projection,
+            UVec2::new(pixel.x, pixel.y),
+            UVec2::new(launch_size.x, launch_size.y),
+        );
+        let xy = UVec2::new(pixel.x, launch_size.y - 1 - pixel.y);
+        if direction == Vec3::splat(0.0) {
+            depth_image.write(xy, Rf32(0.0));
+            color_image.write(xy, vec4(0.0, 0.0, 0.0, 1.0));
+            return;
+        }
+        let lens = &render_info.lens;
+        payload.depth = render_info.options.max_depth;
+        payload.rng_state = pixel_seed(
+            UVec2::new(pixel.x, pixel.y),
+            UVec2::new(launch_size.x, launch_size.y),
+            lens.seed,
+        );
         tlas.trace_ray(
             spirv_std::ray_tracing::RayFlags::OPAQUE,
             0xFF,
             0,
             0,
             0,
-            origin.xyz(),
+            origin,
             tmin,
-            direction.xyz(),
+            direction,
             tmax,
             payload,
         );
+        // depth is always through the lens centre
+        depth_image.write(xy, Rf32(payload.t));
 
-        let xy = UVec2::new(pixel.x, launch_size.y - pixel.y);
+        let mut color = payload.color;
+        if has_lens(lens, &render_info.projection) {
+            color = Vec3::splat(0.0);
+            let mut i = 0;
+            while i < lens.samples {
+                let (origin, direction) = lens_ray(
+                    camera_info.view_inv,
+                    camera_info.projection_inv,
+                    &render_info.projection,
+                    lens,
+                    UVec2::new(pixel.x, pixel.y),
+                    UVec2::new(launch_size.x, launch_size.y),
+                    &mut payload.rng_state,
+                );
+                payload.depth = render_info.options.max_depth;
+                tlas.trace_ray(
+                    spirv_std::ray_tracing::RayFlags::OPAQUE,
+                    0xFF,
+                    0,
+                    0,
+                    0,
+                    origin,
+                    tmin,
+                    direction,
+                    tmax,
+                    payload,
+                );
+                color += payload.color;
+                i += 1;
+            }
+            color /= lens.samples as f32;
+        }
+        color_image.write(xy, color.extend(1.0));
+    }
+}
 
-        depth_image.write(xy, Rf32(payload.x));
+/// Origin and direction of the ray through the center of `pixel`. Also used
+/// by the CPU reference renderer, so both backends see the same rays. The
+/// direction is zero outside the image circle of a fisheye.
+pub fn primary_ray(
+    view_inv: Mat4,
+    projection_inv: Mat4,
+    projection: &ProjectionInfo,
+    pixel: UVec2,
+    launch_size: UVec2,
+) -> (Vec3, Vec3) {
+    let (origin, direction) = view_ray(projection_inv, projection, pixel_ndc(pixel, launch_size));
+    if direction == Vec3::splat(0.0) {
+        return (origin, direction);
+    }
+    let origin = view_inv * origin.extend(1.0);
+    let direction = (view_inv * direction.extend(0.0)).normalize();
+    (origin.xyz(), direction.xyz())
+}
+
+fn pixel_ndc(pixel: UVec2, launch_size: UVec2) -> Vec2 {
+    let pixel_center = Vec2::new(pixel.x as f32, pixel.y as f32) + Vec2::splat(0.5);
+
+    // map to (0, 1)
+    let uv = pixel_center / Vec2::new(launch_size.x as f32, launch_size.y as f32);
+
+    // map to (-1, 1) square
+    uv * 2.0 - Vec2::splat(1.0)
+}
+
+/// Whether rays leave from across the aperture. Panoramas have no focal
+/// plane, so they stay pinholes.
+pub fn has_lens(lens: &LensInfo, projection: &ProjectionInfo) -> bool {
+    lens.aperture_radius > 0.0 && lens.samples > 0 && projection.kind <= ORTHOGRAPHIC
+}
+
+/// A ray through `pixel` from a random point on the aperture, meeting the
+/// [`primary_ray`] on the focal plane. Only for a camera that [`has_lens`].
+pub fn lens_ray(
+    view_inv: Mat4,
+    projection_inv: Mat4,
+    projection: &ProjectionInfo,
+    lens: &LensInfo,
+    pixel: UVec2,
+    launch_size: UVec2,
+    rng_state: &mut u32,
+) -> (Vec3, Vec3) {
+    let (origin, direction) = view_ray(projection_inv, projection, pixel_ndc(pixel, launch_size));
+    let focus = origin + direction * (lens.focus_distance / direction.z);
+    let lens_origin = origin + sample_aperture(lens, rng_state).extend(0.0);
+    let direction = (focus - lens_origin).normalize();
+    let origin = view_inv * lens_origin.extend(1.0);
+    let direction = (view_inv * direction.extend(0.0)).normalize();
+    (origin.xyz(), direction.xyz())
+}
+
+/// A point on the aperture in view space: a disk, or a polygon with a
+/// corner per blade, stretched by the anamorphic ratio.
+pub fn sample_aperture(lens: &LensInfo, rng_state: &mut u32) -> Vec2 {
+    let u = rng(rng_state);
+    let v = rng(rng_state);
+    let point = if lens.blades < 3 {
+        let angle = u * 2.0 * core::f32::consts::PI;
+        vec2(angle.cos(), angle.sin()) * v.sqrt()
+    } else {
+        // uniform in the triangle between the centre and two corners
+        let blades = lens.blades as f32;
+        let sector = (u * blades).floor().min(blades - 1.0);
+        let step = 2.0 * core::f32::consts::PI / blades;
+        let a0 = lens.blade_rotation + sector * step;
+        let a1 = a0 + step;
+        let w = rng(rng_state);
+        (vec2(a0.cos(), a0.sin()) * (1.0 - w) + vec2(a1.cos(), a1.sin()) * w) * v.sqrt()
+    };
+    point * vec2(lens.anamorphic_ratio, 1.0) * lens.aperture_radius
+}
+
+/// Inverts OpenCV's rational distortion model, `k` holds k1, k2, p1, p2 and
+/// `k_high` k3, k4, k5, k6. Returns the unit direction with y down like
+/// `distorted`.
+pub fn undistort_brown_conrady(distorted: Vec2, k: Vec4, k_high: Vec4) -> Vec3 {
+    let mut x = distorted.x;
+    let mut y = distorted.y;
+    let mut i = 0;
+    while i < UNDISTORT_ITERATIONS {
+        let r2 = x * x + y * y;
+        let inverse_radial = (1.0 + r2 * (k_high.y + r2 * (k_high.z + r2 * k_high.w)))
+            / (1.0 + r2 * (k.x + r2 * (k.y + r2 * k_high.x)));
+        let dx = 2.0 * k.z * x * y + k.w * (r2 + 2.0 * x * x);
+        let dy = k.z * (r2 + 2.0 * y * y) + 2.0 * k.w * x * y;
+        x = (distorted.x - dx) * inverse_radial;
+        y = (distorted.y - dy) * inverse_radial;
+        i += 1;
+    }
+    vec3(x, y, 1.0).normalize()
+}
+
+/// Inverts the `cv::fisheye` model with coefficients k1 to k4.
+pub fn undistort_fisheye(distorted: Vec2, k: Vec4) -> Vec3 {
+    let theta_d = distorted.length();
+    if theta_d == 0.0 {
+        return vec3(0.0, 0.0, 1.0);
+    }
+    let mut theta = theta_d;
+    let mut i = 0;
+    while i < UNDISTORT_ITERATIONS {
+        let t2 = theta * theta;
+        theta = theta_d / (1.0 + t2 * (k.x + t2 * (k.y + t2 * (k.z + t2 * k.w))));
+        i += 1;
+    }
+    (distorted / theta_d * theta.sin()).extend(theta.cos())
+}
+
+/// View space origin and direction of the ray through `d`, in (-1, 1) y up.
+/// The direction is zero outside the image circle of a fisheye. The one
+/// copy of the projections, the ray-tracing shader and `Camera::ray` on the
+/// host call it too.
+pub fn view_ray(projection_inv: Mat4, projection: &ProjectionInfo, d: Vec2) -> (Vec3, Vec3) {
+    let pi = core::f32::consts::PI;
+    if projection.kind == ORTHOGRAPHIC {
+        let near = projection_inv * d.extend(0.0).extend(1.0);
+        (
+            vec3(near.x / near.w, near.y / near.w, 0.0),
+            vec3(0.0, 0.0, 1.0),
+        )
+    } else if projection.kind == EQUIRECTANGULAR {
+        let longitude = d.x * pi;
+        let latitude = d.y * pi * 0.5;
+        (
+            Vec3::splat(0.0),
+            vec3(
+                latitude.cos() * longitude.sin(),
+                latitude.sin(),
+                latitude.cos() * longitude.cos(),
+            ),
+        )
+    } else if projection.kind == FISHEYE_EQUIDISTANT || projection.kind == FISHEYE_EQUISOLID {
+        let point = vec2(d.x * projection.aspect_ratio, d.y);
+        let radius = point.length();
+        if radius > 1.0 {
+            return (Vec3::splat(0.0), Vec3::splat(0.0));
+        }
+        let theta = if projection.kind == FISHEYE_EQUIDISTANT {
+            radius * projection.fov * 0.5
+        } else {
+            2.0 * (radius * (projection.fov * 0.25).sin()).asin()
+        };
+        let across = if radius > 0.0 {
+            point / radius * theta.sin()
+        } else {
+            Vec2::splat(0.0)
+        };
+        (Vec3::splat(0.0), across.extend(theta.cos()))
+    } else if projection.kind == CALIBRATED_BROWN_CONRADY || projection.kind == CALIBRATED_FISHEYE {
+        // OpenCV's normalized image coordinates, y down
+        let uv = vec2(d.x + 1.0, 1.0 - d.y) * 0.5;
+        let intrinsics = projection.intrinsics;
+        let distorted = vec2(
+            (uv.x - intrinsics.z) / intrinsics.x,
+            (uv.y - intrinsics.w) / intrinsics.y,
+        );
+        let direction = if projection.kind == CALIBRATED_BROWN_CONRADY {
+            undistort_brown_conrady(
+                distorted,
+                projection.distortion[0],
+                projection.distortion[1],
+            )
+        } else {
+            undistort_fisheye(distorted, projection.distortion[0])
+        };
+        (
+            Vec3::splat(0.0),
+            vec3(direction.x, -direction.y, direction.z),
+        )
+    } else {
+        let target = projection_inv * d.extend(1.0).extend(1.0);
+        (Vec3::splat(0.0), (target.xyz() / target.w).normalize())
     }
 }
 
@@ -138,9 +429,10 @@ pub struct MaterialInfo {
     padding: u64,
 }
 
+#[cfg(feature = "entry-points")]
 #[spirv(closest_hit)]
 pub fn closest_hit(
-    #[spirv(incoming_ray_payload)] payload: &mut Vec3,
+    #[spirv(incoming_ray_payload)] payload: &mut Payload,
     #[spirv(hit_attribute)] hit_attr: &mut Vec2,
     #[spirv(instance_id)] instance_id: usize, // index of instance in tlas
     #[spirv(ray_geometry_index)] geometry_index: usize, // index of geometry in instance
@@ -153,6 +445,7 @@ pub fn closest_hit(
     #[spirv(object_ray_direction)] object_ray_direction: Vec3,
     #[spirv(shader_record_buffer)] shader_record_buffer: &mut ShaderRecordData,
     // #[spirv(world_to_object)] world_to_object: glam::Affine3A,
+    #[spirv(descriptor_set = 0, binding = 0)] tlas: &spirv_std::ray_tracing::AccelerationStructure,
     #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] index_buffer: &mut [u32],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] vertex_buffer: &mut [f32],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] geometry_infos: &mut [GeometryInfo], // per-BLAS
@@ -163,7 +456,7 @@ pub fn closest_hit(
     #[spirv(storage_buffer, descriptor_set = 0, binding = 8)] material_infos: &mut [MaterialInfo],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 9)] color_buffer: &mut [Vec4],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 10)] tex_coord_buffer: &mut [Vec2],
-    #[spirv(push_constant)] camera_info: &CameraInfo,
+    #[spirv(uniform, descriptor_set = 1, binding = 4)] render_info: &RenderInfo,
 ) {
     let barycentrics = vec3(1.0 - hit_attr.x - hit_attr.y, hit_attr.x, hit_attr.y);
 
@@ -198,46 +491,68 @@ pub fn closest_hit(
     let object_to_world = transform_buffer[instance_id];
 
     let object_position = v0 * barycentrics.x + v1 * barycentrics.y + v2 * barycentrics.z;
-    // let object_normal = (v1 - v0).cross(v2 - v0).normalize();
     let world_position = object_to_world.transform_point3(object_position);
-    let world_v0 = object_to_world.transform_point3(v0);
-    let world_v1 = object_to_world.transform_point3(v1);
-    let world_v2 = object_to_world.transform_point3(v2);
-    let mut world_normal = (world_v1 - world_v0).cross(world_v2 - world_v0).normalize();
-    world_normal = utils::facefoward(&world_normal, &world_ray_direction);
+    let world_normal = shading_normal(
+        object_to_world.transform_point3(v0),
+        object_to_world.transform_point3(v1),
+        object_to_world.transform_point3(v2),
+        world_ray_direction,
+    );
 
-    if geometry_info.has_tex_coord == 1 {
+    let mut vertex_color = Vec4::splat(1.0);
+    if geometry_info.has_color == 1 {
+        let v0_color = color_buffer[color_offset + v0_index];
+        let v1_color = color_buffer[color_offset + v1_index];
+        let v2_color = color_buffer[color_offset + v2_index];
+        vertex_color =
+            v0_color * barycentrics.x + v1_color * barycentrics.y + v2_color * barycentrics.z;
+    }
+    let mut texel = Vec4::splat(1.0);
+    if geometry_info.has_tex_coord == 1 && material_info.has_base_color_texture == 1 {
         let v0_tex_coord = tex_coord_buffer[tex_coord_offset + v0_index];
         let v1_tex_coord = tex_coord_buffer[tex_coord_offset + v1_index];
         let v2_tex_coord = tex_coord_buffer[tex_coord_offset + v2_index];
         let tex_coord = v0_tex_coord * barycentrics.x
             + v1_tex_coord * barycentrics.y
             + v2_tex_coord * barycentrics.z;
-        if material_info.has_base_color_texture == 1 {
-            let sampler =
-                unsafe { samplers.index(material_info.base_color_sampler_index as usize) };
-            let image = unsafe { images.index(material_info.base_color_image_index as usize) };
-            let texel: Vec4 = image.sample_by_lod(*sampler, tex_coord, 0.0);
-            let color = texel * material_info.base_color_factor;
-            *payload = color.xyz();
-        } else if geometry_info.has_color == 1 {
-            let v0_color = color_buffer[color_offset + v0_index];
-            let v1_color = color_buffer[color_offset + v1_index];
-            let v2_color = color_buffer[color_offset + v2_index];
-            let color =
-                v0_color * barycentrics.x + v1_color * barycentrics.y + v2_color * barycentrics.z;
-            *payload = (color * material_info.base_color_factor).xyz();
-        } else {
-            *payload = material_info.base_color_factor.xyz();
+        let sampler = unsafe { samplers.index(material_info.base_color_sampler_index as usize) };
+        let image = unsafe { images.index(material_info.base_color_image_index as usize) };
+        texel = image.sample_by_lod(*sampler, tex_coord, 0.0);
+    }
+    let albedo = base_color(material_info.base_color_factor, vertex_color, texel);
+
+    if payload.depth <= 1 {
+        payload.color = Vec3::splat(0.0);
+    } else {
+        payload.depth -= 1;
+        let direction = scatter_direction(world_normal, &mut payload.rng_state);
+        unsafe {
+            tlas.trace_ray(
+                spirv_std::ray_tracing::RayFlags::OPAQUE,
+                0xFF,
+                0,
+                0,
+                0,
+                world_position,
+                render_info.options.tmin,
+                direction,
+                render_info.options.tmax,
+                payload,
+            );
         }
+        payload.color = shade(albedo, payload.color);
     }
+    // after the bounce, which overwrote it
+    payload.t = ray_tmax;
 }
 
+#[cfg(feature = "entry-points")]
 #[spirv(miss)]
 pub fn miss(
-    #[spirv(incoming_ray_payload)] payload: &mut Vec3,
+    #[spirv(incoming_ray_payload)] payload: &mut Payload,
     #[spirv(world_ray_direction)] world_ray_direction: Vec3,
     #[spirv(descriptor_set = 1, binding = 2)] sampler: &Sampler,
+    #[spirv(uniform, descriptor_set = 1, binding = 4)] render_info: &RenderInfo,
     #[spirv(descriptor_set = 2, binding = 0)] sky_texture: &image::Image<
         f32,
         { image::Dimensionality::TwoD },
@@ -249,10 +564,72 @@ pub fn miss(
         { None },
     >,
 ) {
-    // *payload = vec3(1.0, 0.5, 0.23);
     let coord = sample_sphereical_map(&world_ray_direction);
-    let color: Vec4 = sky_texture.sample_by_lod(*sampler, coord, 0.0);
-    *payload = color.xyz();
+    let texel: Vec4 = sky_texture.sample_by_lod(*sampler, coord, 0.0);
+    payload.color = sky_radiance(texel, render_info.options.sky_intensity);
+    payload.t = 0.0;
+}
+
+/// Seeds the rng of the paths through `pixel`, a different stream for every
+/// pixel and `seed`.
+pub fn pixel_seed(pixel: UVec2, launch_size: UVec2, seed: u32) -> u32 {
+    (pixel.y * launch_size.x + pixel.x)
+        .wrapping_add(seed.wrapping_mul(launch_size.x * launch_size.y))
+}
+
+/// [0, 1] float rng, the same stream on the GPU and on the host.
+pub fn rng(state: &mut u32) -> f32 {
+    // Condensed version of pcg_output_rxs_m_xs_32_32, with simple conversion to floating-point [0,1].
+    *state = state.wrapping_mul(747796405).wrapping_add(1);
+    let state = *state;
+    let mut word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    word = (word >> 22) ^ word;
+    word as f32 / 4294967295.0
+}
+
+fn rand_vec3_in_unit_sphere(state: &mut u32) -> Vec3 {
+    loop {
+        let p = vec3(rng(state), rng(state), rng(state)) * 2.0 - Vec3::splat(1.0);
+        if p.length_squared() < 1.0 {
+            return p;
+        }
+    }
+}
+
+/// Geometric normal of the triangle, facing against `direction`.
+pub fn shading_normal(v0: Vec3, v1: Vec3, v2: Vec3, direction: Vec3) -> Vec3 {
+    let normal = (v1 - v0).cross(v2 - v0).normalize();
+    utils::facefoward(&normal, &direction)
+}
+
+/// glTF's base colour: the material factor times the vertex colour and the
+/// base colour texel, both one where the primitive has none.
+pub fn base_color(factor: Vec4, vertex_color: Vec4, texel: Vec4) -> Vec3 {
+    (factor * vertex_color * texel).xyz()
+}
+
+/// Where a path continues after a diffuse hit, cosine distributed around
+/// `normal`.
+pub fn scatter_direction(normal: Vec3, rng_state: &mut u32) -> Vec3 {
+    let direction = normal + rand_vec3_in_unit_sphere(rng_state).normalize();
+    if direction.abs().cmple(Vec3::splat(1e-8)).all() {
+        normal
+    } else {
+        direction.normalize()
+    }
+}
+
+/// Radiance leaving a diffuse surface towards the previous hit, with the
+/// radiance arriving along its [`scatter_direction`]. The cosine cancels
+/// with the sampling density.
+pub fn shade(base_color: Vec3, incoming: Vec3) -> Vec3 {
+    base_color * incoming
+}
+
+/// Radiance of an escaped ray, `texel` sampled from the skymap at
+/// [`sample_sphereical_map`].
+pub fn sky_radiance(texel: Vec4, sky_intensity: f32) -> Vec3 {
+    texel.xyz() * sky_intensity
 }
 
 pub fn sample_sphereical_map(direction: &Vec3) -> Vec2 {
@@ -262,33 +639,3 @@ pub fn sample_sphereical_map(direction: &Vec3) -> Vec2 {
     uv += Vec2::splat(0.5);
     return uv;
 }
-
-#[spirv(closest_hit)]
-pub fn depth_closest_hit(
-    #[spirv(incoming_ray_payload)] payload: &mut Vec3,
-    #[spirv(hit_attribute)] hit_attr: &mut Vec2,
-    #[spirv(instance_id)] instance_id: usize, // index of instance in tlas
-    #[spirv(ray_geometry_index)] geometry_index: usize, // index of geometry in instance
-    #[spirv(primitive_id)] primitive_id: usize, // index of triangle in geometry
-    #[spirv(instance_custom_index)] instance_custom_index: usize, // blas id
-    #[spirv(ray_tmax)] ray_tmax: f32,
-    #[spirv(world_ray_origin)] world_ray_origin: Vec3,
-    #[spirv(world_ray_direction)] world_ray_direction: Vec3,
-    #[spirv(object_ray_origin)] object_ray_origin: Vec3,
-    #[spirv(object_ray_direction)] object_ray_direction: Vec3,
-    #[spirv(shader_record_buffer)] shader_record_buffer: &mut ShaderRecordData,
-    // #[spirv(world_to_object)] world_to_object: glam::Affine3A,
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] index_buffer: &mut [u32],
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] vertex_buffer: &mut [f32],
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] geometry_infos: &mut [GeometryInfo], // per-BLAS
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 4)] geometry_info_offsets: &mut [u32], // per-BLAS
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 5)] transform_buffer: &mut [Mat4], // per-instance
-    #[spirv(descriptor_set = 0, binding = 6)] samplers: &RuntimeArray<Sampler>,
-    #[spirv(descriptor_set = 0, binding = 7)] images: &RuntimeArray<Image2d>,
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 8)] material_infos: &mut [MaterialInfo],
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 9)] color_buffer: &mut [Vec4],
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 10)] tex_coord_buffer: &mut [Vec2],
-    #[spirv(push_constant)] camera_info: &CameraInfo,
-) {
-    *payload = Vec3::splat(ray_tmax);
-}
@@ -20,14 +20,35 @@ use spirv_std::glam::{
 use spirv_std::Image;
 use spirv_std::{image, Sampler};
 
+use po_shader::{has_lens, lens_ray, pixel_seed, primary_ray, LensInfo, ProjectionInfo};
+
 #[cfg(not(target_arch = "spirv"))]
 use spirv_std::macros::spirv;
 
+#[repr(C)]
+pub struct IntegratorOptions {
+    max_depth: u32,
+    sky_intensity: f32,
+    tmin: f32,
+    tmax: f32,
+}
+
+/// Only the matrices, so the push constants stay within the 128 bytes every
+/// device supports.
+#[repr(C)]
 pub struct CameraInfo {
     view_inv: Mat4,
     projection_inv: Mat4,
 }
 
+/// Read from the per frame uniform buffer at set 1, binding 4.
+#[repr(C)]
+pub struct RenderInfo {
+    options: IntegratorOptions,
+    lens: LensInfo,
+    projection: ProjectionInfo,
+}
+
 pub struct Payload {
     color: Vec3,
     depth: u32,
@@ -37,6 +58,7 @@ pub struct Payload {
 #[spirv(ray_generation)]
 pub fn main(
     #[spirv(push_constant)] camera_info: &CameraInfo,
+    #[spirv(uniform, descriptor_set = 1, binding = 4)] render_info: &RenderInfo,
     #[spirv(launch_id)] pixel: UVec3,
     #[spirv(launch_size)] launch_size: UVec3,
     #[spirv(ray_payload)] payload: &mut Payload,
@@ -65,40 +87,66 @@ pub fn main(
     // #[spirv(uniform, descriptor_set = 0, binding = 2)] camera_pos: &mut Vec2,
 ) {
     unsafe {
-        let tmin = 0.001;
-        let tmax = 10000.0;
-        let origin = camera_info.view_inv * Vec3::splat(0.0).extend(1.0);
-
-        let pixel_center = Vec2::new(pixel.x as f32, pixel.y as f32) + Vec2::splat(0.5);
-
-        // map to (0, 1)
-        let uv = pixel_center / Vec2::new(launch_size.x as f32, launch_size.y as f32);
-
-        // map to (-1, 1) square
-        let d = uv * 2.0 - Vec2::splat(1.0);
-
-        let target = camera_info.projection_inv * d.extend(1.0).extend(1.0);
-        let target_norm = (target.xyz() / target.w).normalize();
-        let direction = (camera_info.view_inv * target_norm.extend(0.0)).normalize();
-
-        payload.depth = 10;
-        payload.rng_state = 10086;
-        tlas.trace_ray(
-            spirv_std::ray_tracing::RayFlags::OPAQUE,
-            0xFF,
-            0,
-            0,
-            0,
-            origin.xyz(),
-            tmin,
-            direction.xyz(),
-            tmax,
-            payload,
-        );
+        let tmin = render_info.options.tmin;
+        let tmax = render_info.options.tmax;
+        let lens = &render_info.lens;
+        let pixel = UVec2::new(pixel.x, pixel.y);
+        let launch_size = UVec2::new(launch_size.x, launch_size.y);
 
         let xy = UVec2::new(pixel.x, launch_size.y - pixel.y);
+        let (origin, direction) = primary_ray(
+            camera_info.view_inv,
+            camera_info.projection_inv,
+            &render_info.projection,
+            pixel,
+            launch_size,
+        );
+        if direction == Vec3::splat(0.0) {
+            color_image.write(xy, vec4(0.0, 0.0, 0.0, 1.0));
+            return;
+        }
+
+        // a pinhole needs one ray, a lens averages rays from across the
+        // aperture meeting on the focal plane
+        let through_lens = has_lens(lens, &render_info.projection);
+        let samples = if through_lens { lens.samples } else { 1 };
+        // a new seed every frame, so the noise is not frozen in place
+        payload.rng_state = pixel_seed(pixel, launch_size, lens.seed);
+        let mut color = Vec3::splat(0.0);
+        let mut i = 0;
+        while i < samples {
+            let (origin, direction) = if through_lens {
+                lens_ray(
+                    camera_info.view_inv,
+                    camera_info.projection_inv,
+                    &render_info.projection,
+                    lens,
+                    pixel,
+                    launch_size,
+                    &mut payload.rng_state,
+                )
+            } else {
+                (origin, direction)
+            };
 
-        color_image.write(xy, payload.color.extend(1.0));
+            payload.depth = render_info.options.max_depth;
+            tlas.trace_ray(
+                spirv_std::ray_tracing::RayFlags::OPAQUE,
+                0xFF,
+                0,
+                0,
+                0,
+                origin,
+                tmin,
+                direction,
+                tmax,
+                payload,
+            );
+            color += payload.color;
+            i += 1;
+        }
+
+        color_image.write(xy, (color / samples as f32).extend(1.0));
     }
 }
 
@@ -158,15 +206,15 @@ pub fn closest_hit(
     #[spirv(storage_buffer, descriptor_set = 0, binding = 8)] material_infos: &mut [MaterialInfo],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 9)] color_buffer: &mut [Vec4],
     #[spirv(storage_buffer, descriptor_set = 0, binding = 10)] tex_coord_buffer: &mut [Vec2],
-    #[spirv(push_constant)] camera_info: &CameraInfo,
+    #[spirv(uniform, descriptor_set = 1, binding = 4)] render_info: &RenderInfo,
 ) {
     payload.depth -= 1;
     if payload.depth == 0 {
         payload.color = Vec3::splat(0.0);
         return;
     }
-    let tmin = 0.001;
-    let tmax = 10000.0;
+    let tmin = render_info.options.tmin;
+    let tmax = render_info.options.tmax;
 
     let barycentrics = vec3(1.0 - hit_attr.x - hit_attr.y, hit_attr.x, hit_attr.y);
 
@@ -282,11 +330,62 @@ pub fn closest_hit(
     // }
 }
 
+/// Shading variant that shows the world space geometric normal.
+#[spirv(closest_hit)]
+pub fn closest_hit_normal(
+    #[spirv(incoming_ray_payload)] payload: &mut Payload,
+    #[spirv(hit_attribute)] hit_attr: &mut Vec2,
+    #[spirv(instance_id)] instance_id: usize, // index of instance in tlas
+    #[spirv(ray_geometry_index)] geometry_index: usize, // index of geometry in instance
+    #[spirv(primitive_id)] primitive_id: usize, // index of triangle in geometry
+    #[spirv(instance_custom_index)] instance_custom_index: usize, // blas id
+    #[spirv(world_ray_direction)] world_ray_direction: Vec3,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] index_buffer: &mut [u32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] vertex_buffer: &mut [f32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] geometry_infos: &mut [GeometryInfo], // per-BLAS
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 4)] geometry_info_offsets: &mut [u32], // per-BLAS
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 5)] transform_buffer: &mut [Mat4], // per-instance
+) {
+    let geometry_info =
+        &geometry_infos[geometry_info_offsets[instance_custom_index] as usize + geometry_index];
+    let index_offset = (geometry_info.index_offset / 4) as usize; // by index
+    let vertex_offset = (geometry_info.vertex_offset / 4) as usize; // by index
+
+    let v0_index = index_buffer[index_offset + primitive_id * 3] as usize;
+    let v0 = vec3(
+        vertex_buffer[vertex_offset + v0_index * 3],
+        vertex_buffer[vertex_offset + v0_index * 3 + 1],
+        vertex_buffer[vertex_offset + v0_index * 3 + 2],
+    );
+    let v1_index = index_buffer[index_offset + primitive_id * 3 + 1] as usize;
+    let v1 = vec3(
+        vertex_buffer[vertex_offset + v1_index * 3],
+        vertex_buffer[vertex_offset + v1_index * 3 + 1],
+        vertex_buffer[vertex_offset + v1_index * 3 + 2],
+    );
+    let v2_index = index_buffer[index_offset + primitive_id * 3 + 2] as usize;
+    let v2 = vec3(
+        vertex_buffer[vertex_offset + v2_index * 3],
+        vertex_buffer[vertex_offset + v2_index * 3 + 1],
+        vertex_buffer[vertex_offset + v2_index * 3 + 2],
+    );
+
+    let object_to_world = transform_buffer[instance_id];
+    let world_v0 = object_to_world.transform_point3(v0);
+    let world_v1 = object_to_world.transform_point3(v1);
+    let world_v2 = object_to_world.transform_point3(v2);
+    let mut world_normal = (world_v1 - world_v0).cross(world_v2 - world_v0).normalize();
+    world_normal = util::facefoward(&world_normal, &world_ray_direction);
+
+    payload.color = world_normal * 0.5 + Vec3::splat(0.5);
+}
+
 #[spirv(miss)]
 pub fn miss(
     #[spirv(incoming_ray_payload)] payload: &mut Payload,
     #[spirv(world_ray_direction)] world_ray_direction: Vec3,
     #[spirv(descriptor_set = 1, binding = 2)] sampler: &Sampler,
+    #[spirv(uniform, descriptor_set = 1, binding = 4)] render_info: &RenderInfo,
     #[spirv(descriptor_set = 2, binding = 0)] sky_texture: &image::Image<
         f32,
         { image::Dimensionality::TwoD },
@@ -301,7 +400,7 @@ pub fn miss(
     // *payload = vec3(1.0, 0.5, 0.23);
     let coord = sample_sphereical_map(&world_ray_direction);
     let color: Vec4 = sky_texture.sample_by_lod(*sampler, coord, 0.0);
-    payload.color = color.xyz() * 2.0;
+    payload.color = color.xyz() * render_info.options.sky_intensity;
 }
 
 pub fn sample_sphereical_map(direction: &Vec3) -> Vec2 {